@@ -0,0 +1,80 @@
+; Pulse-per-second (or arbitrary frequency) generation on P9_31 (pru0_pru_r30_0).
+;
+; Edges are scheduled on the IEP counter, which is enabled with an increment of
+; 1 and hence counts at the 200MHz IEP clock frequency. The control block at
+; the beginning of the PRU0 data RAM is laid out as follows:
+;
+;   0x00  period      output period (IEP ticks), read at each rising edge
+;   0x04  high time   pulse width (IEP ticks), read at each rising edge
+;   0x08  adjust      one-shot phase adjustment (signed IEP ticks), added to
+;                     the next rising edge time and then cleared
+;   0x0c  edge count  number of rising edges generated so far
+;   0x10  last edge   IEP count at the last rising edge
+;
+; The first rising edge occurs one period after startup. The generator runs
+; until the host triggers ARM_PRU0_SYSEVT, at which point the output is
+; cleared and completion is notified to the host with PRU0_ARM_SYSEVT.
+
+
+PRU0_ARM_SYSEVT .set 19
+ARM_PRU0_SYSEVT .set 21
+PRU0_IRQ_BIT .set 30
+SICR_OFFSET .set 0x24
+PPS_BIT .set 0
+
+; Constant registers
+CONST_INTC .set c0
+CONST_IEP .set c26
+
+; IEP registers
+IEP_GLOBAL_CFG .set 0x00
+IEP_COUNT .set 0x0c
+IEP_CFG_ENABLE_INC1 .set 0x0111 ; CNT_ENABLE, DEFAULT_INC=1, CMP_INC=1
+
+
+    .global _c_int00
+
+_c_int00:
+    ldi   r0, IEP_CFG_ENABLE_INC1
+    sbco  &r0, CONST_IEP, IEP_GLOBAL_CFG, 4 ; enable the IEP counter
+    ldi   r10, 0x000            ; r10: RAM base address
+    ldi   r3, 0                 ; r3: edge count
+    sbbo  &r3, r10, 12, 4       ; reset edge count
+    clr   r30, r30, PPS_BIT
+    lbbo  &r1, r10, 0, 8        ; r1: period, r2: high time
+    lbco  &r20, CONST_IEP, IEP_COUNT, 4
+    add   r21, r20, r1          ; r21: time of the next rising edge
+
+wait_rise:
+    qbbs  end, r31, PRU0_IRQ_BIT ; irq received from host?
+    lbco  &r20, CONST_IEP, IEP_COUNT, 4
+    sub   r22, r20, r21
+    qbbs  wait_rise, r22, 31    ; loop while the edge time is in the future
+    set   r30, r30, PPS_BIT
+    add   r3, r3, 1
+    mov   r4, r21
+    sbbo  &r3, r10, 12, 8       ; store edge count and last edge (r3, r4)
+    add   r23, r21, r2          ; r23: time of the falling edge
+
+wait_fall:
+    lbco  &r20, CONST_IEP, IEP_COUNT, 4
+    sub   r22, r20, r23
+    qbbs  wait_fall, r22, 31
+    clr   r30, r30, PPS_BIT
+    lbbo  &r1, r10, 0, 12       ; reload period, high time and adjustment (r1-r3)
+    add   r21, r21, r1
+    add   r21, r21, r3
+    qbeq  reload_count, r3, 0
+    ldi   r3, 0
+    sbbo  &r3, r10, 8, 4        ; clear the consumed adjustment
+
+reload_count:
+    lbbo  &r3, r10, 12, 4       ; restore edge count
+    qba   wait_rise
+
+end:
+    clr   r30, r30, PPS_BIT
+    ldi   r7, ARM_PRU0_SYSEVT
+    sbco  &r7, CONST_INTC, SICR_OFFSET, 2 ; clear system event triggered by host
+    ldi   r31.b0, 32 | (PRU0_ARM_SYSEVT - 16) ; notify completion to host
+    halt
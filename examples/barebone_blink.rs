@@ -5,7 +5,7 @@
 
 extern crate prusst;
 
-use prusst::{Pruss, IntcConfig, Evtout, Sysevt};
+use prusst::prelude::*;
 
 use std::fs::File;
 use std::io::Write;
@@ -54,7 +54,7 @@ fn main() {
     // Let us know when the LED is turned on.
     for i in 1..6 {
         // Wait for the PRU to trigger the event out.
-        irq.wait();
+        irq.try_wait().unwrap();
         println!("Blink {}", i);
 
         // Clear the triggering interrupt and re-enable the host irq.
@@ -63,7 +63,7 @@ fn main() {
     }
 
     // Wait for completion of the PRU code.
-    irq.wait();
+    irq.try_wait().unwrap();
     pruss.intc.clear_sysevt(Sysevt::S19);
     println!("Goodbye!");
     
@@ -12,7 +12,7 @@
 extern crate prusst;
 extern crate crossbeam;
 
-use prusst::{Pruss, Intc, IntcConfig, Evtout, Sysevt, EvtoutIrq};
+use prusst::prelude::*;
 
 use std::fs::File;
 use std::io::Write;
@@ -34,7 +34,7 @@ fn blink_monitor(irq: EvtoutIrq, sysevt: Sysevt, my_name: &str, intc: &Intc) {
     // Let us know when the LED is turned on.
     for i in 1..6 {
         // Wait for the PRU to trigger an event out.
-        irq.wait();
+        irq.try_wait().unwrap();
         println!("Blink {} from {}", i, my_name);
 
         // Clear the triggering interrupt and re-enable the host irq.
@@ -43,7 +43,7 @@ fn blink_monitor(irq: EvtoutIrq, sysevt: Sysevt, my_name: &str, intc: &Intc) {
     }
     
     // Wait for completion of the PRU code.
-    irq.wait();
+    irq.try_wait().unwrap();
     intc.clear_sysevt(sysevt);
 }
 
@@ -22,8 +22,8 @@
 
 extern crate prusst;
 
-use prusst::{Pruss, IntcConfig, Evtout, Sysevt};
-use prusst::util::VolatileCell;
+use prusst::prelude::*;
+use prusst::clock::PruClock;
 
 use std::fs::File;
 use std::f32::consts;
@@ -33,7 +33,6 @@ use std::thread;
 
 const ARRAY_BASE: usize = 0x100;
 const NB_SAMPLES: usize = 256;
-const PRU_FREQUENCY: f32 = 200e6;
 const TICKS_PER_SUBSAMPLE: u32 = 10; // nb of PRU clock ticks per sub-sample
 const MIN_SAMPLE_LENGTH: u32 = 255; // min nb of sub-samples per wave sample
 
@@ -69,12 +68,13 @@ fn main() {
     // waveform array; note that the array could have been a field of the Ctrl struct to avoid
     // separate allocation, but this way we have learned how to that and as a bonus we get a
     // nice round address for the array too...
-    let (mut bank1, mut bank2) = pruss.dram0.split_at(ARRAY_BASE);
+    let (mut bank1, mut bank2) = pruss.dram0.split_at(PruAddr(ARRAY_BASE));
     let ctrl = bank1.alloc(Ctrl { sample_count: VolatileCell::new(0), sample_length: 0 });
     let wave = unsafe { bank2.alloc_uninitialized::<[u8; NB_SAMPLES]>() };
 
     // Ask for the amplitude and frequency of the wave.
-    let max_frequency: f32 = PRU_FREQUENCY /
+    let pru_frequency: f32 = PruClock::detect().hz() as f32;
+    let max_frequency: f32 = pru_frequency /
         (TICKS_PER_SUBSAMPLE as f32 * MIN_SAMPLE_LENGTH as f32 * NB_SAMPLES as f32);
     let amplitude:  f32 = get_input("Amplitude [%]",  0.0, 100.0)/100.0;
     let frequency:  f32 = get_input("Frequency [Hz]", 0.0, max_frequency);
@@ -83,11 +83,11 @@ fn main() {
     // Compute the number of sub-sampling cycles per sample required for the requested frequency.
     let sampling_frequency: f32 = frequency * (NB_SAMPLES as f32);
     let sampling_period:    f32 = 1.0/sampling_frequency;
-    let ticks_per_sample:   f32 = sampling_period * PRU_FREQUENCY; // PRU clock ticks per sample
+    let ticks_per_sample:   f32 = sampling_period * pru_frequency; // PRU clock ticks per sample
     let sample_length:      u32 = (ticks_per_sample / (TICKS_PER_SUBSAMPLE as f32)).round() as u32;
 
     // Write the cycle length and generate the sine wave data.
-    println!("\nGenerating wave with frequency {} Hz", PRU_FREQUENCY /
+    println!("\nGenerating wave with frequency {} Hz", pru_frequency /
         (TICKS_PER_SUBSAMPLE as f32 * sample_length as f32 * NB_SAMPLES as f32) );
     ctrl.sample_length = sample_length;
     for (i, val) in wave.iter_mut().enumerate() {
@@ -100,15 +100,15 @@ fn main() {
 
     // Open and load a PRU binary.
     let mut pru_binary = File::open("examples/pwm_generator.bin").unwrap();
-    unsafe { pruss.pru0.load_code(&mut pru_binary).unwrap().run(); }
+    let mut pru0 = pruss.pru0.load_code(&mut pru_binary).unwrap();
+    unsafe { pru0.run(); }
     
-    // Request a PRU halt when the duration has elapsed.
+    // Request a PRU halt when the duration has elapsed and await acknowledgement from PRU.
     thread::sleep(Duration::new(duration.floor() as u64,
                                 (duration.fract()*1e9).floor() as u32));
-    pruss.intc.send_sysevt(Sysevt::S21);
-    
-    // Await acknowledgement from PRU.
-    irq.wait();
+    if !pru0.request_stop(&pruss.intc, Sysevt::S21, &irq, Duration::from_secs(1)) {
+        println!("the PRU did not acknowledge the stop request and was forcibly stopped");
+    }
     pruss.intc.clear_sysevt(Sysevt::S19);
 
     println!("{} PWM samples have been generated", ctrl.sample_count.get());
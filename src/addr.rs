@@ -0,0 +1,219 @@
+//! Typed memory addresses.
+
+use def::{DRAM0_OFFSET, DRAM1_OFFSET, DRAM1_SIZE};
+use pubdef::PruCore;
+
+use std::fmt;
+use std::ops::{Add, Sub};
+
+
+/// A type designating byte positions within memory segments.
+///
+/// This trait is implemented by `PruAddr`, `IramAddr`, `HostOffset` and `DmaBufOffset`, which are
+/// respectively used for segments of the PRU memory, of an instruction RAM, of the host memory
+/// and of a `DmaBuf`, so that a position within one address space cannot be inadvertently used
+/// within another.
+pub trait SegmentAddr: Copy + Ord + From<usize> + Into<usize> {}
+
+
+/// An address in the local address space of the PRU subsystem.
+///
+/// This is a byte offset relative to the base of the PRU memory map, which is also the address
+/// at which the PRU0 core sees the corresponding data RAM location.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PruAddr(pub usize);
+
+impl PruAddr {
+    /// Returns the address at which the given PRU core sees this location.
+    ///
+    /// The local address space of PRU1 differs from that of PRU0 in that the data RAMs of PRU0
+    /// and PRU1 are swapped, so that each core sees its own data RAM at address 0x00000000 and the
+    /// other core's data RAM at address 0x00002000. The shared data RAM and the other PRU
+    /// subsystem resources are seen at the same address by both cores.
+    pub fn local_to(self, core: PruCore) -> u32 {
+        let addr = match core {
+            PruCore::Pru0 => self.0,
+            PruCore::Pru1 => {
+                match self.0 {
+                    a if a < DRAM1_OFFSET => a - DRAM0_OFFSET + DRAM1_OFFSET,
+                    a if a < DRAM1_OFFSET + DRAM1_SIZE => a - DRAM1_OFFSET + DRAM0_OFFSET,
+                    a => a,
+                }
+            }
+        };
+
+        addr as u32
+    }
+
+    /// Returns the location seen by the given PRU core at the specified local address.
+    ///
+    /// This is the inverse of `local_to`.
+    pub fn from_local(core: PruCore, addr: u32) -> PruAddr {
+        PruAddr(PruAddr(addr as usize).local_to(core) as usize)
+    }
+}
+
+impl SegmentAddr for PruAddr {}
+
+impl From<usize> for PruAddr {
+    fn from(addr: usize) -> PruAddr {
+        PruAddr(addr)
+    }
+}
+
+impl From<PruAddr> for usize {
+    fn from(addr: PruAddr) -> usize {
+        addr.0
+    }
+}
+
+impl Add<usize> for PruAddr {
+    type Output = PruAddr;
+
+    fn add(self, rhs: usize) -> PruAddr {
+        PruAddr(self.0 + rhs)
+    }
+}
+
+impl Sub for PruAddr {
+    type Output = usize;
+
+    fn sub(self, rhs: PruAddr) -> usize {
+        self.0 - rhs.0
+    }
+}
+
+impl fmt::Display for PruAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
+
+
+/// A byte offset relative to the start of the instruction RAM of a PRU core.
+///
+/// This is the address at which the core fetches the corresponding instruction, i.e. four times
+/// the instruction word address.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IramAddr(pub usize);
+
+impl SegmentAddr for IramAddr {}
+
+impl From<usize> for IramAddr {
+    fn from(addr: usize) -> IramAddr {
+        IramAddr(addr)
+    }
+}
+
+impl From<IramAddr> for usize {
+    fn from(addr: IramAddr) -> usize {
+        addr.0
+    }
+}
+
+impl Add<usize> for IramAddr {
+    type Output = IramAddr;
+
+    fn add(self, rhs: usize) -> IramAddr {
+        IramAddr(self.0 + rhs)
+    }
+}
+
+impl Sub for IramAddr {
+    type Output = usize;
+
+    fn sub(self, rhs: IramAddr) -> usize {
+        self.0 - rhs.0
+    }
+}
+
+impl fmt::Display for IramAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
+
+
+/// A byte offset relative to the base of the host memory map.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HostOffset(pub usize);
+
+impl SegmentAddr for HostOffset {}
+
+impl From<usize> for HostOffset {
+    fn from(offset: usize) -> HostOffset {
+        HostOffset(offset)
+    }
+}
+
+impl From<HostOffset> for usize {
+    fn from(offset: HostOffset) -> usize {
+        offset.0
+    }
+}
+
+impl Add<usize> for HostOffset {
+    type Output = HostOffset;
+
+    fn add(self, rhs: usize) -> HostOffset {
+        HostOffset(self.0 + rhs)
+    }
+}
+
+impl Sub for HostOffset {
+    type Output = usize;
+
+    fn sub(self, rhs: HostOffset) -> usize {
+        self.0 - rhs.0
+    }
+}
+
+impl fmt::Display for HostOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
+
+
+/// A byte offset relative to the start of a `DmaBuf`.
+///
+/// The physical address of the designated location is the offset added to the address returned
+/// by `DmaBuf::phys_addr`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DmaBufOffset(pub usize);
+
+impl SegmentAddr for DmaBufOffset {}
+
+impl From<usize> for DmaBufOffset {
+    fn from(offset: usize) -> DmaBufOffset {
+        DmaBufOffset(offset)
+    }
+}
+
+impl From<DmaBufOffset> for usize {
+    fn from(offset: DmaBufOffset) -> usize {
+        offset.0
+    }
+}
+
+impl Add<usize> for DmaBufOffset {
+    type Output = DmaBufOffset;
+
+    fn add(self, rhs: usize) -> DmaBufOffset {
+        DmaBufOffset(self.0 + rhs)
+    }
+}
+
+impl Sub for DmaBufOffset {
+    type Output = usize;
+
+    fn sub(self, rhs: DmaBufOffset) -> usize {
+        self.0 - rhs.0
+    }
+}
+
+impl fmt::Display for DmaBufOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
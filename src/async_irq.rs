@@ -0,0 +1,191 @@
+//! Asynchronous waiting on event outs.
+//!
+//! With the `async` feature, an `EvtoutIrq` can be awaited from a tokio runtime instead of
+//! blocking a thread per event out. The event out device is registered with the tokio reactor
+//! on first use through `tokio::io::unix::AsyncFd`, so the runtime must have its IO driver
+//! enabled.
+//!
+//! Event outs can also be consumed as a `futures_core::Stream` of interrupt counts with
+//! `EvtoutIrq::stream`, or with `EvtoutIrq::stream_with` to clear and re-enable the interrupt
+//! automatically after each event.
+
+use EvtoutIrq;
+
+use futures_core::Stream;
+use libc;
+use tokio::io::unix::AsyncFd;
+
+use std::future::Future;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+
+
+/// Future returned by `EvtoutIrq::wait_async`.
+pub struct WaitAsync<'a> {
+    irq: &'a EvtoutIrq,
+    armed: bool,
+}
+
+impl<'a> Future for WaitAsync<'a> {
+    type Output = io::Result<u32>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<u32>> {
+        let WaitAsync { irq, ref mut armed } = *self;
+        poll_wait(irq, armed, cx)
+    }
+}
+
+
+
+/// Stream of event out notifications returned by `EvtoutIrq::stream` and
+/// `EvtoutIrq::stream_with`.
+///
+/// The stream yields the total interrupt count each time the event out is triggered. It ends
+/// if an IO error occurs on the event out device.
+pub struct EvtoutStream<'a, F> {
+    irq: &'a EvtoutIrq,
+    armed: bool,
+    rearm: F,
+}
+
+impl<'a, F: FnMut() + Unpin> Stream for EvtoutStream<'a, F> {
+    type Item = u32;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u32>> {
+        let EvtoutStream { irq, ref mut armed, ref mut rearm } = *self;
+        match poll_wait(irq, armed, cx) {
+            Poll::Ready(Ok(total)) => {
+                *armed = false;
+                rearm();
+                Poll::Ready(Some(total))
+            }
+            Poll::Ready(Err(_)) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+
+
+impl EvtoutIrq {
+    /// Returns a future which resolves with the total interrupt count once the associated event
+    /// out is triggered.
+    ///
+    /// This is the asynchronous counterpart of `try_wait`; the wait policy is ignored since the
+    /// future never spins. The future must be polled within a tokio runtime with its IO driver
+    /// enabled.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded, for instance if the event out device cannot be registered with
+    /// the tokio reactor.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the future is polled outside of a tokio runtime.
+    pub fn wait_async<'a>(&'a self) -> WaitAsync<'a> {
+        WaitAsync {
+            irq: self,
+            armed: false,
+        }
+    }
+
+    /// Returns a stream yielding the total interrupt count each time the associated event out
+    /// is triggered.
+    ///
+    /// The stream must be polled within a tokio runtime with its IO driver enabled.
+    pub fn stream<'a>(&'a self) -> EvtoutStream<'a, fn()> {
+        fn no_rearm() {}
+
+        self.stream_with(no_rearm as fn())
+    }
+
+    /// Returns a stream yielding the total interrupt count each time the associated event out
+    /// is triggered, calling `rearm` before each count is yielded.
+    ///
+    /// The closure typically clears the system event and re-enables the host interrupt so that
+    /// the next event out can be signaled, as `try_drain` does.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use prusst::{Pruss, Evtout, Sysevt, IntcConfig};
+    /// # let pruss = Pruss::new(&IntcConfig::new_populated()).unwrap();
+    /// let irq = pruss.intc.register_irq(Evtout::E0);
+    /// let intc = &pruss.intc;
+    /// let events = irq.stream_with(|| intc.ack(Sysevt::S19, Evtout::E0));
+    /// ```
+    pub fn stream_with<'a, F: FnMut()>(&'a self, rearm: F) -> EvtoutStream<'a, F> {
+        EvtoutStream {
+            irq: self,
+            armed: false,
+            rearm: rearm,
+        }
+    }
+
+    /// Returns the registration of the event out device with the tokio reactor, registering it
+    /// on first use.
+    fn async_fd(&self) -> io::Result<&AsyncFd<RawFd>> {
+        if let Some(async_fd) = self.async_fd.get() {
+            return Ok(async_fd);
+        }
+        match AsyncFd::new(self.file.as_raw_fd()) {
+            Ok(async_fd) => {
+                let _ = self.async_fd.set(async_fd);
+            }
+            // A descriptor cannot be registered twice, so the registration fails if another
+            // thread registered the device concurrently.
+            Err(err) => {
+                if self.async_fd.get().is_none() {
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(self.async_fd.get().unwrap())
+    }
+}
+
+
+// Polls the event out device, re-arming the interrupt on the first call.
+fn poll_wait(irq: &EvtoutIrq, armed: &mut bool, cx: &mut Context) -> Poll<io::Result<u32>> {
+    let async_fd = match irq.async_fd() {
+        Ok(async_fd) => async_fd,
+        Err(err) => return Poll::Ready(Err(err)),
+    };
+    if !*armed {
+        if let Err(err) = irq.begin_wait() {
+            return Poll::Ready(Err(err));
+        }
+        *armed = true;
+    }
+
+    loop {
+        let mut guard = match async_fd.poll_read_ready(cx) {
+            Poll::Ready(Ok(guard)) => guard,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        // The readiness reported by the reactor may be stale, so make sure that reading the
+        // interrupt count will not block.
+        if is_readable(irq.file.as_raw_fd()) {
+            return Poll::Ready(irq.read_count().map(|(_, total)| total));
+        }
+        guard.clear_ready();
+    }
+}
+
+
+// Checks without blocking whether the descriptor is readable.
+fn is_readable(fd: RawFd) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd: fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    unsafe { libc::poll(&mut pollfd, 1, 0) > 0 }
+}
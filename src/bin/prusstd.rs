@@ -0,0 +1,143 @@
+//! Daemon owning the PRU subsystem on behalf of its clients.
+//!
+//! Usage: `prusstd [--mode MODE] [--group GID] [--allow-uid UID]... [--allow-gid GID]...
+//! [SOCKET_PATH]`
+//!
+//! The socket path defaults to `/run/prusstd.sock`. The socket is created with mode `600`; the
+//! octal `--mode` and the `--group` options can be used to give a group access to it, in which
+//! case the group should also be allowed with `--allow-gid`. Only root and the user running the
+//! daemon are allowed to connect unless other users or primary groups are allowed with
+//! `--allow-uid` and `--allow-gid`. See the `prusst::daemon` module for a description of the
+//! protocol and of the threat model.
+
+extern crate prusst;
+
+use prusst::prelude::*;
+use prusst::daemon::Server;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::process;
+
+static DEFAULT_SOCKET_PATH: &'static str = "/run/prusstd.sock";
+
+
+struct Options {
+    path: String,
+    mode: Option<u32>,
+    group: Option<u32>,
+    allowed_uids: Vec<u32>,
+    allowed_gids: Vec<u32>,
+}
+
+
+fn usage() -> ! {
+    eprintln!("usage: prusstd [--mode MODE] [--group GID] [--allow-uid UID]... \
+               [--allow-gid GID]... [SOCKET_PATH]");
+    process::exit(2);
+}
+
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        path: DEFAULT_SOCKET_PATH.to_string(),
+        mode: None,
+        group: None,
+        allowed_uids: Vec::new(),
+        allowed_gids: Vec::new(),
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let radix = if arg == "--mode" { 8 } else { 10 };
+        let value = match arg.as_str() {
+            "--mode" | "--group" | "--allow-uid" | "--allow-gid" => {
+                match args.next().and_then(|v| u32::from_str_radix(&v, radix).ok()) {
+                    Some(v) => v,
+                    None => usage(),
+                }
+            }
+            _ if arg.starts_with('-') => usage(),
+            _ => {
+                options.path = arg;
+                continue;
+            }
+        };
+        match arg.as_str() {
+            "--mode" => options.mode = Some(value),
+            "--group" => options.group = Some(value),
+            "--allow-uid" => options.allowed_uids.push(value),
+            _ => options.allowed_gids.push(value),
+        }
+    }
+
+    options
+}
+
+
+// Removes a stale socket left over by a previous instance, refusing to remove other files.
+fn remove_stale_socket(path: &str) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => {
+            if metadata.file_type().is_socket() {
+                fs::remove_file(path)
+            } else {
+                Err(io::Error::new(io::ErrorKind::AlreadyExists, "file exists and is not a socket"))
+            }
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+
+fn main() {
+    let options = parse_options();
+    let path = &options.path;
+
+    let pruss = match Pruss::new(&IntcConfig::new_populated()) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("prusstd: could not access the PRU subsystem: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = remove_stale_socket(path) {
+        eprintln!("prusstd: could not remove {}: {}", path, e);
+        process::exit(1);
+    }
+
+    let mut server = match Server::bind(path, pruss) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("prusstd: could not bind to {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+
+    if let Some(gid) = options.group {
+        if let Err(e) = server.set_socket_group(gid) {
+            eprintln!("prusstd: could not set the group of {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    if let Some(mode) = options.mode {
+        if let Err(e) = server.set_socket_mode(mode) {
+            eprintln!("prusstd: could not set the mode of {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    for uid in options.allowed_uids {
+        server.allow_uid(uid);
+    }
+    for gid in options.allowed_gids {
+        server.allow_gid(gid);
+    }
+
+    if let Err(e) = server.run() {
+        eprintln!("prusstd: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,112 @@
+//! PRU core clock and cycle/time conversions.
+
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+
+
+/// Default PRU core clock frequency of the AM335x SoC (in Hz).
+pub const AM335X_PRU_CLOCK_HZ: u64 = 200_000_000;
+
+// Common clock framework attributes exposing the PRU functional clock rate as decimal text.
+static CLK_RATE_PATHS: [&'static str; 2] = ["/sys/kernel/debug/clk/pruss_ocp_gclk/clk_rate",
+                                            "/sys/kernel/debug/clk/pruss/clk_rate"];
+
+// Device tree properties exposing the PRU clock frequency as a big-endian 32-bit cell.
+static DT_CLOCK_FREQUENCY_PATHS: [&'static str; 2] =
+    ["/proc/device-tree/ocp/pruss@4a300000/clock-frequency",
+     "/proc/device-tree/ocp/interconnect@4a000000/segment@0/target-module@300000/pruss@0/\
+      clock-frequency"];
+
+
+
+/// PRU core clock.
+///
+/// # Example
+///
+/// ```
+/// # use prusst::clock::PruClock;
+/// # use std::time::Duration;
+/// let clock = PruClock::new(200_000_000);
+/// assert_eq!(clock.cycles_to_duration(200), Duration::from_micros(1));
+/// assert_eq!(clock.duration_to_cycles(Duration::from_millis(1)), 200_000);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PruClock {
+    hz: u64,
+}
+
+impl PruClock {
+    /// Creates a clock with the specified frequency (in Hz).
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the frequency is zero.
+    pub fn new(hz: u64) -> PruClock {
+        assert!(hz != 0);
+
+        PruClock { hz: hz }
+    }
+
+    /// Determines the PRU core clock frequency of the running system.
+    ///
+    /// The frequency is read from the clock framework debug attributes or from the device tree
+    /// when available, and defaults to the AM335x frequency otherwise.
+    pub fn detect() -> PruClock {
+        for path in CLK_RATE_PATHS.iter() {
+            if let Some(hz) = read_text(path).and_then(|s| s.trim().parse().ok()) {
+                if hz != 0 {
+                    return PruClock::new(hz);
+                }
+            }
+        }
+        for path in DT_CLOCK_FREQUENCY_PATHS.iter() {
+            if let Some(hz) = read_be_u32(path) {
+                if hz != 0 {
+                    return PruClock::new(hz as u64);
+                }
+            }
+        }
+
+        PruClock::default()
+    }
+
+    /// Returns the clock frequency (in Hz).
+    pub fn hz(&self) -> u64 {
+        self.hz
+    }
+
+    /// Converts a number of cycles to a duration, rounded down to the nanosecond.
+    pub fn cycles_to_duration(&self, cycles: u64) -> Duration {
+        let nanos = cycles as u128 * 1_000_000_000 / self.hz as u128;
+
+        Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+    }
+
+    /// Converts a duration to the nearest number of cycles.
+    pub fn duration_to_cycles(&self, duration: Duration) -> u64 {
+        ((duration.as_nanos() * self.hz as u128 + 500_000_000) / 1_000_000_000) as u64
+    }
+}
+
+impl Default for PruClock {
+    /// Returns the default AM335x PRU core clock.
+    fn default() -> PruClock {
+        PruClock::new(AM335X_PRU_CLOCK_HZ)
+    }
+}
+
+
+fn read_text(path: &str) -> Option<String> {
+    let mut text = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut text)).ok().map(|_| text)
+}
+
+
+fn read_be_u32(path: &str) -> Option<u32> {
+    let mut cell = [0u8; 4];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut cell))
+        .ok()
+        .map(|_| u32::from_be_bytes(cell))
+}
@@ -0,0 +1,132 @@
+//! PRU control register.
+
+use def::*;
+
+use std::fmt;
+use std::ptr;
+
+
+/// Content of a PRU control register.
+///
+/// The register is decoded into its bit fields, which can be modified individually with the
+/// `with_*` methods without clobbering the other fields:
+///
+/// ```
+/// # use prusst::PruCtrl;
+/// let ctrl = PruCtrl::from_bits(0x0000_0002).with_counter_enable(true);
+/// assert!(ctrl.enable() && ctrl.counter_enable());
+/// assert_eq!(ctrl.bits(), 0x0000_000a);
+/// ```
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PruCtrl(u32);
+
+impl PruCtrl {
+    /// Creates a decoded register from its raw content.
+    pub fn from_bits(bits: u32) -> PruCtrl {
+        PruCtrl(bits)
+    }
+
+    /// Raw content of the register.
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// SOFT_RST_N field: `false` while the core is held in reset.
+    pub fn soft_rst_n(self) -> bool {
+        self.0 & PRUCTRL_SOFT_RST_N != 0
+    }
+
+    /// ENABLE field: whether the core is allowed to fetch instructions.
+    pub fn enable(self) -> bool {
+        self.0 & PRUCTRL_ENABLE != 0
+    }
+
+    /// SLEEPING field: whether the core is asleep, waiting for an event.
+    pub fn sleeping(self) -> bool {
+        self.0 & PRUCTRL_SLEEPING != 0
+    }
+
+    /// COUNTER_ENABLE field: whether the cycle and stall counters are enabled.
+    pub fn counter_enable(self) -> bool {
+        self.0 & PRUCTRL_COUNTER_ENABLE != 0
+    }
+
+    /// SINGLE_STEP field: whether the core executes a single instruction each time it is enabled.
+    pub fn single_step(self) -> bool {
+        self.0 & PRUCTRL_SINGLE_STEP != 0
+    }
+
+    /// RUNSTATE field (read-only): whether the core is executing instructions.
+    pub fn runstate(self) -> bool {
+        self.0 & PRUCTRL_RUNSTATE != 0
+    }
+
+    /// PCTR_RST_VAL field: word address loaded in the program counter upon reset.
+    pub fn pctr_rst_val(self) -> u16 {
+        (self.0 >> PRUCTRL_PCTR_RST_VAL_SHIFT) as u16
+    }
+
+    /// Returns the register with the SOFT_RST_N field set to the specified value.
+    pub fn with_soft_rst_n(self, soft_rst_n: bool) -> PruCtrl {
+        self.with_bit(PRUCTRL_SOFT_RST_N, soft_rst_n)
+    }
+
+    /// Returns the register with the ENABLE field set to the specified value.
+    pub fn with_enable(self, enable: bool) -> PruCtrl {
+        self.with_bit(PRUCTRL_ENABLE, enable)
+    }
+
+    /// Returns the register with the SLEEPING field set to the specified value.
+    ///
+    /// Clearing this field wakes up a sleeping core.
+    pub fn with_sleeping(self, sleeping: bool) -> PruCtrl {
+        self.with_bit(PRUCTRL_SLEEPING, sleeping)
+    }
+
+    /// Returns the register with the COUNTER_ENABLE field set to the specified value.
+    pub fn with_counter_enable(self, counter_enable: bool) -> PruCtrl {
+        self.with_bit(PRUCTRL_COUNTER_ENABLE, counter_enable)
+    }
+
+    /// Returns the register with the SINGLE_STEP field set to the specified value.
+    pub fn with_single_step(self, single_step: bool) -> PruCtrl {
+        self.with_bit(PRUCTRL_SINGLE_STEP, single_step)
+    }
+
+    /// Returns the register with the PCTR_RST_VAL field set to the specified word address.
+    pub fn with_pctr_rst_val(self, pc: u16) -> PruCtrl {
+        let mask = 0xffff << PRUCTRL_PCTR_RST_VAL_SHIFT;
+        PruCtrl((self.0 & !mask) | ((pc as u32) << PRUCTRL_PCTR_RST_VAL_SHIFT))
+    }
+
+    /// Reads the control register.
+    pub(crate) unsafe fn read(reg: *const u32) -> PruCtrl {
+        PruCtrl(ptr::read_volatile(reg))
+    }
+
+    /// Writes the control register.
+    pub(crate) unsafe fn write(self, reg: *mut u32) {
+        ptr::write_volatile(reg, self.0);
+    }
+
+    fn with_bit(self, mask: u32, value: bool) -> PruCtrl {
+        match value {
+            true => PruCtrl(self.0 | mask),
+            false => PruCtrl(self.0 & !mask),
+        }
+    }
+}
+
+impl fmt::Debug for PruCtrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PruCtrl")
+            .field("soft_rst_n", &self.soft_rst_n())
+            .field("enable", &self.enable())
+            .field("sleeping", &self.sleeping())
+            .field("counter_enable", &self.counter_enable())
+            .field("single_step", &self.single_step())
+            .field("runstate", &self.runstate())
+            .field("pctr_rst_val", &self.pctr_rst_val())
+            .finish()
+    }
+}
@@ -0,0 +1,86 @@
+//! Sequential writer over a memory segment.
+
+use {MemSegment, SegmentAddr, PruAddr};
+
+use std::mem;
+use std::ptr;
+use std::slice;
+
+
+/// A cursor writing objects sequentially into a memory segment.
+///
+/// The cursor keeps track of the write position and returns the position at which each object
+/// was written, which makes it convenient to build packed command buffers or tables consumed by
+/// the PRU firmware. The segment remains borrowed for the lifetime of the cursor.
+pub struct SegmentCursor<'s, 'a: 's, A: SegmentAddr + 's = PruAddr> {
+    segment: &'s mut MemSegment<'a, A>,
+    pos: usize,
+}
+
+impl<'s, 'a, A: SegmentAddr> SegmentCursor<'s, 'a, A> {
+    pub(crate) fn new(segment: &'s mut MemSegment<'a, A>) -> SegmentCursor<'s, 'a, A> {
+        let pos = segment.from;
+        SegmentCursor {
+            segment: segment,
+            pos: pos,
+        }
+    }
+
+    /// Current write position.
+    pub fn position(&self) -> A {
+        self.pos.into()
+    }
+
+    /// Number of bytes that can still be written.
+    pub fn remaining(&self) -> usize {
+        self.segment.to - self.pos
+    }
+
+    /// Advances the write position to the next multiple of `align` and returns the new position.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `align` is not a power of two or if the aligned position
+    /// lies beyond the end of the segment.
+    pub fn align_to(&mut self, align: usize) -> A {
+        assert!(align.is_power_of_two());
+        let pos = (self.pos + align - 1) & !(align - 1);
+        assert!(pos <= self.segment.to);
+        self.pos = pos;
+
+        self.pos.into()
+    }
+
+    /// Writes an object at the current position and returns the position at which it was written.
+    ///
+    /// The object is written as is, without any padding or alignment: `align_to` should be called
+    /// beforehand if the firmware expects the object to be aligned.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the size of T exceeds the remaining capacity.
+    pub fn write_struct<T: Copy>(&mut self, value: &T) -> A {
+        self.write_slice(slice::from_ref(value))
+    }
+
+    /// Writes a slice of objects at the current position and returns the position at which it
+    /// was written.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the size of the slice exceeds the remaining capacity.
+    pub fn write_slice<T: Copy>(&mut self, values: &[T]) -> A {
+        let size = mem::size_of_val(values);
+        assert!(self.remaining() >= size);
+        let pos = self.pos;
+        unsafe {
+            let dst = self.segment.base.offset(pos as isize) as *mut T;
+            for (i, value) in values.iter().enumerate() {
+                ptr::write_unaligned(dst.offset(i as isize), *value);
+            }
+        }
+        self.pos += size;
+
+        pos.into()
+    }
+}
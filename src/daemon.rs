@@ -0,0 +1,527 @@
+//! Remote control of the PRU subsystem over a Unix-domain socket.
+//!
+//! Since only one `Pruss` instance may exist at a time and accessing the PRU subsystem usually
+//! requires elevated privileges, it is often convenient to have a single privileged process own
+//! the PRU subsystem on behalf of other processes. This module provides such a server, which is
+//! also available as the `prusstd` executable, together with the matching client.
+//!
+//! # Protocol
+//!
+//! Requests and responses are exchanged as frames made of a 32-bit little-endian payload length,
+//! a one-byte opcode and the payload itself. All multi-byte integers are encoded in little-endian
+//! order.
+//!
+//! | Request          | Opcode | Payload                                    | Response payload  |
+//! |------------------|--------|--------------------------------------------|-------------------|
+//! | Load code        | `0x01` | core (u8), code                            | -                 |
+//! | Run              | `0x02` | core (u8)                                  | -                 |
+//! | Halt             | `0x03` | core (u8)                                  | -                 |
+//! | Reset            | `0x04` | core (u8)                                  | -                 |
+//! | Peek             | `0x10` | region (u8), offset (u32), length (u32)    | data              |
+//! | Poke             | `0x11` | region (u8), offset (u32), data            | -                 |
+//! | Send system event| `0x20` | system event (u8)                          | -                 |
+//! | Clear system evt | `0x21` | system event (u8)                          | -                 |
+//! | Enable host      | `0x22` | host (u8)                                  | -                 |
+//! | Subscribe        | `0x30` | event out (u8)                             | -                 |
+//!
+//! Cores are numbered 0 (PRU0) and 1 (PRU1), and memory regions 0 (DRAM0), 1 (DRAM1), 2 (shared
+//! DRAM) and 3 (host memory), with offsets relative to the beginning of the region. Run, halt and
+//! reset requests are rejected until code has been successfully loaded in the core.
+//!
+//! Each request is answered with either an `OK` frame (opcode `0x00`) carrying the response
+//! payload or an `ERROR` frame (opcode `0xff`) carrying an UTF-8 error message. Once an event out
+//! has been subscribed to, the server also sends an `EVENT` frame (opcode `0x80`) with the event
+//! out (u8) and the interrupt count (u32) each time the event out is triggered; as with
+//! `EvtoutIrq`, the system event must then be cleared and the host interrupt re-enabled by the
+//! client.
+//!
+//! # Security
+//!
+//! A client of the server can run arbitrary code on the PRUs and write to the interrupt controller
+//! and to the PRU control registers. Since the PRUs can access the system memory, any client can
+//! take control of the whole system, so being allowed to connect is equivalent to being root.
+//!
+//! Access is therefore restricted in two ways. The socket file is created with mode `0o600`, so
+//! only its owner can connect unless `set_socket_mode` and `set_socket_group` are used to open it
+//! to a group. In addition, the credentials of each client are checked with `SO_PEERCRED`: only
+//! root, the user running the server and the users and primary groups added with `allow_uid` and
+//! `allow_gid` are served, while other clients receive an `ERROR` frame and are disconnected.
+//! Note that supplementary groups of the client are not taken into account by `allow_gid`.
+
+use {Pruss, PruCode, Evtout, Host, Sysevt};
+use {volatile_copy_from, volatile_copy_to};
+
+use libc;
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fs::{self, Permissions};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::fs::{self as unix_fs, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+
+// Opcodes
+const OP_LOAD: u8 = 0x01;
+const OP_RUN: u8 = 0x02;
+const OP_HALT: u8 = 0x03;
+const OP_RESET: u8 = 0x04;
+const OP_PEEK: u8 = 0x10;
+const OP_POKE: u8 = 0x11;
+const OP_SEND_SYSEVT: u8 = 0x20;
+const OP_CLEAR_SYSEVT: u8 = 0x21;
+const OP_ENABLE_HOST: u8 = 0x22;
+const OP_SUBSCRIBE: u8 = 0x30;
+const OP_OK: u8 = 0x00;
+const OP_EVENT: u8 = 0x80;
+const OP_ERROR: u8 = 0xff;
+
+// Maximum payload size accepted by the server and the client.
+const MAX_PAYLOAD_SIZE: usize = 0x100000;
+
+// Mode of the socket file after binding.
+const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+
+/// A memory region accessible through the daemon.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    Dram0,
+    Dram1,
+    Dram2,
+    Hostram,
+}
+
+
+
+// PRU subsystem together with the code loaded by the clients.
+struct State {
+    pruss: Pruss<'static>,
+    // Entry point and size (in words) of the code loaded in each core.
+    loaded: [Option<(u16, usize)>; 2],
+}
+
+
+
+/// Server owning the PRU subsystem on behalf of its clients.
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    state: Arc<Mutex<State>>,
+    allowed_uids: Vec<u32>,
+    allowed_gids: Vec<u32>,
+}
+
+impl Server {
+    /// Binds the server to the specified socket path.
+    ///
+    /// The socket file is given mode `0o600` and only root and the user running the server are
+    /// initially allowed to connect, see the module documentation.
+    ///
+    /// # Errors
+    ///
+    /// IO errors that may occur while binding the socket or setting its mode are forwarded.
+    pub fn bind<P: AsRef<Path>>(path: P, pruss: Pruss<'static>) -> io::Result<Server> {
+        let path = path.as_ref().to_path_buf();
+        let listener = try!(UnixListener::bind(&path));
+        try!(fs::set_permissions(&path, Permissions::from_mode(DEFAULT_SOCKET_MODE)));
+        let uid = unsafe { libc::geteuid() };
+        let mut allowed_uids = vec![0];
+        if uid != 0 {
+            allowed_uids.push(uid);
+        }
+
+        Ok(Server {
+            listener: listener,
+            path: path,
+            state: Arc::new(Mutex::new(State {
+                pruss: pruss,
+                loaded: [None, None],
+            })),
+            allowed_uids: allowed_uids,
+            allowed_gids: Vec::new(),
+        })
+    }
+
+    /// Sets the permission bits of the socket file, e.g. `0o660` to let the members of the
+    /// socket group connect.
+    ///
+    /// Note that clients must also be allowed with `allow_uid` or `allow_gid`.
+    ///
+    /// # Errors
+    ///
+    /// IO errors that may occur while changing the mode are forwarded.
+    pub fn set_socket_mode(&self, mode: u32) -> io::Result<()> {
+        fs::set_permissions(&self.path, Permissions::from_mode(mode))
+    }
+
+    /// Sets the group owning the socket file.
+    ///
+    /// # Errors
+    ///
+    /// IO errors that may occur while changing the group are forwarded.
+    pub fn set_socket_group(&self, gid: u32) -> io::Result<()> {
+        unix_fs::chown(&self.path, None, Some(gid))
+    }
+
+    /// Allows clients running with the specified user ID to be served.
+    pub fn allow_uid(&mut self, uid: u32) {
+        self.allowed_uids.push(uid);
+    }
+
+    /// Allows clients running with the specified primary group ID to be served.
+    pub fn allow_gid(&mut self, gid: u32) {
+        self.allowed_gids.push(gid);
+    }
+
+    /// Accepts and serves clients, each within a dedicated thread.
+    ///
+    /// Clients which are not allowed are sent an `ERROR` frame and disconnected.
+    ///
+    /// This function only returns if an error occurs while accepting a connection.
+    pub fn run(&self) -> io::Result<()> {
+        loop {
+            let (mut stream, _) = try!(self.listener.accept());
+            if !self.is_allowed(&stream) {
+                let _ = write_frame(&mut stream, OP_ERROR, b"permission denied");
+                continue;
+            }
+            let state = self.state.clone();
+            thread::spawn(move || {
+                let _ = serve(stream, state);
+            });
+        }
+    }
+}
+
+
+impl Server {
+    // Checks the credentials of a client.
+    fn is_allowed(&self, stream: &UnixStream) -> bool {
+        match peer_credentials(stream) {
+            Ok((uid, gid)) => self.allowed_uids.contains(&uid) || self.allowed_gids.contains(&gid),
+            Err(_) => false,
+        }
+    }
+}
+
+
+// Returns the user and group IDs of the peer of a socket.
+fn peer_credentials(stream: &UnixStream) -> io::Result<(u32, u32)> {
+    let mut cred: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let err = unsafe {
+        libc::getsockopt(stream.as_raw_fd(),
+                         libc::SOL_SOCKET,
+                         libc::SO_PEERCRED,
+                         &mut cred as *mut libc::ucred as *mut libc::c_void,
+                         &mut len)
+    };
+    if err != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((cred.uid, cred.gid))
+}
+
+
+// Serves a client until it disconnects.
+fn serve(stream: UnixStream, state: Arc<Mutex<State>>) -> io::Result<()> {
+    let mut reader = try!(stream.try_clone());
+    let writer = Arc::new(Mutex::new(stream));
+
+    loop {
+        let (opcode, payload) = try!(read_frame(&mut reader));
+        let response = match opcode {
+            OP_SUBSCRIBE => subscribe(&payload, &state, &writer),
+            _ => execute(opcode, &payload, &mut state.lock().unwrap()),
+        };
+        let mut writer = writer.lock().unwrap();
+        try!(match response {
+            Ok(data) => write_frame(&mut *writer, OP_OK, &data),
+            Err(msg) => write_frame(&mut *writer, OP_ERROR, msg.as_bytes()),
+        });
+    }
+}
+
+
+// Executes a request.
+fn execute(opcode: u8, payload: &[u8], state: &mut State) -> Result<Vec<u8>, String> {
+    let State { ref mut pruss, ref mut loaded } = *state;
+    match opcode {
+        OP_LOAD => {
+            let (&core, mut code) = try!(payload.split_first().ok_or("missing core"));
+            let loader = match core {
+                0 => &mut pruss.pru0,
+                1 => &mut pruss.pru1,
+                _ => return Err("invalid core".to_string()),
+            };
+            // The previous code may be partially overwritten if loading fails.
+            loaded[core as usize] = None;
+            let code = try!(loader.load_code(&mut code).map_err(|e| e.to_string()));
+            loaded[core as usize] = Some((code.entry, code.words));
+            Ok(Vec::new())
+        }
+        OP_RUN | OP_HALT | OP_RESET => {
+            let (loader, loaded) = match payload.first() {
+                Some(&0) => (&pruss.pru0, loaded[0]),
+                Some(&1) => (&pruss.pru1, loaded[1]),
+                _ => return Err("invalid core".to_string()),
+            };
+            // Only the code returned by a successful load may be controlled.
+            let (entry, words) = try!(loaded.ok_or("no code loaded"));
+            let mut code = PruCode::new(loader, entry, words);
+            match opcode {
+                OP_RUN => unsafe { code.run() },
+                OP_HALT => code.halt(),
+                _ => code.reset(),
+            }
+            Ok(Vec::new())
+        }
+        OP_PEEK | OP_POKE => {
+            if payload.len() < 5 {
+                return Err("truncated request".to_string());
+            }
+            let (base, size) = try!(region(pruss, payload[0]));
+            let offset = read_u32(&payload[1..5]) as usize;
+            if opcode == OP_PEEK {
+                if payload.len() < 9 {
+                    return Err("truncated request".to_string());
+                }
+                let len = read_u32(&payload[5..9]) as usize;
+                if len > MAX_PAYLOAD_SIZE || offset > size || len > size - offset {
+                    return Err("out of bounds access".to_string());
+                }
+                let mut data = vec![0u8; len];
+                unsafe { volatile_copy_from(&mut data, base.offset(offset as isize)) };
+                Ok(data)
+            } else {
+                let data = &payload[5..];
+                if offset > size || data.len() > size - offset {
+                    return Err("out of bounds access".to_string());
+                }
+                unsafe { volatile_copy_to(base.offset(offset as isize), data) };
+                Ok(Vec::new())
+            }
+        }
+        OP_SEND_SYSEVT | OP_CLEAR_SYSEVT => {
+            let se = try!(payload.first().ok_or("missing system event"));
+            let sysevt = try!(Sysevt::try_from(*se).map_err(|e| e.to_string()));
+            match opcode {
+                OP_SEND_SYSEVT => pruss.intc.send_sysevt(sysevt),
+                _ => pruss.intc.clear_sysevt(sysevt),
+            }
+            Ok(Vec::new())
+        }
+        OP_ENABLE_HOST => {
+            let h = try!(payload.first().ok_or("missing host"));
+            let host = try!(Host::try_from(*h).map_err(|e| e.to_string()));
+            pruss.intc.enable_host(host);
+            Ok(Vec::new())
+        }
+        _ => Err("unknown request".to_string()),
+    }
+}
+
+
+// Subscribes to an event out by spawning a thread forwarding events to the client.
+//
+// The thread terminates when it fails to forward an event, i.e. on the first event following
+// the disconnection of the client.
+fn subscribe(payload: &[u8],
+             state: &Arc<Mutex<State>>,
+             writer: &Arc<Mutex<UnixStream>>)
+             -> Result<Vec<u8>, String> {
+    let e = try!(payload.first().ok_or("missing event out"));
+    let evtout = try!(Evtout::try_from(*e).map_err(|e| e.to_string()));
+    let irq = try!(state.lock()
+        .unwrap()
+        .pruss
+        .intc
+        .try_register_irq(evtout)
+        .map_err(|e| format!("failed to open event out device: {}", e)));
+    let writer = writer.clone();
+    thread::spawn(move || {
+        // A failing event out device is handled like a disconnection of the client.
+        while let Ok(count) = irq.try_wait() {
+            let mut event = vec![evtout as u8];
+            event.extend_from_slice(&count.to_le_bytes());
+            if write_frame(&mut *writer.lock().unwrap(), OP_EVENT, &event).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(Vec::new())
+}
+
+
+// Returns the base address and size of a memory region.
+fn region(pruss: &Pruss, region: u8) -> Result<(*mut u8, usize), String> {
+    let (base, from, to) = match region {
+        0 => (pruss.dram0.base, pruss.dram0.from, pruss.dram0.to),
+        1 => (pruss.dram1.base, pruss.dram1.from, pruss.dram1.to),
+        2 => (pruss.dram2.base, pruss.dram2.from, pruss.dram2.to),
+        3 => (pruss.hostram.base, pruss.hostram.from, pruss.hostram.to),
+        _ => return Err("invalid region".to_string()),
+    };
+
+    Ok((unsafe { base.offset(from as isize) }, to - from))
+}
+
+
+
+/// Client of a PRU subsystem server.
+pub struct Client {
+    stream: UnixStream,
+    events: VecDeque<(Evtout, u32)>,
+}
+
+impl Client {
+    /// Connects to the server listening at the specified socket path.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Client> {
+        let stream = try!(UnixStream::connect(path));
+
+        Ok(Client {
+            stream: stream,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Loads a binary of opcodes to a PRU without executing it.
+    pub fn load_code(&mut self, core: u8, code: &[u8]) -> io::Result<()> {
+        let mut payload = vec![core];
+        payload.extend_from_slice(code);
+        self.request(OP_LOAD, &payload).map(|_| ())
+    }
+
+    /// Executes the code loaded in a PRU.
+    pub fn run(&mut self, core: u8) -> io::Result<()> {
+        self.request(OP_RUN, &[core]).map(|_| ())
+    }
+
+    /// Halts the execution of the code running in a PRU.
+    pub fn halt(&mut self, core: u8) -> io::Result<()> {
+        self.request(OP_HALT, &[core]).map(|_| ())
+    }
+
+    /// Resets a PRU.
+    pub fn reset(&mut self, core: u8) -> io::Result<()> {
+        self.request(OP_RESET, &[core]).map(|_| ())
+    }
+
+    /// Reads data from a memory region.
+    pub fn peek(&mut self, region: Region, offset: u32, len: u32) -> io::Result<Vec<u8>> {
+        let mut payload = vec![region as u8];
+        payload.extend_from_slice(&offset.to_le_bytes());
+        payload.extend_from_slice(&len.to_le_bytes());
+        self.request(OP_PEEK, &payload)
+    }
+
+    /// Writes data to a memory region.
+    pub fn poke(&mut self, region: Region, offset: u32, data: &[u8]) -> io::Result<()> {
+        let mut payload = vec![region as u8];
+        payload.extend_from_slice(&offset.to_le_bytes());
+        payload.extend_from_slice(data);
+        self.request(OP_POKE, &payload).map(|_| ())
+    }
+
+    /// Triggers a system event.
+    pub fn send_sysevt(&mut self, sysevt: Sysevt) -> io::Result<()> {
+        self.request(OP_SEND_SYSEVT, &[sysevt as u8]).map(|_| ())
+    }
+
+    /// Clears a system event.
+    pub fn clear_sysevt(&mut self, sysevt: Sysevt) -> io::Result<()> {
+        self.request(OP_CLEAR_SYSEVT, &[sysevt as u8]).map(|_| ())
+    }
+
+    /// Enables or re-enables a host interrupt.
+    pub fn enable_host<T: Into<Host>>(&mut self, host: T) -> io::Result<()> {
+        let host: Host = host.into();
+        self.request(OP_ENABLE_HOST, &[host as u8]).map(|_| ())
+    }
+
+    /// Subscribes to notifications of an event out.
+    pub fn subscribe(&mut self, evtout: Evtout) -> io::Result<()> {
+        self.request(OP_SUBSCRIBE, &[evtout as u8]).map(|_| ())
+    }
+
+    /// Waits for the next notification of a subscribed event out.
+    ///
+    /// Returns the event out and the interrupt count.
+    pub fn wait_event(&mut self) -> io::Result<(Evtout, u32)> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            let (opcode, payload) = try!(read_frame(&mut self.stream));
+            if opcode != OP_EVENT {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected response"));
+            }
+            self.events.push_back(try!(decode_event(&payload)));
+        }
+    }
+
+    // Sends a request and waits for the response, queuing event notifications received
+    // in the meantime.
+    fn request(&mut self, opcode: u8, payload: &[u8]) -> io::Result<Vec<u8>> {
+        try!(write_frame(&mut self.stream, opcode, payload));
+        loop {
+            let (opcode, payload) = try!(read_frame(&mut self.stream));
+            match opcode {
+                OP_OK => return Ok(payload),
+                OP_ERROR => {
+                    return Err(io::Error::other(String::from_utf8_lossy(&payload).into_owned()))
+                }
+                OP_EVENT => self.events.push_back(try!(decode_event(&payload))),
+                _ => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected response"))
+                }
+            }
+        }
+    }
+}
+
+
+fn decode_event(payload: &[u8]) -> io::Result<(Evtout, u32)> {
+    match (payload.len(), payload.first().map(|&e| Evtout::try_from(e))) {
+        (5, Some(Ok(evtout))) => Ok((evtout, read_u32(&payload[1..5]))),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid event notification")),
+    }
+}
+
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(buf)
+}
+
+
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    try!(reader.read_exact(&mut header));
+    let len = read_u32(&header[..4]) as usize;
+    if len > MAX_PAYLOAD_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+    }
+    let mut payload = vec![0u8; len];
+    try!(reader.read_exact(&mut payload));
+
+    Ok((header[4], payload))
+}
+
+
+fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.push(opcode);
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
@@ -2,14 +2,14 @@
 // System paths
 pub const PRUSS_DEVICE_PATH: &'static str = "/dev/uio0";
 pub const EVTOUT_DEVICE_ROOT_PATH: &'static str = "/dev/uio";
-pub const UIO_PRUMEM_SIZE_PATH: &'static str = "/sys/class/uio/uio0/maps/map0/size";
-pub const UIO_HOSTMEM_SIZE_PATH: &'static str = "/sys/class/uio/uio0/maps/map1/size";
+pub const UIO_SYSFS_PATH: &'static str = "/sys/class/uio/uio0";
 
 
 // Number of hosts, channels and events
 pub const NUM_SYSEVTS: u8 = 64;
 pub const NUM_CHANNELS: u8 = 10;
 pub const NUM_HOSTS: u8 = 10;
+pub const NUM_EVTOUTS: u8 = 8;
 
 
 // Memory offsets relative to PRU memory base and sizes of PRU memory regions
@@ -19,6 +19,7 @@ pub const DRAM2_OFFSET: usize = 0x10000;
 pub const INTC_OFFSET: usize = 0x20000;
 pub const PRU0CTRL_OFFSET: usize = 0x22000;
 pub const PRU1CTRL_OFFSET: usize = 0x24000;
+pub const IEP_OFFSET: usize = 0x2e000;
 pub const IRAM0_OFFSET: usize = 0x34000;
 pub const IRAM1_OFFSET: usize = 0x38000;
 
@@ -53,10 +54,19 @@ pub const SECR2_REG: isize = 0x0a1;
 pub const ESR1_REG: isize = 0x0c0;
 pub const ESR2_REG: isize = 0x0c1;
 
+pub const ECR1_REG: isize = 0x0e0;
+pub const ECR2_REG: isize = 0x0e1;
+
 pub const CMR_REG: isize = 0x100;
 
 pub const HMR_REG: isize = 0x200;
 
+pub const HIPIR_REG: isize = 0x240;
+
+pub const HINLR_REG: isize = 0x440;
+
+pub const HIER_REG: isize = 0x540;
+
 pub const SIPR1_REG: isize = 0x340;
 pub const SIPR2_REG: isize = 0x341;
 
@@ -64,10 +74,37 @@ pub const SITR1_REG: isize = 0x360;
 pub const SITR2_REG: isize = 0x361;
 
 
+// Memory offsets expressed as 32-bit words relative to the PRU control registers base
+pub const PRUSTATUS_REG: isize = 0x001;
+
+pub const PRUCYCLE_REG: isize = 0x003;
+
+pub const PRUSTALL_REG: isize = 0x004;
+
+pub const PRUDBG_GPREG: isize = 0x100;
+
+
+// Memory offsets expressed as 32-bit words relative to the IEP registers base
+pub const IEP_COUNT_REG: isize = 0x003;
+
+
 // Number of sub-registers
 pub const NUM_CMRX: isize = 16;
 pub const NUM_HMRX: isize = 3;
 
 
+// Bit fields
+pub const HIPIR_NONE_HINT: u32 = 0x80000000;
+pub const HINLR_NEST_LVL: u32 = 0x000001ff;
+pub const HINLR_AUTO_OVERRIDE: u32 = 0x80000000;
+pub const PRUCTRL_SOFT_RST_N: u32 = 0x00000001;
+pub const PRUCTRL_ENABLE: u32 = 0x00000002;
+pub const PRUCTRL_SLEEPING: u32 = 0x00000004;
+pub const PRUCTRL_COUNTER_ENABLE: u32 = 0x00000008;
+pub const PRUCTRL_SINGLE_STEP: u32 = 0x00000100;
+pub const PRUCTRL_RUNSTATE: u32 = 0x00008000;
+pub const PRUCTRL_PCTR_RST_VAL_SHIFT: u32 = 16;
+
+
 // Misc
 pub const PAGE_SIZE: isize = 4096;
@@ -0,0 +1,265 @@
+//! Interrupt controller configuration from device-tree data.
+//!
+//! Boards which describe the routing of PRU system events in their device tree, typically in a
+//! remoteproc overlay, reference the PRUSS interrupt controller node (compatible
+//! `ti,pruss-intc` or `ti,icssg-intc`) as the interrupt parent of their nodes. The interrupt
+//! specifiers of these nodes have three cells: the system event, the channel and the host
+//! interrupt. Collecting all such specifiers yields the interrupt controller configuration
+//! intended by the board.
+//!
+//! The device tree can be read from the file system, as exposed by the kernel in
+//! `/proc/device-tree`, or from a flattened device tree blob.
+
+use {Channel, Host, IntcConfig, Sysevt};
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+
+// Flattened device tree magic number and structure block tokens.
+const FDT_MAGIC: u32 = 0xd00dfeed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+// Compatible strings of the PRUSS interrupt controllers.
+const INTC_COMPATIBLES: [&'static str; 2] = ["ti,pruss-intc", "ti,icssg-intc"];
+
+
+
+// Device-tree node with its properties and sub-nodes.
+struct Node {
+    props: Vec<(String, Vec<u8>)>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new() -> Node {
+        Node {
+            props: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn prop(&self, name: &str) -> Option<&[u8]> {
+        self.props.iter().find(|prop| prop.0 == name).map(|prop| &prop.1[..])
+    }
+}
+
+
+
+impl IntcConfig {
+    /// Creates a configuration from the interrupt mapping of a device tree exposed on the file
+    /// system, typically `/proc/device-tree`.
+    ///
+    /// Each interrupt specifier of a node whose interrupt parent is the PRUSS interrupt
+    /// controller routes a system event to a host interrupt through a channel; the routed system
+    /// events and host interrupts are enabled, see `IntcConfigBuilder::route`.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded. An error of the kind `ErrorKind::NotFound` is returned if the
+    /// device tree has no PRUSS interrupt controller node and an error of the kind
+    /// `ErrorKind::InvalidData` if an interrupt specifier is malformed or conflicts with
+    /// another.
+    pub fn from_device_tree<P: AsRef<Path>>(root: P) -> io::Result<IntcConfig> {
+        decode(&try!(read_dir_node(root.as_ref())))
+    }
+
+    /// Creates a configuration from the interrupt mapping of a flattened device tree blob.
+    ///
+    /// This behaves like `from_device_tree`. The blob must contain the PRUSS interrupt
+    /// controller node, so unresolved overlays are not supported.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the blob is malformed, and
+    /// errors are otherwise returned as by `from_device_tree`.
+    pub fn from_dtb(dtb: &[u8]) -> io::Result<IntcConfig> {
+        decode(&try!(parse_dtb(dtb)))
+    }
+}
+
+
+// Reads a node and its sub-nodes from the file system.
+fn read_dir_node(path: &Path) -> io::Result<Node> {
+    let mut entries = try!(try!(fs::read_dir(path)).collect::<io::Result<Vec<_>>>());
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut node = Node::new();
+    for entry in entries {
+        let file_type = try!(entry.file_type());
+        if file_type.is_dir() {
+            node.children.push(try!(read_dir_node(&entry.path())));
+        } else if file_type.is_file() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            node.props.push((name, try!(fs::read(entry.path()))));
+        }
+    }
+
+    Ok(node)
+}
+
+
+// Parses the structure block of a flattened device tree blob.
+fn parse_dtb(dtb: &[u8]) -> io::Result<Node> {
+    if try!(read_be_u32(dtb, 0)) != FDT_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a device tree blob"));
+    }
+    let mut offset = try!(read_be_u32(dtb, 8)) as usize;
+    let strings = try!(read_be_u32(dtb, 12)) as usize;
+
+    let mut stack: Vec<Node> = Vec::new();
+    let mut root = None;
+    loop {
+        let token = try!(read_be_u32(dtb, offset));
+        offset += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name_len = try!(read_string(dtb, offset)).len();
+                offset = align4(offset + name_len + 1);
+                stack.push(Node::new());
+            }
+            FDT_END_NODE => {
+                let node = try!(stack.pop().ok_or_else(malformed));
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(node),
+                    None => root = Some(node),
+                }
+            }
+            FDT_PROP => {
+                let len = try!(read_be_u32(dtb, offset)) as usize;
+                let name_offset = try!(read_be_u32(dtb, offset + 4)) as usize;
+                let name_offset = try!(strings.checked_add(name_offset).ok_or_else(malformed));
+                let name = try!(read_string(dtb, name_offset));
+                let end = try!((offset + 8).checked_add(len).ok_or_else(malformed));
+                let value = try!(dtb.get(offset + 8..end).ok_or_else(malformed));
+                try!(stack.last_mut().ok_or_else(malformed)).props.push((name, value.to_vec()));
+                offset = align4(end);
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            _ => return Err(malformed()),
+        }
+    }
+
+    root.ok_or_else(malformed)
+}
+
+
+// Builds the configuration from the interrupt specifiers referencing the interrupt controller.
+fn decode(root: &Node) -> io::Result<IntcConfig> {
+    let mut intc = Vec::new();
+    try!(find_intc(root, &mut intc));
+    if intc.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound,
+                                  "no PRUSS interrupt controller in device tree"));
+    }
+    let mut routes = Vec::new();
+    try!(collect_routes(root, None, &intc, &mut routes));
+
+    let mut builder = IntcConfig::builder();
+    for &(sysevt, channel, host) in &routes {
+        let route = (u8::try_from(sysevt).ok().and_then(|n| Sysevt::try_from(n).ok()),
+                     u8::try_from(channel).ok().and_then(|n| Channel::try_from(n).ok()),
+                     u8::try_from(host).ok().and_then(|n| Host::try_from(n).ok()));
+        match route {
+            (Some(sysevt), Some(channel), Some(host)) => {
+                builder = builder.route(sysevt, channel, host);
+            }
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("invalid PRUSS interrupt specifier <{} {} {}>",
+                                                  sysevt,
+                                                  channel,
+                                                  host)));
+            }
+        }
+    }
+
+    builder.build().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+
+// Collects the phandles of the PRUSS interrupt controller nodes.
+fn find_intc(node: &Node, phandles: &mut Vec<u32>) -> io::Result<()> {
+    let compatible = node.prop("compatible").unwrap_or(&[]);
+    if compatible.split(|&c| c == 0)
+        .any(|name| INTC_COMPATIBLES.iter().any(|intc| intc.as_bytes() == name)) {
+        if let Some(phandle) = node.prop("phandle").or_else(|| node.prop("linux,phandle")) {
+            phandles.push(try!(read_be_u32(phandle, 0)));
+        }
+    }
+    for child in &node.children {
+        try!(find_intc(child, phandles));
+    }
+
+    Ok(())
+}
+
+
+// Collects the `(sysevt, channel, host)` interrupt specifiers of the nodes whose interrupt
+// parent, which is inherited from the ancestors, is a PRUSS interrupt controller.
+fn collect_routes(node: &Node,
+                  parent: Option<u32>,
+                  intc: &[u32],
+                  routes: &mut Vec<(u32, u32, u32)>)
+                  -> io::Result<()> {
+    let parent = match node.prop("interrupt-parent") {
+        Some(phandle) => Some(try!(read_be_u32(phandle, 0))),
+        None => parent,
+    };
+    if let (Some(phandle), Some(interrupts)) = (parent, node.prop("interrupts")) {
+        if intc.contains(&phandle) {
+            if interrupts.len() % 12 != 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "malformed PRUSS interrupt specifiers"));
+            }
+            for i in 0..interrupts.len() / 12 {
+                let route = (try!(read_be_u32(interrupts, 12 * i)),
+                             try!(read_be_u32(interrupts, 12 * i + 4)),
+                             try!(read_be_u32(interrupts, 12 * i + 8)));
+                // The same event may be referenced by several nodes.
+                if !routes.contains(&route) {
+                    routes.push(route);
+                }
+            }
+        }
+    }
+    for child in &node.children {
+        try!(collect_routes(child, parent, intc, routes));
+    }
+
+    Ok(())
+}
+
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed device tree blob")
+}
+
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+
+fn read_be_u32(bytes: &[u8], offset: usize) -> io::Result<u32> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(malformed()),
+    }
+}
+
+
+// Reads a NUL-terminated string.
+fn read_string(bytes: &[u8], offset: usize) -> io::Result<String> {
+    let bytes = try!(bytes.get(offset..).ok_or_else(malformed));
+    let len = try!(bytes.iter().position(|&c| c == 0).ok_or_else(malformed));
+
+    Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
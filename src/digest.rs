@@ -0,0 +1,151 @@
+//! Firmware image digests.
+
+use std::fmt;
+use std::io;
+
+
+/// Digest of a firmware image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Digest {
+    /// CRC-32 checksum (IEEE 802.3 polynomial).
+    Crc32(u32),
+    /// SHA-256 hash.
+    Sha256([u8; 32]),
+}
+
+impl Digest {
+    /// Parses a digest in the `crc32:<hex>` or `sha256:<hex>` format.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the digest is malformed.
+    pub fn parse(digest: &str) -> io::Result<Digest> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed digest");
+        // `from_str_radix` accepts a leading sign, so digits are checked beforehand.
+        let is_hex = |hex: &str, len: usize| {
+            hex.len() == len && hex.bytes().all(|b| b.is_ascii_hexdigit())
+        };
+        if let Some(hex) = digest.strip_prefix("crc32:") {
+            if !is_hex(hex, 8) {
+                return Err(invalid());
+            }
+            u32::from_str_radix(hex, 16).map(Digest::Crc32).map_err(|_| invalid())
+        } else if let Some(hex) = digest.strip_prefix("sha256:") {
+            if !is_hex(hex, 64) {
+                return Err(invalid());
+            }
+            let mut hash = [0u8; 32];
+            for (i, byte) in hash.iter_mut().enumerate() {
+                *byte = try!(u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+                    .map_err(|_| invalid()));
+            }
+            Ok(Digest::Sha256(hash))
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Computes a digest of the same kind for the specified data.
+    pub fn compute(&self, data: &[u8]) -> Digest {
+        match *self {
+            Digest::Crc32(_) => Digest::Crc32(crc32(data)),
+            Digest::Sha256(_) => Digest::Sha256(sha256(data)),
+        }
+    }
+
+    /// Checks whether the digest matches the specified data.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.compute(data) == *self
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Digest::Crc32(crc) => write!(f, "crc32:{:08x}", crc),
+            Digest::Sha256(ref hash) => {
+                try!(write!(f, "sha256:"));
+                for byte in hash.iter() {
+                    try!(write!(f, "{:02x}", byte));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+
+/// Computes the CRC-32 checksum (IEEE 802.3 polynomial) of the data.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+
+    !crc
+}
+
+
+// SHA-256 round constants.
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+
+/// Computes the SHA-256 hash of the data.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+                           0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+    // Pad the message with a 1 bit, zeros and the message length in bits.
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let mut v = h;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7].wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+            v = [t1.wrapping_add(t2), v[0], v[1], v[2], v[3].wrapping_add(t1), v[4], v[5], v[6]];
+        }
+        for (hi, vi) in h.iter_mut().zip(v.iter()) {
+            *hi = hi.wrapping_add(*vi);
+        }
+    }
+
+    let mut hash = [0u8; 32];
+    for (bytes, word) in hash.chunks_mut(4).zip(h.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+
+    hash
+}
@@ -0,0 +1,171 @@
+//! Background dispatching of event outs to callbacks.
+//!
+//! Most applications dedicate a thread to waiting for event outs and, for each of them, clearing
+//! the triggering system event, re-enabling the host interrupt and handling the event. An
+//! `IrqDispatcher` does this on behalf of the application: it waits on all registered event outs
+//! from a single service thread and, each time an event out is triggered, clears the associated
+//! system event and re-enables the host interrupt before invoking the callback, so that an event
+//! signaled while the callback runs is not lost.
+
+use {DispatchError, Evtout, Intc, Sysevt, WaitError};
+use irq_set::{IrqSet, Wakeup};
+
+use libc;
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::result;
+use std::thread;
+
+
+// Callback invoked with the number of interrupts since the previous invocation.
+type Callback = Box<dyn FnMut(&Intc, u32) + Send>;
+
+
+
+// Handle to the interrupt controller owned by the service thread.
+struct ServiceIntc(Intc);
+
+unsafe impl Send for ServiceIntc {}
+
+
+
+/// Set of event out callbacks to be run on a service thread.
+pub struct IrqDispatcher {
+    callbacks: IrqSet<Callback>,
+}
+
+impl IrqDispatcher {
+    /// Creates a dispatcher without callbacks.
+    pub fn new() -> IrqDispatcher {
+        IrqDispatcher { callbacks: IrqSet::new() }
+    }
+
+    /// Registers a callback for an event out.
+    ///
+    /// Each time the event out is triggered, the system event is cleared and the host interrupt
+    /// of the event out is re-enabled, after which the callback is invoked with the interrupt
+    /// controller and the number of interrupts signaled since the previous invocation. The
+    /// latter may be greater than 1 if the callback could not keep up with the PRU.
+    ///
+    /// The event out is registered immediately, so this function should be called before the
+    /// event out can be triggered.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::AlreadyExists` is returned if a callback is already
+    /// registered for the event out. IO errors that may occur while opening the event out device
+    /// are forwarded.
+    pub fn on_evtout<T, F>(&mut self,
+                           intc: &Intc,
+                           e: Evtout,
+                           sysevt: T,
+                           callback: F)
+                           -> io::Result<()>
+        where T: Into<Sysevt>,
+              F: FnMut(&Intc, u32) + Send + 'static
+    {
+        self.callbacks.insert(intc, e, sysevt.into(), Box::new(callback))
+    }
+
+    /// Starts dispatching the event outs on a new service thread.
+    ///
+    /// The service thread runs until the returned handle is stopped or dropped.
+    ///
+    /// # Errors
+    ///
+    /// OS errors that may occur while spawning the thread are forwarded.
+    pub fn spawn(self, intc: &Intc) -> io::Result<DispatcherHandle> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (stop_rx, stop_tx) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let intc = ServiceIntc(Intc {
+            intc_reg: intc.intc_reg,
+            prumap: intc.prumap.clone(),
+            irq_control: intc.irq_control,
+            evtout_prefix: intc.evtout_prefix.clone(),
+            event_counters: intc.event_counters.clone(),
+        });
+        let callbacks = self.callbacks;
+        let thread = try!(thread::Builder::new()
+            .name("prusst-irq".to_string())
+            .spawn(move || dispatch(intc, callbacks, stop_rx)));
+
+        Ok(DispatcherHandle {
+            thread: Some(thread),
+            stop_tx: stop_tx,
+        })
+    }
+}
+
+impl Default for IrqDispatcher {
+    fn default() -> IrqDispatcher {
+        IrqDispatcher::new()
+    }
+}
+
+
+
+/// Handle to a running `IrqDispatcher`.
+///
+/// The service thread is stopped when the handle is dropped. The service thread also terminates
+/// if a callback panics or if an event out device fails, in which case the cause is reported by
+/// `stop`.
+pub struct DispatcherHandle {
+    thread: Option<thread::JoinHandle<result::Result<(), WaitError>>>,
+    stop_tx: File,
+}
+
+impl DispatcherHandle {
+    /// Stops the service thread and waits for its termination.
+    ///
+    /// A callback being invoked is allowed to complete.
+    ///
+    /// # Errors
+    ///
+    /// If a callback panicked or if an interrupt could not be re-armed or an event out device
+    /// failed, the service thread has terminated prematurely and the panic payload or the device
+    /// error is returned.
+    pub fn stop(mut self) -> result::Result<(), DispatchError> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> result::Result<(), DispatchError> {
+        match self.thread.take() {
+            Some(thread) => {
+                let _ = self.stop_tx.write_all(&[0]);
+                match thread.join() {
+                    Ok(result) => result.map_err(DispatchError::Wait),
+                    Err(payload) => Err(DispatchError::Panicked(payload)),
+                }
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for DispatcherHandle {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+
+// Body of the service thread.
+fn dispatch(intc: ServiceIntc,
+            mut callbacks: IrqSet<Callback>,
+            stop_rx: File)
+            -> result::Result<(), WaitError> {
+    let ServiceIntc(intc) = intc;
+    loop {
+        let wakeup = try!(callbacks.wait(&intc, Some(stop_rx.as_raw_fd()), -1, |callback, intc, n| {
+            callback(intc, n)
+        }));
+        if wakeup == Wakeup::Stopped {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Host memory buffers shareable with other kernel drivers.
+//!
+//! The host memory allocated by the UIO driver cannot be handed over to other kernel drivers. A
+//! `DmaBuf` is instead allocated from a Linux DMA heap and is exported as a dma-buf file
+//! descriptor, so that data produced by the PRU can be consumed zero-copy by camera, display or
+//! crypto drivers which import dma-bufs. When the buffer is allocated from a physically
+//! contiguous heap such as the CMA heap, its physical address can be passed to the PRU firmware.
+//!
+//! The buffer is freed when the `DmaBuf` and all file descriptors referring to it, including
+//! those duplicated by importing drivers, have been closed.
+
+use {MemSegment, DmaBufOffset, SyncFile, MemMap};
+use def::PAGE_SIZE;
+
+use libc;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::io::RawFd;
+
+
+/// Directory of the DMA heap devices.
+pub const DMA_HEAP_DIR: &'static str = "/dev/dma_heap";
+
+// ioctl numbers and flags from linux/dma-heap.h and linux/dma-buf.h.
+const DMA_HEAP_IOCTL_ALLOC: libc::c_ulong = 0xc0184800;
+const DMA_BUF_IOCTL_SYNC: libc::c_ulong = 0x40086200;
+const DMA_BUF_SYNC_RW: u64 = 3;
+const DMA_BUF_SYNC_END: u64 = 4;
+
+// Page frame number field and page present bit of a /proc/self/pagemap entry.
+const PAGEMAP_PFN_MASK: u64 = (1 << 55) - 1;
+const PAGEMAP_PRESENT: u64 = 1 << 63;
+
+
+
+#[repr(C)]
+struct DmaHeapAllocationData {
+    len: u64,
+    fd: u32,
+    fd_flags: u32,
+    heap_flags: u64,
+}
+
+
+
+/// Buffer allocated from a DMA heap and exported as a dma-buf.
+pub struct DmaBuf {
+    // The mapping must be released before the file descriptor.
+    map: MemMap,
+    file: SyncFile,
+}
+
+impl DmaBuf {
+    /// Allocates a buffer of at least `size` bytes from the named DMA heap, e.g. `"linux,cma"`
+    /// or `"reserved"`, and maps it in the process address space.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded, for instance if the heap does not exist or if it is exhausted.
+    pub fn alloc(heap: &str, size: usize) -> io::Result<DmaBuf> {
+        let size = (size + PAGE_SIZE as usize - 1) & !(PAGE_SIZE as usize - 1);
+        let heap = try!(SyncFile::new(format!("{}/{}", DMA_HEAP_DIR, heap)));
+        let mut data = DmaHeapAllocationData {
+            len: size as u64,
+            fd: 0,
+            fd_flags: (libc::O_RDWR | libc::O_CLOEXEC) as u32,
+            heap_flags: 0,
+        };
+        if unsafe { libc::ioctl(heap.fd, DMA_HEAP_IOCTL_ALLOC as _, &mut data) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = SyncFile { fd: data.fd as libc::c_int };
+        let map = try!(MemMap::new(file.fd, size, 0, true));
+
+        Ok(DmaBuf {
+            map: map,
+            file: file,
+        })
+    }
+
+    /// Size of the buffer (in bytes).
+    pub fn size(&self) -> usize {
+        self.map.size
+    }
+
+    /// Locks the buffer in RAM.
+    ///
+    /// The buffer is always pre-faulted upon allocation; this additionally uses `mlock` to make
+    /// sure that its pages remain resident. Note that locking memory may require elevated
+    /// privileges or a sufficient `RLIMIT_MEMLOCK` limit.
+    ///
+    /// # Errors
+    ///
+    /// OS errors that may occur while locking the buffer are forwarded.
+    pub fn lock(&self) -> io::Result<()> {
+        self.map.lock()
+    }
+
+    /// Returns the dma-buf file descriptor.
+    ///
+    /// The descriptor remains owned by the `DmaBuf`; importing drivers take their own reference
+    /// to the buffer.
+    pub fn fd(&self) -> RawFd {
+        self.file.fd
+    }
+
+    /// Returns a new dma-buf file descriptor owned by the caller.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded.
+    pub fn dup_fd(&self) -> io::Result<RawFd> {
+        match unsafe { libc::fcntl(self.file.fd, libc::F_DUPFD_CLOEXEC, 0) } {
+            fd if fd < 0 => Err(io::Error::last_os_error()),
+            fd => Ok(fd),
+        }
+    }
+
+    /// Returns a memory segment spanning the whole buffer.
+    ///
+    /// The segment borrows the buffer, which hence cannot be dropped while the segment is alive.
+    /// Positions within the segment are offsets relative to the physical address returned by
+    /// `phys_addr`.
+    pub fn segment<'b>(&'b mut self) -> MemSegment<'b, DmaBufOffset> {
+        MemSegment::new(self.map.base, 0, self.map.size)
+    }
+
+    /// Returns the physical address of the buffer for use by the PRU firmware.
+    ///
+    /// Reading physical addresses requires the `CAP_SYS_ADMIN` capability.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded. An error of the kind `ErrorKind::InvalidData` is returned if the
+    /// buffer is not physically contiguous, or if the physical addresses are not readable.
+    pub fn phys_addr(&self) -> io::Result<usize> {
+        let mut pagemap = try!(File::open("/proc/self/pagemap"));
+        let page_size = PAGE_SIZE as usize;
+        let mut first = 0;
+        for i in 0..self.map.size / page_size {
+            let vaddr = self.map.base as usize + i * page_size;
+            let mut bytes = [0u8; 8];
+            try!(pagemap.seek(SeekFrom::Start((vaddr / page_size * bytes.len()) as u64)));
+            try!(pagemap.read_exact(&mut bytes));
+            let entry = u64::from_ne_bytes(bytes);
+            let pfn = entry & PAGEMAP_PFN_MASK;
+            if entry & PAGEMAP_PRESENT == 0 || pfn == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "physical address not available"));
+            }
+            if i == 0 {
+                first = pfn;
+            } else if pfn != first + i as u64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "buffer is not physically contiguous"));
+            }
+        }
+
+        Ok(first as usize * page_size)
+    }
+
+    /// Notifies the exporter that the host processor starts accessing the buffer.
+    ///
+    /// This maintains cache coherency with devices when the buffer is cached; accesses should be
+    /// bracketed by `begin_cpu_access` and `end_cpu_access`.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded.
+    pub fn begin_cpu_access(&self) -> io::Result<()> {
+        self.sync(DMA_BUF_SYNC_RW)
+    }
+
+    /// Notifies the exporter that the host processor has finished accessing the buffer.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded.
+    pub fn end_cpu_access(&self) -> io::Result<()> {
+        self.sync(DMA_BUF_SYNC_RW | DMA_BUF_SYNC_END)
+    }
+
+    fn sync(&self, flags: u64) -> io::Result<()> {
+        match unsafe { libc::ioctl(self.file.fd, DMA_BUF_IOCTL_SYNC as _, &flags) } {
+            err if err < 0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
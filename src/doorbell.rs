@@ -0,0 +1,270 @@
+//! Command kick and acknowledgement.
+//!
+//! Many firmwares wait for a system event from the host, perform a request and notify the host
+//! through an event out. A `Doorbell` implements the host side of this interaction, taking care
+//! of clearing the reply system event and re-enabling the host interrupt in the right order:
+//!
+//! ```no_run
+//! # use prusst::{Pruss, IntcConfig, Evtout, Sysevt};
+//! # use prusst::doorbell::Doorbell;
+//! # use std::time::Duration;
+//! # let pruss = Pruss::new(&IntcConfig::new_populated()).unwrap();
+//! let doorbell = Doorbell::from_evtout(&pruss.intc, Sysevt::S21, Evtout::E0, Sysevt::S19)
+//!     .unwrap();
+//! doorbell.ring();
+//! if !doorbell.wait_ack(Duration::from_millis(10)).unwrap() {
+//!     println!("no reply from the firmware");
+//! }
+//! ```
+//!
+//! Firmwares may also read a command from a status word, perform it and overwrite the status
+//! word with a result before replying:
+//!
+//! ```no_run
+//! # use prusst::{Pruss, IntcConfig, Evtout, Sysevt};
+//! # use prusst::doorbell::Doorbell;
+//! # use prusst::util::VolatileCell;
+//! # use std::time::Duration;
+//! # let mut pruss = Pruss::new(&IntcConfig::new_populated()).unwrap();
+//! let irq = pruss.intc.register_irq(Evtout::E0);
+//! let status = pruss.dram0.alloc(VolatileCell::new(0u32));
+//! let mut doorbell = Doorbell::new(&pruss.intc, Sysevt::S21, &irq, Sysevt::S19, status);
+//! match doorbell.ring_and_wait(42, Duration::from_millis(10)).unwrap() {
+//!     Some(result) => println!("result: {}", result),
+//!     None => println!("no reply from the firmware"),
+//! }
+//! ```
+//!
+//! The firmware is expected to clear the kick system event itself.
+//!
+//! Interactions which do not need a status word can also use `Intc::transaction`.
+
+use {Intc, Evtout, EvtoutIrq, Sysevt, Host, WaitError};
+use def::*;
+use util::VolatileCell;
+
+use std::convert::TryFrom;
+use std::io;
+use std::ptr;
+use std::result;
+use std::time::Duration;
+
+
+
+// Event out monitored by a doorbell.
+enum ReplyIrq<'a> {
+    Borrowed(&'a EvtoutIrq),
+    Owned(Box<EvtoutIrq>),
+}
+
+impl<'a> ReplyIrq<'a> {
+    fn get(&self) -> &EvtoutIrq {
+        match *self {
+            ReplyIrq::Borrowed(irq) => irq,
+            ReplyIrq::Owned(ref irq) => irq,
+        }
+    }
+}
+
+
+
+/// Host side of a kick/acknowledge interaction with the firmware.
+pub struct Doorbell<'a> {
+    intc: &'a Intc,
+    kick: Sysevt,
+    irq: ReplyIrq<'a>,
+    reply: Sysevt,
+    status: Option<&'a mut VolatileCell<u32>>,
+}
+
+impl<'a> Doorbell<'a> {
+    /// Creates a doorbell with a status word.
+    ///
+    /// The `kick` system event is triggered to notify the firmware of a command written in the
+    /// status word, and the firmware replies by triggering the `reply` system event, which must
+    /// be mapped to the event out monitored by `irq`.
+    pub fn new<K, R>(intc: &'a Intc,
+                     kick: K,
+                     irq: &'a EvtoutIrq,
+                     reply: R,
+                     status: &'a mut VolatileCell<u32>)
+                     -> Doorbell<'a>
+        where K: Into<Sysevt>,
+              R: Into<Sysevt>
+    {
+        Doorbell {
+            intc: intc,
+            kick: kick.into(),
+            irq: ReplyIrq::Borrowed(irq),
+            reply: reply.into(),
+            status: Some(status),
+        }
+    }
+
+    /// Creates a doorbell without status word which monitors an event out of its own.
+    ///
+    /// The `kick` system event is triggered to notify the firmware, and the firmware replies by
+    /// triggering the `reply` system event, which must be mapped to `evtout`. As with
+    /// `Intc::register_irq`, the doorbell should be created before the event out is triggered.
+    ///
+    /// # Errors
+    ///
+    /// IO errors that may occur while opening the event out device are forwarded.
+    pub fn from_evtout<K, R>(intc: &'a Intc,
+                             kick: K,
+                             evtout: Evtout,
+                             reply: R)
+                             -> io::Result<Doorbell<'a>>
+        where K: Into<Sysevt>,
+              R: Into<Sysevt>
+    {
+        Ok(Doorbell {
+            intc: intc,
+            kick: kick.into(),
+            irq: ReplyIrq::Owned(Box::new(try!(intc.try_register_irq(evtout)))),
+            reply: reply.into(),
+            status: None,
+        })
+    }
+
+    /// Triggers the kick system event.
+    pub fn ring(&self) {
+        self.intc.send_sysevt(self.kick);
+    }
+
+    /// Writes a command to the status word and triggers the kick system event.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the doorbell has no status word.
+    pub fn send(&mut self, command: u32) {
+        self.status_word().set(command);
+        self.ring();
+    }
+
+    /// Waits for the reply of the firmware and acknowledges it.
+    ///
+    /// Returns `false` if no reply was received before the timeout expired.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the interrupt cannot be re-armed or if the event out device fails.
+    pub fn wait_ack(&self, timeout: Duration) -> result::Result<bool, WaitError> {
+        match try!(self.irq.get().try_wait_timeout(timeout)) {
+            Some(_) => {
+                self.acknowledge();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Waits for the reply of the firmware, acknowledges it and returns the status word.
+    ///
+    /// Returns `None` if no reply was received before the timeout expired.
+    ///
+    /// # Errors
+    ///
+    /// Same as `wait_ack`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the doorbell has no status word.
+    pub fn wait(&mut self, timeout: Duration) -> result::Result<Option<u32>, WaitError> {
+        if try!(self.wait_ack(timeout)) {
+            Ok(Some(self.status_word().get()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Writes a command to the status word, triggers the kick system event, waits for the reply
+    /// of the firmware, acknowledges it and returns the status word.
+    ///
+    /// Returns `None` if no reply was received before the timeout expired.
+    ///
+    /// # Errors
+    ///
+    /// Same as `wait_ack`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the doorbell has no status word.
+    pub fn ring_and_wait(&mut self,
+                         command: u32,
+                         timeout: Duration)
+                         -> result::Result<Option<u32>, WaitError> {
+        self.send(command);
+        self.wait(timeout)
+    }
+
+    /// Acknowledges the reply of the firmware by clearing the reply system event and
+    /// re-enabling the host interrupt.
+    pub fn acknowledge(&self) {
+        self.intc.ack(self.reply, self.irq.get().get_evtout());
+    }
+
+    /// Returns the current content of the status word, if any.
+    pub fn status(&self) -> Option<u32> {
+        self.status.as_ref().map(|status| status.get())
+    }
+
+    fn status_word(&mut self) -> &mut VolatileCell<u32> {
+        self.status.as_mut().expect("the doorbell has no status word")
+    }
+}
+
+
+
+impl Intc {
+    /// Triggers a system event, waits for the reply of the firmware on an event out and
+    /// acknowledges it.
+    ///
+    /// Once the reply has been received, the pending system events mapped to the host interrupt
+    /// of the event out are cleared and the host interrupt is re-enabled, so the event out is
+    /// ready for the next transaction. Note that system events sharing the same host interrupt
+    /// are cleared as well.
+    ///
+    /// Returns the number of interrupts received since the previous wait on the event out,
+    /// which is usually 1.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::TimedOut` is returned if no reply was received before the
+    /// timeout expired, in which case no system event is cleared. Errors of the event out device
+    /// are forwarded.
+    pub fn transaction<T: Into<Sysevt>>(&self,
+                                        trigger: T,
+                                        reply: &EvtoutIrq,
+                                        timeout: Duration)
+                                        -> io::Result<u32> {
+        self.send_sysevt(trigger);
+        let n = try!(try!(reply.wait_and_count_timeout(Some(timeout)))
+            .map(|(n, _)| n)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "no reply from the firmware")));
+        let host: Host = reply.get_evtout().into();
+        self.clear_host_sysevts(host);
+        self.enable_host(host);
+
+        Ok(n)
+    }
+
+    // Clears the pending enabled system events mapped to a host interrupt.
+    fn clear_host_sysevts(&self, host: Host) {
+        unsafe {
+            let reg = |offset: isize| ptr::read_volatile(self.intc_reg.offset(offset));
+            let pending = [reg(SECR1_REG), reg(SECR2_REG)];
+            for sysevt in 0..NUM_SYSEVTS {
+                if pending[(sysevt >> 5) as usize] & (1 << (sysevt & 31)) == 0 {
+                    continue;
+                }
+                let cmr = reg(CMR_REG + (sysevt >> 2) as isize);
+                let channel = (cmr >> ((sysevt as u32 & 0b11) * 8)) & 0x0f;
+                let hmr = reg(HMR_REG + (channel >> 2) as isize);
+                if (hmr >> ((channel & 0b11) * 8)) & 0x0f == host as u32 {
+                    self.clear_sysevt(Sysevt::try_from(sysevt).unwrap());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,498 @@
+//! Loading of ELF executables generated by the TI PRU C compiler toolchain.
+
+use {MemSegment, PruLoader, PruCode, PruAddr, PruCore};
+use error::LoadError;
+use remoteproc::ResourceTable;
+use def::{DRAM0_OFFSET, DRAM0_SIZE, DRAM1_OFFSET, DRAM1_SIZE, DRAM2_OFFSET, DRAM2_SIZE};
+use volatile_copy_to;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::ptr;
+use std::result;
+use std::sync::atomic::{Ordering, compiler_fence};
+
+
+// ELF definitions.
+pub(crate) const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const ELF_HEADER_SIZE: usize = 52;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHF_EXECINSTR: u32 = 4;
+const SHN_UNDEF: u16 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+const SYM_SIZE: usize = 16;
+
+// Name of the resource table section of remoteproc firmwares.
+const RESOURCE_TABLE_SECTION: &'static str = ".resource_table";
+
+
+
+/// A loadable segment of an ELF executable.
+#[derive(Clone, Debug)]
+pub(crate) struct ElfSegment {
+    // Address of the segment in the instruction or data address space of the core.
+    pub(crate) address: u32,
+    // Initialized content of the segment.
+    pub(crate) data: Vec<u8>,
+    // Size of the segment in memory, including the zero-filled part.
+    pub(crate) mem_size: usize,
+    // Whether the segment belongs to the instruction memory.
+    pub(crate) executable: bool,
+}
+
+
+
+/// A symbol of an ELF executable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ElfSymbol {
+    /// Name of the symbol.
+    pub name: String,
+    /// Address of the symbol in the local address space of the core the firmware was linked
+    /// for, or in the instruction address space for code symbols.
+    pub address: u32,
+    /// Size of the object designated by the symbol (in bytes), if known.
+    pub size: u32,
+    /// Whether the symbol has global binding.
+    pub global: bool,
+    /// Whether the symbol belongs to an executable section.
+    pub executable: bool,
+}
+
+
+
+/// A PRU firmware in the ELF format.
+///
+/// This is the format of the `.out` executables produced by the TI `clpru` C compiler and
+/// linker. Executable segments such as `.text` are loaded to the instruction RAM, while other
+/// loadable segments such as `.data` or `.bss` are loaded to the data RAM at the addresses seen
+/// by the core, the uninitialized part of a segment being zero-filled.
+///
+/// The symbol table, if not stripped, can be used to locate the variables of the firmware so that
+/// they can be accessed from the host without hard-coding their addresses. Firmwares written for
+/// the remoteproc driver additionally describe the resources they need in a resource table.
+///
+/// # Example
+///
+/// ```no_run
+/// # use prusst::{Pruss, IntcConfig, ElfFirmware, PruCore};
+/// let mut pruss = Pruss::new(&IntcConfig::new_populated()).unwrap();
+/// let firmware = ElfFirmware::open("/lib/firmware/myapp-pru0.out").unwrap();
+/// let mut pru0 = pruss.pru0.load_elf(&firmware, &mut [&mut pruss.dram0, &mut pruss.dram2])
+///     .unwrap();
+/// let ctrl_block = firmware.symbol_addr(PruCore::Pru0, "ctrl_block").unwrap();
+/// let (_, mut ctrl_segment) = pruss.dram0.split_at(ctrl_block);
+/// unsafe { pru0.run(); }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ElfFirmware {
+    entry: u32,
+    segments: Vec<ElfSegment>,
+    symbols: Vec<ElfSymbol>,
+    resource_table: Option<ResourceTable>,
+}
+
+impl ElfFirmware {
+    /// Loads an ELF executable from a file.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the file is not a valid
+    /// 32-bit little-endian ELF executable. Other IO errors are forwarded.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<ElfFirmware> {
+        let mut image = Vec::new();
+        try!(try!(File::open(path)).read_to_end(&mut image));
+
+        ElfFirmware::parse(&image)
+    }
+
+    /// Parses an ELF executable.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the image is not a valid
+    /// 32-bit little-endian ELF executable or if its resource table is malformed.
+    pub fn parse(image: &[u8]) -> io::Result<ElfFirmware> {
+        if image.len() < ELF_HEADER_SIZE || !image.starts_with(&ELF_MAGIC) ||
+           image[4] != ELFCLASS32 || image[5] != ELFDATA2LSB {
+            return Err(invalid_elf());
+        }
+        let entry = try!(read_u32(image, 24));
+        let phoff = try!(read_u32(image, 28)) as usize;
+        let phentsize = try!(read_u16(image, 42)) as usize;
+        let phnum = try!(read_u16(image, 44)) as usize;
+
+        let mut segments = Vec::new();
+        for i in 0..phnum {
+            let ph = try!(end(phoff, i * phentsize));
+            let p_type = try!(read_u32(image, ph));
+            let p_offset = try!(read_u32(image, ph + 4)) as usize;
+            let p_paddr = try!(read_u32(image, ph + 12));
+            let p_filesz = try!(read_u32(image, ph + 16)) as usize;
+            let p_memsz = try!(read_u32(image, ph + 20)) as usize;
+            let p_flags = try!(read_u32(image, ph + 24));
+            if p_type != PT_LOAD || p_memsz == 0 {
+                continue;
+            }
+            if p_filesz > p_memsz {
+                return Err(invalid_elf());
+            }
+            let data = try!(slice(image, p_offset, p_filesz));
+            segments.push(ElfSegment {
+                address: p_paddr,
+                data: data.to_vec(),
+                mem_size: p_memsz,
+                executable: p_flags & PF_X != 0,
+            });
+        }
+
+        Ok(ElfFirmware {
+            entry: entry,
+            segments: segments,
+            symbols: try!(parse_symbols(image)),
+            resource_table: match try!(section_data(image, RESOURCE_TABLE_SECTION)) {
+                Some(table) => Some(try!(ResourceTable::parse(table))),
+                None => None,
+            },
+        })
+    }
+
+    /// Returns the entry point, i.e. the byte address in the instruction RAM at which execution
+    /// starts.
+    pub fn entry_point(&self) -> u32 {
+        self.entry
+    }
+
+    /// Returns the named symbols defined by the executable.
+    ///
+    /// The returned slice is empty if the executable was stripped.
+    pub fn symbols(&self) -> &[ElfSymbol] {
+        &self.symbols
+    }
+
+    /// Returns the symbol with the specified name, if any.
+    ///
+    /// Global symbols take precedence over local symbols with the same name.
+    pub fn symbol(&self, name: &str) -> Option<&ElfSymbol> {
+        let mut matching = self.symbols.iter().filter(|symbol| symbol.name == name);
+        let first = matching.next();
+
+        match first {
+            Some(symbol) if !symbol.global => matching.find(|symbol| symbol.global).or(first),
+            _ => first,
+        }
+    }
+
+    /// Returns the address of a data symbol in the local address space of PRU0, given the core
+    /// the firmware was linked for.
+    ///
+    /// `None` is returned if there is no such symbol or if it designates code.
+    pub fn symbol_addr(&self, core: PruCore, name: &str) -> Option<PruAddr> {
+        self.symbol(name)
+            .and_then(|symbol| if symbol.executable { None } else { Some(symbol) })
+            .map(|symbol| PruAddr::from_local(core, symbol.address))
+    }
+
+    /// Returns the resource table of a remoteproc firmware, if any.
+    pub fn resource_table(&self) -> Option<&ResourceTable> {
+        self.resource_table.as_ref()
+    }
+
+    pub(crate) fn segments(&self) -> &[ElfSegment] {
+        &self.segments
+    }
+}
+
+
+
+impl PruLoader {
+    /// Loads an ELF executable to the PRU without executing it.
+    ///
+    /// This function proceeds as follows:
+    ///
+    /// * a soft PRU reset is forced,
+    /// * the executable segments are written to the PRU instruction RAM,
+    /// * the other segments are written to the data RAM.
+    ///
+    /// Data segments are located using the local address space of the core, which means that a
+    /// firmware linked for PRU1 must be loaded with the PRU1 loader for its variables to end up in
+    /// the PRU1 data RAM.
+    ///
+    /// Data segments are written through the `data_rams` segments, each data segment being
+    /// required to lie within one of them. Borrowing the segments mutably ensures that no object
+    /// allocated in the data RAM is alive while it is overwritten.
+    ///
+    /// The code can be subsequently started and stopped using the returned `PruCode` handle,
+    /// which starts execution at the entry point of the executable.
+    ///
+    /// # Errors
+    ///
+    /// `LoadError::CodeTooLarge` is returned if an executable segment does not fit into the
+    /// instruction RAM and `LoadError::BadFormat` if a data segment does not fit into one of the
+    /// data RAMs or into one of the `data_rams` segments, or if the entry point is not a valid
+    /// instruction address. No memory is modified in such case.
+    pub fn load_elf(&mut self,
+                    firmware: &ElfFirmware,
+                    data_rams: &mut [&mut MemSegment])
+                    -> result::Result<PruCode<'_>, LoadError> {
+        // Index of the `data_rams` segment receiving each data segment.
+        let mut targets = Vec::new();
+        for segment in firmware.segments() {
+            if segment.executable {
+                let size = (segment.address as usize).saturating_add(segment.mem_size);
+                if size > self.iram_size {
+                    return Err(LoadError::CodeTooLarge {
+                        size: size,
+                        capacity: self.iram_size,
+                    });
+                }
+                continue;
+            }
+            if !fits_dram(segment.address as usize, segment.mem_size) {
+                return Err(LoadError::BadFormat(format!("data segment at {:#x} exceeding data \
+                                                         RAM bounds",
+                                                        segment.address)));
+            }
+            let PruAddr(offset) = PruAddr::from_local(self.core, segment.address);
+            match find_segment(data_rams, offset, segment.mem_size) {
+                Some(i) => targets.push(i),
+                None => {
+                    return Err(LoadError::BadFormat(format!("data segment at {:#x} outside of \
+                                                             the data RAM segments",
+                                                            segment.address)))
+                }
+            }
+        }
+        let entry = firmware.entry_point() as usize;
+        if !entry.is_multiple_of(4) || entry >= self.iram_size {
+            return Err(LoadError::BadFormat(format!("invalid entry point {:#x}", entry)));
+        }
+
+        // Invoke a soft reset of the PRU to make sure no code is currently running.
+        self.reset();
+        let mut targets = targets.into_iter();
+        for segment in firmware.segments() {
+            if segment.executable {
+                unsafe {
+                    let dst = self.iram_base.offset(segment.address as isize);
+                    volatile_copy_to(dst, &segment.data);
+                    for i in segment.data.len()..segment.mem_size {
+                        ptr::write_volatile(dst.offset(i as isize), 0);
+                    }
+                }
+            } else {
+                let dram = &mut data_rams[targets.next().unwrap()];
+                let PruAddr(offset) = PruAddr::from_local(self.core, segment.address);
+                dram.write_at(PruAddr(offset), &segment.data);
+                let zeros = vec![0u8; segment.mem_size - segment.data.len()];
+                dram.write_at(PruAddr(offset + segment.data.len()), &zeros);
+            }
+        }
+        // Make sure that memory writes are not reordered past the call to PruCode::run().
+        compiler_fence(Ordering::Release);
+        let words = firmware.segments()
+            .iter()
+            .filter(|segment| segment.executable)
+            .map(|segment| segment.mem_size.div_ceil(4))
+            .sum();
+
+        Ok(PruCode::new(self, (entry / 4) as u16, words))
+    }
+}
+
+
+impl<'a> PruCode<'a> {
+    /// Halts the PRU and overwrites the object designated by a symbol of the firmware.
+    ///
+    /// The symbol is looked up in the firmware the code was loaded from, and the value is
+    /// written to the instruction RAM for code symbols or to the data RAM for data symbols. This
+    /// allows constants such as calibration values to be patched without hard-coding their
+    /// addresses. The PRU remains halted and its execution can be resumed with `run`.
+    ///
+    /// Data symbols are patched through the `data_rams` segments, as for `PruLoader::load_elf`;
+    /// an empty slice can be provided when patching code symbols.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::NotFound` is returned if the symbol does not exist. An
+    /// error of the kind `ErrorKind::InvalidInput` is returned if the value is larger than the
+    /// object designated by the symbol, when its size is known, or if it would be written outside
+    /// the instruction RAM or the `data_rams` segments.
+    pub fn patch_symbol(&mut self,
+                        firmware: &ElfFirmware,
+                        name: &str,
+                        value: &[u8],
+                        data_rams: &mut [&mut MemSegment])
+                        -> io::Result<()> {
+        let symbol = try!(firmware.symbol(name).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no symbol `{}` in firmware", name))
+        }));
+        if symbol.size != 0 && value.len() > symbol.size as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("value larger than symbol `{}`", name)));
+        }
+        let address = symbol.address as usize;
+        let outside = || {
+            io::Error::new(io::ErrorKind::InvalidInput,
+                           format!("symbol `{}` outside of PRU memory", name))
+        };
+        if symbol.executable {
+            if address.checked_add(value.len()).is_none_or(|end| end > self.iram_size) {
+                return Err(outside());
+            }
+            self.halt();
+            unsafe {
+                volatile_copy_to(self.prumem_base.offset((self.iram_offset + address) as isize),
+                                 value);
+            }
+        } else {
+            if !fits_dram(address, value.len()) {
+                return Err(outside());
+            }
+            let PruAddr(offset) = PruAddr::from_local(self.core, symbol.address);
+            let i = try!(find_segment(data_rams, offset, value.len()).ok_or_else(outside));
+            self.halt();
+            data_rams[i].write_at(PruAddr(offset), value);
+        }
+        compiler_fence(Ordering::Release);
+
+        Ok(())
+    }
+}
+
+
+// Reads the named symbols of the symbol table, if any.
+fn parse_symbols(image: &[u8]) -> io::Result<Vec<ElfSymbol>> {
+    let shoff = try!(read_u32(image, 32)) as usize;
+    let shentsize = try!(read_u16(image, 46)) as usize;
+    let shnum = try!(read_u16(image, 48)) as usize;
+    let section = |index: usize, field: usize| {
+        read_u32(image, try!(end(shoff, index * shentsize + field)))
+    };
+
+    let mut symbols = Vec::new();
+    for i in 0..shnum {
+        if try!(section(i, 4)) != SHT_SYMTAB {
+            continue;
+        }
+        let offset = try!(section(i, 16)) as usize;
+        let size = try!(section(i, 20)) as usize;
+        let strtab = try!(section(i, 24)) as usize;
+        if strtab >= shnum {
+            return Err(invalid_elf());
+        }
+        let strtab_offset = try!(section(strtab, 16)) as usize;
+        let strtab_size = try!(section(strtab, 20)) as usize;
+        let strtab = try!(slice(image, strtab_offset, strtab_size));
+
+        for sym in (offset..try!(end(offset, size))).step_by(SYM_SIZE).skip(1) {
+            let name = try!(read_u32(image, sym)) as usize;
+            let info = try!(slice(image, try!(end(sym, 12)), 1))[0];
+            let shndx = try!(read_u16(image, sym + 14));
+            if name == 0 || shndx == SHN_UNDEF || info & 0xf == STT_SECTION ||
+               info & 0xf == STT_FILE {
+                continue;
+            }
+            let name = try!(strtab.get(name..)
+                .and_then(|tail| tail.split(|&c| c == 0).next())
+                .ok_or_else(invalid_elf));
+            let executable = (shndx as usize) < shnum &&
+                             try!(section(shndx as usize, 8)) & SHF_EXECINSTR != 0;
+            symbols.push(ElfSymbol {
+                name: String::from_utf8_lossy(name).into_owned(),
+                address: try!(read_u32(image, sym + 4)),
+                size: try!(read_u32(image, sym + 8)),
+                global: info >> 4 == STB_GLOBAL,
+                executable: executable,
+            });
+        }
+    }
+
+    Ok(symbols)
+}
+
+
+// Returns the content of the section with the specified name, if any.
+fn section_data<'i>(image: &'i [u8], name: &str) -> io::Result<Option<&'i [u8]>> {
+    let shoff = try!(read_u32(image, 32)) as usize;
+    let shentsize = try!(read_u16(image, 46)) as usize;
+    let shnum = try!(read_u16(image, 48)) as usize;
+    let shstrndx = try!(read_u16(image, 50)) as usize;
+    if shnum == 0 {
+        return Ok(None);
+    }
+    if shstrndx >= shnum {
+        return Err(invalid_elf());
+    }
+    let section = |index: usize, field: usize| {
+        read_u32(image, try!(end(shoff, index * shentsize + field)))
+    };
+    let names_offset = try!(section(shstrndx, 16)) as usize;
+
+    for i in 0..shnum {
+        let name_offset = try!(end(names_offset, try!(section(i, 0)) as usize));
+        let section_name = try!(image.get(name_offset..)
+            .and_then(|tail| tail.split(|&c| c == 0).next())
+            .ok_or_else(invalid_elf));
+        if section_name != name.as_bytes() || try!(section(i, 4)) == SHT_NOBITS {
+            continue;
+        }
+        let offset = try!(section(i, 16)) as usize;
+        let size = try!(section(i, 20)) as usize;
+
+        return slice(image, offset, size).map(Some);
+    }
+
+    Ok(None)
+}
+
+
+// Returns the index of the segment holding a range of data RAM offsets, if any.
+fn find_segment(segments: &[&mut MemSegment], offset: usize, size: usize) -> Option<usize> {
+    segments.iter().position(|segment| {
+        offset >= segment.from && offset <= segment.to && size <= segment.to - offset
+    })
+}
+
+
+// Checks whether a range of local addresses lies within a single data RAM.
+fn fits_dram(addr: usize, size: usize) -> bool {
+    [(DRAM0_OFFSET, DRAM0_SIZE), (DRAM1_OFFSET, DRAM1_SIZE), (DRAM2_OFFSET, DRAM2_SIZE)]
+        .iter()
+        .any(|&(offset, len)| addr >= offset && addr - offset <= len && size <= offset + len - addr)
+}
+
+
+fn invalid_elf() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed PRU firmware ELF file")
+}
+
+
+// Computes the end of a range of the image, rejecting ranges that overflow.
+fn end(offset: usize, size: usize) -> io::Result<usize> {
+    offset.checked_add(size).ok_or_else(invalid_elf)
+}
+
+
+fn slice(image: &[u8], offset: usize, size: usize) -> io::Result<&[u8]> {
+    image.get(offset..try!(end(offset, size))).ok_or_else(invalid_elf)
+}
+
+
+fn read_u16(image: &[u8], offset: usize) -> io::Result<u16> {
+    let b = try!(slice(image, offset, 2));
+    Ok(u16::from(b[0]) | u16::from(b[1]) << 8)
+}
+
+
+fn read_u32(image: &[u8], offset: usize) -> io::Result<u32> {
+    let b = try!(slice(image, offset, 4));
+    Ok(u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16 | u32::from(b[3]) << 24)
+}
@@ -1,4 +1,5 @@
 
+use std::any::Any;
 use std::error;
 use std::fmt;
 use std::io;
@@ -39,3 +40,295 @@ impl From<io::Error> for Error {
         }
     }
 }
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::AlreadyInstantiated => io::Error::other("PRU subsystem already instantiated"),
+            Error::PermissionDenied => io::Error::from(io::ErrorKind::PermissionDenied),
+            Error::DeviceNotFound => io::Error::from(io::ErrorKind::NotFound),
+            Error::OtherDeviceError => io::Error::other("PRU subsystem device error"),
+        }
+    }
+}
+
+
+
+/// PRU code loading error.
+///
+/// This error is returned by the loading methods of `PruLoader` so that an oversized or corrupted
+/// firmware can be told apart from a failing reader. It converts into an `io::Error` for callers
+/// that do not need to distinguish these cases.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The code does not fit into the instruction RAM.
+    CodeTooLarge {
+        /// Size of the code (in bytes).
+        size: usize,
+        /// Capacity of the instruction RAM (in bytes).
+        capacity: usize,
+    },
+    /// The data image does not fit into the data RAM.
+    DataTooLarge {
+        /// Size of the data image (in bytes).
+        size: usize,
+        /// Capacity of the data RAM (in bytes).
+        capacity: usize,
+    },
+    /// The CRC-32 checksum of the code does not match the expected value.
+    ChecksumMismatch {
+        /// Expected checksum.
+        expected: u32,
+        /// Checksum of the code.
+        actual: u32,
+    },
+    /// The content of the instruction RAM read back after loading differs from the code.
+    VerificationFailed {
+        /// Offset of the first mismatching byte.
+        offset: usize,
+    },
+    /// The firmware image is malformed or cannot be mapped onto the PRU memories, e.g. a data
+    /// segment lies outside the data RAMs or the entry point is not a valid instruction address.
+    BadFormat(String),
+    /// An IO error occurred while reading the code.
+    Io(io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::CodeTooLarge { size, capacity } => {
+                write!(f,
+                       "size of PRU code ({} bytes) exceeding instruction RAM capacity ({} bytes)",
+                       size,
+                       capacity)
+            }
+            LoadError::DataTooLarge { size, capacity } => {
+                write!(f,
+                       "size of PRU data image ({} bytes) exceeding data RAM capacity ({} bytes)",
+                       size,
+                       capacity)
+            }
+            LoadError::ChecksumMismatch { expected, actual } => {
+                write!(f,
+                       "PRU code checksum mismatch: expected {:#010x}, found {:#010x}",
+                       expected,
+                       actual)
+            }
+            LoadError::VerificationFailed { offset } => {
+                write!(f, "PRU instruction RAM content mismatch at offset {:#x}", offset)
+            }
+            LoadError::BadFormat(ref msg) => write!(f, "invalid PRU firmware: {}", msg),
+            LoadError::Io(ref err) => write!(f, "failed to read PRU code: {}", err),
+        }
+    }
+}
+
+impl error::Error for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::CodeTooLarge { .. } => "code too large",
+            LoadError::DataTooLarge { .. } => "data too large",
+            LoadError::ChecksumMismatch { .. } => "checksum mismatch",
+            LoadError::VerificationFailed { .. } => "verification failed",
+            LoadError::BadFormat(_) => "bad format",
+            LoadError::Io(_) => "IO error",
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+impl From<LoadError> for io::Error {
+    fn from(err: LoadError) -> io::Error {
+        match err {
+            LoadError::Io(err) => err,
+            err @ LoadError::ChecksumMismatch { .. } |
+            err @ LoadError::VerificationFailed { .. } |
+            err @ LoadError::BadFormat(_) => {
+                io::Error::new(io::ErrorKind::InvalidData, err)
+            }
+            err => io::Error::new(io::ErrorKind::InvalidInput, err),
+        }
+    }
+}
+
+
+
+/// Interrupt controller configuration error.
+///
+/// This error is returned by the fallible methods of `IntcConfig`, so that configurations
+/// assembled from user input or files can be validated without panicking. Each variant carries
+/// the number of the conflicting system event, channel or host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// A system event is listed several times, e.g. mapped to several channels.
+    DuplicateSysevt(u8),
+    /// A channel is mapped to several hosts.
+    DuplicateChannel(u8),
+    /// A host interrupt is enabled several times.
+    DuplicateHost(u8),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::DuplicateSysevt(sysevt) => {
+                write!(f, "system event {} configured several times", sysevt)
+            }
+            ConfigError::DuplicateChannel(channel) => {
+                write!(f, "channel {} mapped to several hosts", channel)
+            }
+            ConfigError::DuplicateHost(host) => {
+                write!(f, "host interrupt {} enabled several times", host)
+            }
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::DuplicateSysevt(_) => "duplicate system event",
+            ConfigError::DuplicateChannel(_) => "duplicate channel",
+            ConfigError::DuplicateHost(_) => "duplicate host interrupt",
+        }
+    }
+}
+
+impl From<ConfigError> for io::Error {
+    fn from(err: ConfigError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+
+
+/// Error returned when a number does not designate a valid system event, channel, host
+/// interrupt or event out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutOfRangeError {
+    what: &'static str,
+    value: u8,
+    count: u8,
+}
+
+impl OutOfRangeError {
+    pub(crate) fn new(what: &'static str, value: u8, count: u8) -> OutOfRangeError {
+        OutOfRangeError {
+            what: what,
+            value: value,
+            count: count,
+        }
+    }
+
+    /// The number that could not be converted.
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+}
+
+impl fmt::Display for OutOfRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "invalid {} number {}, expected 0 to {}",
+               self.what,
+               self.value,
+               self.count - 1)
+    }
+}
+
+impl error::Error for OutOfRangeError {
+    fn description(&self) -> &str {
+        "number out of range"
+    }
+}
+
+
+
+/// Event out wait error.
+///
+/// This error is returned by the fallible wait methods of `EvtoutIrq`, so that long-running
+/// applications can recover from a failing event out device, e.g. by re-registering the event out
+/// after the UIO module was reloaded.
+#[derive(Debug)]
+pub enum WaitError {
+    /// The interrupt could not be re-armed through the event out device.
+    Rearm(io::Error),
+    /// The event out device could not be polled or the interrupt count could not be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WaitError::Rearm(ref err) => write!(f, "failed to re-arm event out interrupt: {}", err),
+            WaitError::Io(ref err) => write!(f, "failed to wait for event out: {}", err),
+        }
+    }
+}
+
+impl error::Error for WaitError {
+    fn description(&self) -> &str {
+        match *self {
+            WaitError::Rearm(_) => "re-arm error",
+            WaitError::Io(_) => "IO error",
+        }
+    }
+}
+
+impl From<io::Error> for WaitError {
+    fn from(err: io::Error) -> WaitError {
+        WaitError::Io(err)
+    }
+}
+
+impl From<WaitError> for io::Error {
+    fn from(err: WaitError) -> io::Error {
+        match err {
+            WaitError::Rearm(err) | WaitError::Io(err) => err,
+        }
+    }
+}
+
+
+
+/// Event out dispatching error.
+///
+/// This error is returned when stopping an `IrqDispatcher` whose service thread has terminated
+/// prematurely.
+#[derive(Debug)]
+pub enum DispatchError {
+    /// A callback panicked; the panic payload is provided.
+    Panicked(Box<dyn Any + Send>),
+    /// An event out device failed.
+    Wait(WaitError),
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DispatchError::Panicked(_) => write!(f, "an event out callback panicked"),
+            DispatchError::Wait(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for DispatchError {
+    fn description(&self) -> &str {
+        match *self {
+            DispatchError::Panicked(_) => "callback panic",
+            DispatchError::Wait(_) => "wait error",
+        }
+    }
+}
+
+impl From<WaitError> for DispatchError {
+    fn from(err: WaitError) -> DispatchError {
+        DispatchError::Wait(err)
+    }
+}
@@ -0,0 +1,141 @@
+//! Event loop for PRU applications.
+//!
+//! Most applications wait for event outs in a loop and, each time an event out is triggered,
+//! clear the triggering system event, re-enable the host interrupt and handle the event. Getting
+//! the order right is subtle: if the host interrupt is re-enabled after the event is handled,
+//! an event signaled by the PRU in the meantime is lost. An `EventLoop` runs this cycle on the
+//! calling thread with the correct ordering, i.e. the system event is cleared and the host
+//! interrupt re-enabled before the handler is invoked.
+//!
+//! Unlike an `IrqDispatcher`, the handlers need not be `Send` or `'static` and can therefore
+//! borrow the state of the application:
+//!
+//! ```no_run
+//! # use prusst::{Pruss, IntcConfig, Evtout, Sysevt};
+//! # use prusst::event_loop::EventLoop;
+//! # use std::cell::Cell;
+//! # use std::time::Duration;
+//! # let pruss = Pruss::new(&IntcConfig::new_populated()).unwrap();
+//! let count = Cell::new(0);
+//! let mut event_loop = EventLoop::new(&pruss.intc);
+//! event_loop.on_evtout(Evtout::E0, Sysevt::S19, |_, n| count.set(count.get() + n)).unwrap();
+//! event_loop.set_timeout(Duration::from_secs(1), |_| println!("the PRU is idle"));
+//! event_loop.shutdown_when(|| count.get() >= 100);
+//! event_loop.run().unwrap();
+//! ```
+
+use {Evtout, Intc, Sysevt, WaitError};
+use irq_set::{IrqSet, Wakeup};
+use wait;
+
+use std::io;
+use std::result;
+use std::time::Duration;
+
+
+// Handler invoked with the number of interrupts since the previous invocation.
+type Handler<'a> = Box<dyn FnMut(&Intc, u32) + 'a>;
+
+// Handler invoked when no event out was triggered before the timeout expired.
+type TimeoutHandler<'a> = Box<dyn FnMut(&Intc) + 'a>;
+
+
+
+/// Set of event out handlers run on the calling thread.
+pub struct EventLoop<'a> {
+    intc: &'a Intc,
+    handlers: IrqSet<Handler<'a>>,
+    timeout: Option<(Duration, TimeoutHandler<'a>)>,
+    shutdown: Option<Box<dyn FnMut() -> bool + 'a>>,
+}
+
+impl<'a> EventLoop<'a> {
+    /// Creates an event loop without handlers.
+    pub fn new(intc: &'a Intc) -> EventLoop<'a> {
+        EventLoop {
+            intc: intc,
+            handlers: IrqSet::new(),
+            timeout: None,
+            shutdown: None,
+        }
+    }
+
+    /// Registers a handler for an event out.
+    ///
+    /// Each time the event out is triggered, the system event is cleared and the host interrupt
+    /// of the event out is re-enabled, after which the handler is invoked with the interrupt
+    /// controller and the number of interrupts signaled since the previous invocation.
+    ///
+    /// The event out is registered immediately, so this function should be called before the
+    /// event out can be triggered.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::AlreadyExists` is returned if a handler is already
+    /// registered for the event out. IO errors that may occur while opening the event out device
+    /// are forwarded.
+    pub fn on_evtout<T, F>(&mut self, e: Evtout, sysevt: T, handler: F) -> io::Result<()>
+        where T: Into<Sysevt>,
+              F: FnMut(&Intc, u32) + 'a
+    {
+        self.handlers.insert(self.intc, e, sysevt.into(), Box::new(handler))
+    }
+
+    /// Sets a handler invoked each time no event out is triggered for the duration of the
+    /// timeout.
+    ///
+    /// The timeout is restarted after each event out, so the handler is invoked periodically
+    /// while the PRU is idle. This replaces any previously set timeout.
+    pub fn set_timeout<F>(&mut self, timeout: Duration, handler: F)
+        where F: FnMut(&Intc) + 'a
+    {
+        self.timeout = Some((timeout, Box::new(handler)));
+    }
+
+    /// Sets the condition upon which the event loop terminates.
+    ///
+    /// The condition is evaluated before each wait, i.e. once before the first wait and then
+    /// after the handlers were invoked, after the timeout handler was invoked and after the wait
+    /// was interrupted by a signal. A timeout should be set if the condition may become true
+    /// while the loop is waiting, e.g. because it is set by another thread. This replaces any
+    /// previously set condition.
+    pub fn shutdown_when<F>(&mut self, condition: F)
+        where F: FnMut() -> bool + 'a
+    {
+        self.shutdown = Some(Box::new(condition));
+    }
+
+    /// Runs the event loop until the shutdown condition is met.
+    ///
+    /// The event outs are waited for with a blocking `poll` irrespective of their wait policy.
+    /// When several event outs are triggered at once, their handlers are invoked in
+    /// registration order. The event out devices are released when the function returns, so
+    /// interrupts signaled after the last wait are left pending in the interrupt controller.
+    ///
+    /// Without a shutdown condition, the loop only returns on error.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if an interrupt cannot be re-armed or if an event out device fails.
+    pub fn run(mut self) -> result::Result<(), WaitError> {
+        loop {
+            if let Some(ref mut shutdown) = self.shutdown {
+                if shutdown() {
+                    return Ok(());
+                }
+            }
+            let ms = match self.timeout {
+                Some((timeout, _)) => wait::poll_timeout_ms(timeout),
+                None => -1,
+            };
+            let wakeup = try!(self.handlers.wait(self.intc, None, ms, |handler, intc, n| {
+                handler(intc, n)
+            }));
+            if wakeup == Wakeup::Timeout {
+                if let Some((_, ref mut handler)) = self.timeout {
+                    handler(self.intc);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Loading of PRU firmware images by name.
+
+use digest::Digest;
+use elf::{ElfFirmware, ELF_MAGIC};
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+
+/// Environment variable holding a colon-separated list of directories searched for firmware
+/// images before the standard firmware directories.
+pub static FIRMWARE_PATH_VAR: &'static str = "PRUSST_FIRMWARE_PATH";
+
+/// Standard firmware directories, in search order.
+pub static FIRMWARE_DIRS: [&'static str; 2] = ["/lib/firmware", "/usr/lib/firmware"];
+
+// File extensions tried in order when resolving a firmware name.
+static FIRMWARE_EXTENSIONS: [&'static str; 4] = ["", ".bin", ".out", ".elf"];
+
+
+/// A PRU firmware image.
+///
+/// The image is either a raw binary of opcodes or an ELF executable, in which case the
+/// executable segment is extracted so that it can be written to the PRU instruction RAM.
+///
+/// # Example
+///
+/// ```no_run
+/// # use prusst::{Pruss, IntcConfig, Firmware};
+/// let mut pruss = Pruss::new(&IntcConfig::new_populated()).unwrap();
+/// let firmware = Firmware::load("myapp-pru0").unwrap();
+/// let mut pru0 = pruss.pru0.load_code(&mut firmware.code()).unwrap();
+/// unsafe { pru0.run(); }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Firmware {
+    path: PathBuf,
+    image: Vec<u8>,
+    code: Vec<u8>,
+}
+
+impl Firmware {
+    /// Loads a firmware image by name.
+    ///
+    /// The image is searched in the directories listed in the `PRUSST_FIRMWARE_PATH`
+    /// environment variable, if any, and then in the standard firmware directories. Within each
+    /// directory, the name is tried as is and then with the `.bin`, `.out` and `.elf`
+    /// extensions.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::NotFound` is returned if the image could not be found.
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the image is a malformed
+    /// ELF file or an ELF file without executable segment. Other IO errors are forwarded.
+    pub fn load(name: &str) -> io::Result<Firmware> {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        if let Some(paths) = env::var_os(FIRMWARE_PATH_VAR) {
+            dirs.extend(env::split_paths(&paths));
+        }
+        dirs.extend(FIRMWARE_DIRS.iter().map(PathBuf::from));
+
+        Firmware::load_from(name, &dirs)
+    }
+
+    /// Loads a firmware image by name from the specified directories.
+    ///
+    /// Directories are searched in order, trying the name as is and then with the `.bin`,
+    /// `.out` and `.elf` extensions.
+    ///
+    /// # Errors
+    ///
+    /// See `Firmware::load`.
+    pub fn load_from<P: AsRef<Path>>(name: &str, dirs: &[P]) -> io::Result<Firmware> {
+        for dir in dirs {
+            for ext in FIRMWARE_EXTENSIONS.iter() {
+                let path = dir.as_ref().join(format!("{}{}", name, ext));
+                if path.is_file() {
+                    return Firmware::open(path);
+                }
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::NotFound,
+                           format!("PRU firmware `{}` not found", name)))
+    }
+
+    /// Loads a firmware image from a file.
+    ///
+    /// # Errors
+    ///
+    /// See `Firmware::load`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Firmware> {
+        let mut image = Vec::new();
+        try!(try!(File::open(path.as_ref())).read_to_end(&mut image));
+        let code = if image.starts_with(&ELF_MAGIC) {
+            try!(elf_code(&image))
+        } else {
+            image.clone()
+        };
+
+        Ok(Firmware {
+            path: path.as_ref().to_path_buf(),
+            image: image,
+            code: code,
+        })
+    }
+
+    /// Returns the path of the firmware image.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the content of the firmware file.
+    pub fn image(&self) -> &[u8] {
+        &self.image
+    }
+
+    /// Verifies the content of the firmware file against a digest.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the digest does not match.
+    pub fn verify(&self, digest: &Digest) -> io::Result<()> {
+        let actual = digest.compute(&self.image);
+        if actual != *digest {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("digest mismatch for PRU firmware {}: \
+                                               expected {}, found {}",
+                                              self.path.display(),
+                                              digest,
+                                              actual)));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the binary of opcodes.
+    ///
+    /// The returned slice implements `Read` when taken by mutable reference and can thus be
+    /// passed directly to `PruLoader::load_code`.
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+}
+
+
+// Extracts the executable segment of an ELF file.
+//
+// The segment is placed at its physical address within the returned image so that the code can
+// be loaded as is at the beginning of the instruction RAM.
+fn elf_code(image: &[u8]) -> io::Result<Vec<u8>> {
+    let elf = try!(ElfFirmware::parse(image));
+    match elf.segments().iter().find(|segment| segment.executable) {
+        Some(segment) => {
+            let mut code = vec![0u8; segment.address as usize];
+            code.extend_from_slice(&segment.data);
+            Ok(code)
+        }
+        None => {
+            Err(io::Error::new(io::ErrorKind::InvalidData,
+                               "no executable segment in PRU firmware"))
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! GPIO line management through the GPIO character device.
+//!
+//! PRU applications frequently depend on GPIO lines driven by the host (enables, resets,
+//! multiplexer selects...). This module makes it possible to claim and configure such lines
+//! through the Linux GPIO character device API so that pin ownership can be managed alongside
+//! the PRU subsystem: lines remain claimed for as long as the returned `GpioLines` handle is
+//! alive and are released when it is dropped.
+
+use libc;
+
+use std::cmp;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+
+// GPIO character device ABI (version 1).
+const GPIOHANDLES_MAX: usize = 64;
+const GPIOHANDLE_REQUEST_INPUT: u32 = 1 << 0;
+const GPIOHANDLE_REQUEST_OUTPUT: u32 = 1 << 1;
+const GPIO_GET_LINEHANDLE_IOCTL: u32 = 0xc16cb403;
+const GPIOHANDLE_GET_LINE_VALUES_IOCTL: u32 = 0xc040b408;
+const GPIOHANDLE_SET_LINE_VALUES_IOCTL: u32 = 0xc040b409;
+
+#[repr(C)]
+struct GpioHandleRequest {
+    line_offsets: [u32; GPIOHANDLES_MAX],
+    flags: u32,
+    default_values: [u8; GPIOHANDLES_MAX],
+    consumer_label: [u8; 32],
+    lines: u32,
+    fd: libc::c_int,
+}
+
+#[repr(C)]
+struct GpioHandleData {
+    values: [u8; GPIOHANDLES_MAX],
+}
+
+
+/// A GPIO chip.
+pub struct GpioChip {
+    file: File,
+}
+
+impl GpioChip {
+    /// Opens a GPIO character device such as `/dev/gpiochip0`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<GpioChip> {
+        let file = try!(OpenOptions::new().read(true).write(true).open(path));
+
+        Ok(GpioChip { file: file })
+    }
+
+    /// Claims lines of the chip as outputs with the specified initial values.
+    ///
+    /// The consumer label identifies the owner of the lines, e.g. in the output of `gpioinfo`.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidInput` is returned if the number of values does not
+    /// match the number of lines or if more than 64 lines are requested. OS errors, in particular
+    /// if a line is already claimed, are forwarded.
+    pub fn request_outputs(&self, offsets: &[u32], values: &[bool], consumer: &str)
+                           -> io::Result<GpioLines> {
+        if values.len() != offsets.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "number of values not matching the number of lines"));
+        }
+        self.request(offsets, GPIOHANDLE_REQUEST_OUTPUT, values, consumer)
+    }
+
+    /// Claims lines of the chip as inputs.
+    ///
+    /// The consumer label identifies the owner of the lines, e.g. in the output of `gpioinfo`.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidInput` is returned if more than 64 lines are
+    /// requested. OS errors, in particular if a line is already claimed, are forwarded.
+    pub fn request_inputs(&self, offsets: &[u32], consumer: &str) -> io::Result<GpioLines> {
+        self.request(offsets, GPIOHANDLE_REQUEST_INPUT, &[], consumer)
+    }
+
+    fn request(&self, offsets: &[u32], flags: u32, values: &[bool], consumer: &str)
+               -> io::Result<GpioLines> {
+        if offsets.is_empty() || offsets.len() > GPIOHANDLES_MAX {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid number of lines"));
+        }
+
+        let mut request: GpioHandleRequest = unsafe { mem::zeroed() };
+        request.line_offsets[..offsets.len()].copy_from_slice(offsets);
+        request.flags = flags;
+        for (default, &value) in request.default_values.iter_mut().zip(values) {
+            *default = value as u8;
+        }
+        let label = consumer.as_bytes();
+        let label_len = cmp::min(label.len(), request.consumer_label.len() - 1);
+        request.consumer_label[..label_len].copy_from_slice(&label[..label_len]);
+        request.lines = offsets.len() as u32;
+
+        let fd = self.file.as_raw_fd();
+        if unsafe { libc::ioctl(fd, GPIO_GET_LINEHANDLE_IOCTL as _, &mut request) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(GpioLines {
+            fd: request.fd,
+            count: offsets.len(),
+        })
+    }
+}
+
+
+/// A set of claimed GPIO lines.
+///
+/// The lines are released when the handle is dropped.
+pub struct GpioLines {
+    fd: libc::c_int,
+    count: usize,
+}
+
+impl GpioLines {
+    /// Number of lines.
+    pub fn num_lines(&self) -> usize {
+        self.count
+    }
+
+    /// Reads the values of the lines.
+    pub fn get(&self) -> io::Result<Vec<bool>> {
+        let mut data = GpioHandleData { values: [0; GPIOHANDLES_MAX] };
+        if unsafe { libc::ioctl(self.fd, GPIOHANDLE_GET_LINE_VALUES_IOCTL as _, &mut data) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(data.values[..self.count].iter().map(|&v| v != 0).collect())
+    }
+
+    /// Sets the values of output lines.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidInput` is returned if the number of values does not
+    /// match the number of lines. OS errors are forwarded.
+    pub fn set(&self, values: &[bool]) -> io::Result<()> {
+        if values.len() != self.count {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "number of values not matching the number of lines"));
+        }
+        let mut data = GpioHandleData { values: [0; GPIOHANDLES_MAX] };
+        for (v, &value) in data.values.iter_mut().zip(values) {
+            *v = value as u8;
+        }
+        if unsafe { libc::ioctl(self.fd, GPIOHANDLE_SET_LINE_VALUES_IOCTL as _, &mut data) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for GpioLines {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
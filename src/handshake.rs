@@ -0,0 +1,271 @@
+//! Host/firmware handshake.
+//!
+//! To guard against silent protocol drift between the host application and the PRU firmware,
+//! the firmware may advertise its protocol version and capabilities by writing a
+//! `FirmwareHeader` at the beginning of its local data RAM as soon as it starts. The magic number
+//! should be written last so that the host never observes a partially written header.
+//!
+//! The handshake can also be made bidirectional so that the firmware may check the host as well:
+//! with `PruCode::handshake`, the host writes a `HostHeader` right after the firmware header
+//! before starting the PRU, and the firmware reads it before publishing its own header.
+//!
+//! The expected version and capabilities are described by a `HandshakeConfig`. The turn-taking
+//! handshake used to exchange data once the firmware runs is provided by `semaphore::Handshake`.
+
+use {PruCode, MemSegment, SegmentAddr};
+use wait::{self, SpinPolicy};
+
+use std::error;
+use std::fmt;
+use std::ptr;
+use std::result;
+use std::time::Duration;
+
+
+/// Magic number identifying a firmware header (`"PRUH"` in little-endian order).
+pub const FIRMWARE_MAGIC: u32 = 0x48555250;
+
+/// Magic number identifying a host header (`"HOST"` in little-endian order).
+pub const HOST_MAGIC: u32 = 0x54534f48;
+
+
+
+/// Header placed by the firmware at offset 0 of its local data RAM.
+///
+/// Its layout is that of the following C structure:
+///
+/// ```c
+/// struct firmware_header {
+///     uint32_t magic;
+///     uint32_t version;
+///     uint32_t capabilities;
+/// };
+/// ```
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FirmwareHeader {
+    /// Magic number, which must be equal to `FIRMWARE_MAGIC`.
+    pub magic: u32,
+    /// Protocol version.
+    pub version: u32,
+    /// Bit mask of the capabilities supported by the firmware.
+    pub capabilities: u32,
+}
+
+
+
+/// Header written by the host right after the firmware header, i.e. at offset 12 of the local
+/// data RAM of the firmware.
+///
+/// Its layout is that of the following C structure:
+///
+/// ```c
+/// struct host_header {
+///     uint32_t magic;
+///     uint32_t version;
+/// };
+/// ```
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HostHeader {
+    /// Magic number, which is equal to `HOST_MAGIC`.
+    pub magic: u32,
+    /// Protocol version of the host.
+    pub version: u32,
+}
+
+
+
+// Headers of a bidirectional handshake.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Headers {
+    firmware: FirmwareHeader,
+    host: HostHeader,
+}
+
+
+
+/// Handshake expected from the firmware.
+#[derive(Copy, Clone, Debug)]
+pub struct HandshakeConfig {
+    /// Protocol version expected by the host.
+    pub version: u32,
+    /// Bit mask of the capabilities required by the host.
+    pub capabilities: u32,
+    /// Maximum time allowed for the firmware to publish its header.
+    pub timeout: Duration,
+}
+
+
+
+/// Handshake error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// No header was published by the firmware within the allotted time.
+    Timeout,
+    /// The firmware published a header with an unexpected magic number.
+    BadMagic(u32),
+    /// The protocol version of the firmware differs from the expected version.
+    VersionMismatch { expected: u32, found: u32 },
+    /// The firmware lacks some of the required capabilities.
+    MissingCapabilities { required: u32, found: u32 },
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HandshakeError::Timeout => write!(f, "firmware handshake timed out"),
+            HandshakeError::BadMagic(magic) => {
+                write!(f, "invalid firmware header magic number {:#010x}", magic)
+            }
+            HandshakeError::VersionMismatch { expected, found } => {
+                write!(f, "firmware protocol version {} found, {} expected", found, expected)
+            }
+            HandshakeError::MissingCapabilities { required, found } => {
+                write!(f, "firmware capabilities {:#x} found, {:#x} required", found, required)
+            }
+        }
+    }
+}
+
+impl error::Error for HandshakeError {
+    fn description(&self) -> &str {
+        match *self {
+            HandshakeError::Timeout => "handshake timeout",
+            HandshakeError::BadMagic(_) => "bad magic number",
+            HandshakeError::VersionMismatch { .. } => "version mismatch",
+            HandshakeError::MissingCapabilities { .. } => "missing capabilities",
+        }
+    }
+}
+
+
+
+impl<'a> PruCode<'a> {
+    /// Executes the code loaded in the PRU and verifies the firmware handshake.
+    ///
+    /// The header area at the beginning of the data RAM segment is first cleared, the code is
+    /// started and the function then waits for the firmware to publish its header. The segment
+    /// is expected to start at offset 0 of the local data RAM of the PRU, i.e. `dram0` for PRU0
+    /// and `dram1` for PRU1.
+    ///
+    /// The PRU is left running when the handshake fails, so it is up to the caller to halt it.
+    ///
+    /// # Errors
+    ///
+    /// A `HandshakeError` is returned if no valid header is published before the timeout
+    /// expires or if the header does not match the expected version and capabilities.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the beginning of the segment is not properly aligned for
+    /// a `FirmwareHeader` or if the segment is too small to contain it.
+    ///
+    /// # Safety
+    ///
+    /// See `PruCode::run`.
+    pub unsafe fn run_with_handshake<A>(&mut self,
+                                        dram: &mut MemSegment<A>,
+                                        handshake: &HandshakeConfig)
+                                        -> result::Result<FirmwareHeader, HandshakeError>
+        where A: SegmentAddr
+    {
+        let header: *mut FirmwareHeader = dram.alloc_uninitialized();
+        ptr::write_volatile(&mut (*header).magic, 0);
+        self.run();
+
+        wait_header(header, handshake)
+    }
+
+    /// Announces the host protocol version, executes the code loaded in the PRU and verifies
+    /// the firmware handshake.
+    ///
+    /// This function behaves like `run_with_handshake`, except that a `HostHeader` carrying
+    /// `HOST_MAGIC` and the version expected in `handshake` is written right after the firmware
+    /// header before the code is started. The firmware may thus check the host version and
+    /// refuse to operate, in which case it should publish its header with the version it
+    /// supports so that the mismatch is reported on the host side as well.
+    ///
+    /// The PRU is left running when the handshake fails, so it is up to the caller to halt it.
+    ///
+    /// # Errors
+    ///
+    /// A `HandshakeError` is returned if no valid header is published before the timeout
+    /// expires or if the header does not match the expected version and capabilities.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the beginning of the segment is not properly aligned for
+    /// a `FirmwareHeader` or if the segment is too small to contain both headers.
+    ///
+    /// # Safety
+    ///
+    /// See `PruCode::run`.
+    pub unsafe fn handshake<A>(&mut self,
+                               dram: &mut MemSegment<A>,
+                               handshake: &HandshakeConfig)
+                               -> result::Result<FirmwareHeader, HandshakeError>
+        where A: SegmentAddr
+    {
+        let headers: *mut Headers = dram.alloc_uninitialized();
+        ptr::write_volatile(&mut (*headers).firmware.magic, 0);
+        ptr::write_volatile(&mut (*headers).host,
+                            HostHeader {
+                                magic: HOST_MAGIC,
+                                version: handshake.version,
+                            });
+        self.run();
+
+        wait_header(&mut (*headers).firmware, handshake)
+    }
+}
+
+
+// Waits for the firmware to publish its header and verifies it.
+unsafe fn wait_header(header: *mut FirmwareHeader,
+                      handshake: &HandshakeConfig)
+                      -> result::Result<FirmwareHeader, HandshakeError> {
+    let magic = || ptr::read_volatile(&(*header).magic);
+    let policy = SpinPolicy {
+        budget: Some(handshake.timeout),
+        pause: true,
+    };
+    if !wait::spin_until(&policy, || magic() != 0) {
+        return Err(HandshakeError::Timeout);
+    }
+    if magic() != FIRMWARE_MAGIC {
+        return Err(HandshakeError::BadMagic(magic()));
+    }
+
+    let header = ptr::read_volatile(header);
+    verify(&header, handshake).map(|_| header)
+}
+
+
+/// Verifies that a firmware header matches the expected handshake.
+///
+/// # Errors
+///
+/// See `PruCode::run_with_handshake`; `HandshakeError::Timeout` is never returned.
+pub fn verify(header: &FirmwareHeader,
+              handshake: &HandshakeConfig)
+              -> result::Result<(), HandshakeError> {
+    if header.magic != FIRMWARE_MAGIC {
+        return Err(HandshakeError::BadMagic(header.magic));
+    }
+    if header.version != handshake.version {
+        return Err(HandshakeError::VersionMismatch {
+            expected: handshake.version,
+            found: header.version,
+        });
+    }
+    if header.capabilities & handshake.capabilities != handshake.capabilities {
+        return Err(HandshakeError::MissingCapabilities {
+            required: handshake.capabilities,
+            found: header.capabilities,
+        });
+    }
+
+    Ok(())
+}
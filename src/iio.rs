@@ -0,0 +1,120 @@
+//! Bridging between PRU memory and Industrial I/O (IIO) buffers.
+//!
+//! This module makes it possible to integrate PRU acquisition or generation with the Linux IIO
+//! subsystem: scan data read from the buffer of an IIO input device can be copied to PRU memory,
+//! and samples produced by the PRU can be pushed to the buffer of an IIO output device.
+//!
+//! The device must be configured through its sysfs attributes (enabled scan elements, buffer
+//! length, trigger) before its buffer is enabled; `IioDevice` provides helpers for the most common
+//! settings.
+
+use {MemSegment, SegmentAddr};
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+
+// System paths
+const IIO_DEVICE_ROOT_PATH: &'static str = "/dev/iio:device";
+const IIO_SYSFS_ROOT_PATH: &'static str = "/sys/bus/iio/devices/iio:device";
+
+
+/// An IIO device.
+pub struct IioDevice {
+    index: u32,
+    sysfs: PathBuf,
+}
+
+impl IioDevice {
+    /// Creates a handle to the IIO device with the specified index.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::NotFound` is returned if the device does not exist.
+    pub fn new(index: u32) -> io::Result<IioDevice> {
+        let sysfs = PathBuf::from(format!("{}{}", IIO_SYSFS_ROOT_PATH, index));
+        if !sysfs.exists() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "IIO device not found"));
+        }
+
+        Ok(IioDevice {
+            index: index,
+            sysfs: sysfs,
+        })
+    }
+
+    /// Enables or disables a scan element, e.g. `in_voltage0`.
+    pub fn enable_scan_element(&self, name: &str, enable: bool) -> io::Result<()> {
+        self.write_attr(&format!("scan_elements/{}_en", name), if enable { "1" } else { "0" })
+    }
+
+    /// Sets the trigger of the device, e.g. `trigger0`.
+    pub fn set_trigger(&self, trigger: &str) -> io::Result<()> {
+        self.write_attr("trigger/current_trigger", trigger)
+    }
+
+    /// Sets the length of the buffer (in scans).
+    pub fn set_buffer_length(&self, length: usize) -> io::Result<()> {
+        self.write_attr("buffer/length", &length.to_string())
+    }
+
+    /// Enables or disables the buffer.
+    pub fn enable_buffer(&self, enable: bool) -> io::Result<()> {
+        self.write_attr("buffer/enable", if enable { "1" } else { "0" })
+    }
+
+    /// Opens the buffer of the device.
+    pub fn open_buffer(&self) -> io::Result<IioBuffer> {
+        let file = try!(OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!("{}{}", IIO_DEVICE_ROOT_PATH, self.index)));
+
+        Ok(IioBuffer { file: file })
+    }
+
+    fn write_attr(&self, attr: &str, value: &str) -> io::Result<()> {
+        let mut file = try!(OpenOptions::new().write(true).open(self.sysfs.join(attr)));
+        file.write_all(value.as_bytes())
+    }
+}
+
+
+/// The buffer of an IIO device.
+pub struct IioBuffer {
+    file: File,
+}
+
+impl IioBuffer {
+    /// Reads scan data from the buffer into a memory segment.
+    ///
+    /// The data is copied at the beginning of the segment. A single read is performed, which
+    /// blocks until scan data is available, so that the number of bytes copied may be lower than
+    /// the segment size.
+    pub fn read_into<A: SegmentAddr>(&mut self, segment: &mut MemSegment<A>) -> io::Result<usize> {
+        let begin = segment.begin();
+        let mut buffer = vec![0u8; segment.end().into() - begin.into()];
+        let n = try!(self.file.read(&mut buffer));
+        let (_, mut view) = segment.split_at(begin);
+        try!(view.write_all(&buffer[..n]));
+
+        Ok(n)
+    }
+
+    /// Writes the content of a memory segment to the buffer.
+    ///
+    /// This is meant for IIO output devices. A single write is performed so that the number of
+    /// bytes copied may be lower than the segment size.
+    pub fn write_from<A: SegmentAddr>(&mut self, segment: &mut MemSegment<A>)
+                                      -> io::Result<usize> {
+        let begin = segment.begin();
+        let mut buffer = vec![0u8; segment.end().into() - begin.into()];
+        {
+            let (_, mut view) = segment.split_at(begin);
+            try!(view.read_exact(&mut buffer));
+        }
+
+        self.file.write(&buffer)
+    }
+}
@@ -0,0 +1,140 @@
+//! Event outs waited for together by the event loop and the dispatcher.
+
+use {Evtout, EvtoutIrq, Intc, Sysevt, WaitError};
+
+use libc;
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::result;
+
+
+struct Entry<H> {
+    irq: EvtoutIrq,
+    sysevt: Sysevt,
+    handler: H,
+    armed: bool,
+}
+
+
+
+/// Cause of the return of `IrqSet::wait`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Wakeup {
+    /// At least one event out was triggered and its handler invoked.
+    Triggered,
+    /// No event out was triggered before the timeout expired.
+    Timeout,
+    /// The wait was interrupted by a signal.
+    Interrupted,
+    /// The stop descriptor became readable.
+    Stopped,
+}
+
+
+
+/// Set of event outs, each with a handler.
+pub struct IrqSet<H> {
+    entries: Vec<Entry<H>>,
+    pollfds: Vec<libc::pollfd>,
+}
+
+impl<H> IrqSet<H> {
+    /// Creates an empty set.
+    pub fn new() -> IrqSet<H> {
+        IrqSet {
+            entries: Vec::new(),
+            pollfds: Vec::new(),
+        }
+    }
+
+    /// Registers the event out and adds it to the set.
+    ///
+    /// An error of the kind `ErrorKind::AlreadyExists` is returned if the event out is already in
+    /// the set.
+    pub fn insert(&mut self,
+                  intc: &Intc,
+                  e: Evtout,
+                  sysevt: Sysevt,
+                  handler: H)
+                  -> io::Result<()> {
+        if self.entries.iter().any(|entry| entry.irq.get_evtout() == e) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      "a handler is already registered for this event out"));
+        }
+        self.entries.push(Entry {
+            irq: try!(intc.try_register_irq(e)),
+            sysevt: sysevt,
+            handler: handler,
+            armed: false,
+        });
+
+        Ok(())
+    }
+
+    /// Waits for the event outs and invokes the handlers of those that were triggered.
+    ///
+    /// The interrupts are re-armed before the wait. For each triggered event out, the system
+    /// event is cleared and the host interrupt re-enabled before the handler is invoked with the
+    /// number of interrupts since the previous invocation. Handlers are invoked in insertion
+    /// order. The stop descriptor, if any, takes precedence over the event outs.
+    pub fn wait<F>(&mut self,
+                   intc: &Intc,
+                   stop: Option<RawFd>,
+                   timeout_ms: libc::c_int,
+                   mut invoke: F)
+                   -> result::Result<Wakeup, WaitError>
+        where F: FnMut(&mut H, &Intc, u32)
+    {
+        for entry in &mut self.entries {
+            if !entry.armed {
+                try!(entry.irq.begin_wait().map_err(WaitError::Rearm));
+                entry.armed = true;
+            }
+        }
+        if self.pollfds.len() != self.entries.len() + stop.iter().count() {
+            self.pollfds = self.entries
+                .iter()
+                .map(|entry| entry.irq.as_raw_fd())
+                .chain(stop)
+                .map(|fd| {
+                    libc::pollfd {
+                        fd: fd,
+                        events: libc::POLLIN,
+                        revents: 0,
+                    }
+                })
+                .collect();
+        }
+        let ready = unsafe {
+            libc::poll(self.pollfds.as_mut_ptr(),
+                       self.pollfds.len() as libc::nfds_t,
+                       timeout_ms)
+        };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(Wakeup::Interrupted);
+            }
+            return Err(WaitError::Io(err));
+        }
+        if ready == 0 {
+            return Ok(Wakeup::Timeout);
+        }
+        if stop.is_some() && self.pollfds[self.entries.len()].revents != 0 {
+            return Ok(Wakeup::Stopped);
+        }
+        for (entry, pollfd) in self.entries.iter_mut().zip(&self.pollfds) {
+            // Errors reported by `poll` are surfaced by the read.
+            if pollfd.revents == 0 {
+                continue;
+            }
+            let (n, _) = try!(entry.irq.read_count());
+            entry.armed = false;
+            intc.ack(entry.sysevt, entry.irq.get_evtout());
+            invoke(&mut entry.handler, intc, n);
+        }
+
+        Ok(Wakeup::Triggered)
+    }
+}
@@ -59,7 +59,7 @@
 //!     unsafe { pruss.pru0.load_code(&mut file).unwrap().run(); }
 //!     
 //!     // Wait for the PRU code from hello.bin to trigger an event out.
-//!     irq.wait();
+//!     irq.try_wait().unwrap();
 //!     
 //!     // Clear the triggering interrupt.
 //!     pruss.intc.clear_sysevt(Sysevt::S19);
@@ -70,26 +70,101 @@
 //! ```
 
 extern crate libc;
-
+#[cfg(feature = "bytemuck")]
+extern crate bytemuck;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(feature = "manifest")]
+extern crate serde;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "manifest")]
+extern crate toml;
+#[cfg(feature = "zerocopy")]
+extern crate zerocopy;
+
+#[macro_use]
+mod macros;
+mod addr;
+#[cfg(feature = "async")]
+pub mod async_irq;
+pub mod clock;
+mod ctrl;
+mod cursor;
+#[cfg(feature = "daemon")]
+pub mod daemon;
 mod def;
+mod devtree;
+pub mod digest;
+pub mod dispatch;
+pub mod dmabuf;
+pub mod doorbell;
+mod elf;
 mod error;
+pub mod event_loop;
+mod firmware;
+#[cfg(feature = "gpio")]
+pub mod gpio;
+pub mod handshake;
+#[cfg(feature = "iio")]
+pub mod iio;
+mod irq_set;
+pub mod linker;
+pub mod lock;
+pub mod logger;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+mod paths;
+pub mod perf;
+pub mod pps;
 mod pubdef;
+pub mod prelude;
+pub mod remoteproc;
+pub mod rpc;
+#[cfg(feature = "rt")]
+pub mod rt;
+pub mod semaphore;
+pub mod snapshot;
+pub mod status;
+pub mod stream;
+pub mod timesync;
 pub mod util;
+mod wait;
 
 use def::*;
-pub use error::Error;
+pub use addr::{SegmentAddr, PruAddr, IramAddr, HostOffset, DmaBufOffset};
+pub use ctrl::PruCtrl;
+pub use cursor::SegmentCursor;
+pub use elf::{ElfFirmware, ElfSymbol};
+pub use error::{ConfigError, DispatchError, Error, LoadError, OutOfRangeError, WaitError};
+pub use firmware::Firmware;
+pub use paths::DevicePaths;
 pub use pubdef::*;
+pub use wait::{SpinPolicy, WaitPolicy, IrqControl};
+use perf::{EventCounters, EventCounts, LatencyRecorder, LatencyStats};
+use util::VolatileCell;
 
 use std::cmp::Eq;
+use std::convert::TryFrom;
 use std::ffi::CString;
-use std::fs::File;
-use std::io::{self, Read};
+use std::fs::{File, OpenOptions};
+use std::cmp;
+use std::io::{self, Read, Write};
 use std::marker::PhantomData;
-use std::mem;
+use std::mem::{self, MaybeUninit};
 use std::ops::{BitOrAssign, Shl};
 use std::ptr;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::path::Path;
 use std::result;
+#[cfg(feature = "zerocopy")]
+use std::slice;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "async")]
+use std::sync::OnceLock;
 use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT, compiler_fence};
+use std::time::{Duration, Instant};
 
 
 
@@ -105,8 +180,9 @@ pub type Result<T> = result::Result<T, Error>;
 
 /// Main interface to the PRU subsystem.
 pub struct Pruss<'a> {
-    _prumap: MemMap,
+    _prumap: Arc<MemMap>,
     _hostmap: MemMap,
+    hostmem_phys_addr: usize,
 
     /// PRU interrupt controller
     pub intc: Intc,
@@ -121,45 +197,304 @@ pub struct Pruss<'a> {
     /// Shared data RAM
     pub dram2: MemSegment<'a>,
     /// Host memory
-    pub hostram: MemSegment<'a>,
+    pub hostram: MemSegment<'a, HostOffset>,
 }
 
 impl<'a> Pruss<'a> {
     /// Creates a PRU subsystem context, mapping all necessary PRU registers and memory.
     ///
     /// The interrupt controller is initialized with the provided mapping.
+    ///
+    /// This is equivalent to `PrussBuilder::new().build(intc_config)`.
     pub fn new(intc_config: &IntcConfig) -> Result<Pruss<'a>> {
+        PrussBuilder::new().build(intc_config)
+    }
+
+    /// Returns the base address of the mapping of the PRU memory and registers.
+    ///
+    /// This is meant for specialized code that needs to hand raw addresses over to third-party
+    /// libraries or to build DMA descriptors; the memory segment and register views of `Pruss`
+    /// should be used otherwise.
+    ///
+    /// # Safety
+    ///
+    /// The returned pointer bypasses the borrow checking applied to memory segments and to the
+    /// interrupt controller and PRU control registers. The caller must ensure that the memory it
+    /// accesses through this pointer is not concurrently borrowed via a `MemSegment` allocation,
+    /// that registers are not modified behind the back of `Intc`, `PruLoader` and `PruCode`, and
+    /// that the pointer is not used after the `Pruss` instance is dropped.
+    pub unsafe fn prumem_base(&self) -> *mut u8 {
+        self._prumap.base
+    }
+
+    /// Size of the mapping of the PRU memory and registers (in bytes).
+    pub fn prumem_size(&self) -> usize {
+        self._prumap.size
+    }
+
+    /// Returns the base address of the mapping of the host memory.
+    ///
+    /// # Safety
+    ///
+    /// The returned pointer bypasses the borrow checking applied to `hostram`. The caller must
+    /// ensure that the memory it accesses through this pointer is not concurrently borrowed via a
+    /// `MemSegment` allocation and that the pointer is not used after the `Pruss` instance is
+    /// dropped.
+    pub unsafe fn hostmem_base(&self) -> *mut u8 {
+        self._hostmap.base
+    }
+
+    /// Size of the mapping of the host memory (in bytes).
+    pub fn hostmem_size(&self) -> usize {
+        self._hostmap.size
+    }
+
+    /// Loads binaries of opcodes to both PRUs without executing them.
+    ///
+    /// Both codes are read until the end of their buffer and validated before either PRU is
+    /// reset, so that either both codes are loaded or the PRUs are left untouched.
+    ///
+    /// As for `load_and_run_both`, the loaders must be provided explicitly, e.g.
+    /// `Pruss::load_both(&mut pruss.pru0, &mut pruss.pru1, ...)`.
+    ///
+    /// # Errors
+    ///
+    /// The errors are the same as for `PruLoader::load_code`. No code is loaded if an error
+    /// occurs.
+    pub fn load_both<'b, R0, R1>(pru0: &'b mut PruLoader,
+                                 pru1: &'b mut PruLoader,
+                                 code0: &mut R0,
+                                 code1: &mut R1)
+                                 -> result::Result<(PruCode<'b>, PruCode<'b>), LoadError>
+        where R0: Read,
+              R1: Read
+    {
+        let mut image0 = Vec::new();
+        try!(code0.read_to_end(&mut image0));
+        let mut image1 = Vec::new();
+        try!(code1.read_to_end(&mut image1));
+        for &(image, loader) in &[(&image0, &*pru0), (&image1, &*pru1)] {
+            if image.len() > loader.iram_size {
+                return Err(LoadError::CodeTooLarge {
+                    size: image.len(),
+                    capacity: loader.iram_size,
+                });
+            }
+        }
+
+        Ok((try!(pru0.load_code_from_slice(&image0)), try!(pru1.load_code_from_slice(&image1))))
+    }
+
+    /// Loads binaries of opcodes to both PRUs and starts them back-to-back.
+    ///
+    /// Both codes are first written to the instruction RAMs, then the two PRUs are started with
+    /// consecutive writes to their control registers so as to minimize the skew between their
+    /// start times.
+    ///
+    /// Tighter synchronization can be achieved with a start flag located in PRU memory: if a
+    /// start flag is provided, it is reset to 0 before the PRUs are started and set to 1 once both
+    /// are running, so that firmwares which spin on the flag until it becomes non-zero are
+    /// released simultaneously.
+    ///
+    /// Since the loaders are fields of `Pruss`, they must be provided explicitly, e.g.
+    /// `Pruss::load_and_run_both(&mut pruss.pru0, &mut pruss.pru1, ...)`; this leaves the other
+    /// fields of `Pruss` available while the codes are running.
+    ///
+    /// # Errors
+    ///
+    /// The errors are the same as for `Pruss::load_both`. No code is loaded or started if an
+    /// error occurs.
+    ///
+    /// # Safety
+    ///
+    /// This runs binary codes that have unrestricted access to pretty much all the processor
+    /// memory and peripherals.
+    pub unsafe fn load_and_run_both<'b, R0, R1>(pru0: &'b mut PruLoader,
+                                                pru1: &'b mut PruLoader,
+                                                code0: &mut R0,
+                                                code1: &mut R1,
+                                                start_flag: Option<&mut VolatileCell<u32>>)
+                                                -> result::Result<(PruCode<'b>, PruCode<'b>),
+                                                                  LoadError>
+        where R0: Read,
+              R1: Read
+    {
+        let (mut code0, mut code1) = try!(Pruss::load_both(pru0, pru1, code0, code1));
+        match start_flag {
+            Some(flag) => {
+                flag.set(0);
+                code0.run();
+                code1.run();
+                flag.set(1);
+            }
+            None => {
+                code0.run();
+                code1.run();
+            }
+        }
+
+        Ok((code0, code1))
+    }
+
+    /// Physical address of the host memory.
+    pub fn hostmem_phys_addr(&self) -> usize {
+        self.hostmem_phys_addr
+    }
+
+    /// Returns the physical address at which a PRU core sees the given host memory location.
+    ///
+    /// Host memory is accessed by the PRU through the L3 interconnect, so this is the address
+    /// the PRU firmware must use to read or write host memory data.
+    pub fn host_phys_addr(&self, offset: HostOffset) -> u32 {
+        (self.hostmem_phys_addr + offset.0) as u32
+    }
+}
+
+impl<'a> Drop for Pruss<'a> {
+    fn drop(&mut self) {
+        // Stop instruction executions in both PRUs
+        self.pru0.reset();
+        self.pru1.reset();
+
+        // Allow another PRU subsystem context to be instantiated.
+        PRUSS_IS_INSTANTIATED.store(false, Ordering::Release);
+    }
+}
+
+unsafe impl<'a> Send for Pruss<'a> {}
+
+unsafe impl<'a> Sync for Pruss<'a> {}
+
+
+
+/// Builder for PRU subsystem contexts.
+///
+/// The builder makes it possible to create a `Pruss` instance with non-default options.
+#[derive(Clone, Debug, Default)]
+pub struct PrussBuilder {
+    populate: bool,
+    lock: bool,
+    irq_control: IrqControl,
+    paths: DevicePaths,
+}
+
+impl PrussBuilder {
+    /// Creates a builder with default options.
+    pub fn new() -> PrussBuilder {
+        PrussBuilder {
+            populate: false,
+            lock: false,
+            irq_control: IrqControl::Off,
+            paths: DevicePaths::default(),
+        }
+    }
+
+    /// Pre-faults the PRU and host memory maps upon creation.
+    ///
+    /// This uses the `MAP_POPULATE` mapping flag so that the first access to a page in a
+    /// time-critical path does not incur a page fault. Host-side staging buffers are not
+    /// covered: `DmaBuf` buffers are always pre-faulted and the buffers of a `DataLogger` can be
+    /// pre-faulted with `DataLogger::locked`.
+    pub fn populate(mut self, populate: bool) -> PrussBuilder {
+        self.populate = populate;
+        self
+    }
+
+    /// Locks the PRU and host memory maps in RAM.
+    ///
+    /// This uses `mlock` to make sure that the mapped pages are resident and remain so. Note that
+    /// locking memory may require elevated privileges or a sufficient `RLIMIT_MEMLOCK` limit.
+    /// Host-side staging buffers are not covered and can be locked with `DmaBuf::lock` and
+    /// `DataLogger::locked`.
+    pub fn lock(mut self, lock: bool) -> PrussBuilder {
+        self.lock = lock;
+        self
+    }
+
+    /// Sets the interrupt re-arming semantics of the UIO driver.
+    ///
+    /// This setting applies to all event out synchronization primitives returned by
+    /// `Intc::register_irq`. It should be left to `IrqControl::Off` for the `uio_pruss` driver.
+    pub fn irq_control(mut self, irq_control: IrqControl) -> PrussBuilder {
+        self.irq_control = irq_control;
+        self
+    }
+
+    /// Sets the location of the UIO device files and sysfs attributes.
+    ///
+    /// The default paths are those of the `uio_pruss` driver.
+    pub fn paths(mut self, paths: DevicePaths) -> PrussBuilder {
+        self.paths = paths;
+        self
+    }
+
+    /// Creates a PRU subsystem context, mapping all necessary PRU registers and memory.
+    ///
+    /// The interrupt controller is initialized with the provided mapping.
+    pub fn build<'a>(&self, intc_config: &IntcConfig) -> Result<Pruss<'a>> {
+        self.build_compiled(&intc_config.compile())
+    }
+
+    /// Creates a PRU subsystem context, mapping all necessary PRU registers and memory.
+    ///
+    /// The interrupt controller is initialized with the provided precompiled mapping.
+    pub fn build_compiled<'a>(&self, intc_config: &CompiledIntcConfig) -> Result<Pruss<'a>> {
         // Enforce singleton instantiation.
         if PRUSS_IS_INSTANTIATED.swap(true, Ordering::Acquire) {
             return Err(Error::AlreadyInstantiated);
         }
 
-        // Handy function to read the size of system devices.
-        fn memsize(path: &str) -> io::Result<usize> {
+        // Allow another instantiation attempt if this one fails.
+        let pruss = self.map_devices(intc_config);
+        if pruss.is_err() {
+            PRUSS_IS_INSTANTIATED.store(false, Ordering::Release);
+        }
+
+        pruss
+    }
+
+    fn map_devices<'a>(&self, intc_config: &CompiledIntcConfig) -> Result<Pruss<'a>> {
+        // Handy function to read the size and physical address of system devices.
+        fn memsize(path: &Path) -> io::Result<usize> {
             let mut f = try!(File::open(path));
             let mut buffer = String::new();
             try!(f.read_to_string(&mut buffer));
-            Ok(usize::from_str_radix(&buffer[2..].trim(), 16).unwrap())
-        };
+            let buffer = buffer.trim();
+            let digits = buffer.strip_prefix("0x").unwrap_or(buffer);
+            usize::from_str_radix(digits, 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid map attribute"))
+        }
 
         // Create memory mapped devices.
-        let file = try!(SyncFile::new(PRUSS_DEVICE_PATH));
-        let prumem_size = try!(memsize(UIO_PRUMEM_SIZE_PATH));
-        let hostmem_size = try!(memsize(UIO_HOSTMEM_SIZE_PATH));
-        let prumap = try!(MemMap::new(file.fd, prumem_size, 0));
-        let hostmap = try!(MemMap::new(file.fd, hostmem_size, 1));
+        let file = try!(SyncFile::new(&self.paths.device));
+        let prumem_size = try!(memsize(&self.paths.map_attr(0, "size")));
+        let hostmem_size = try!(memsize(&self.paths.map_attr(1, "size")));
+        let hostmem_phys_addr = try!(memsize(&self.paths.map_attr(1, "addr")));
+        let prumap = Arc::new(try!(MemMap::new(file.fd, prumem_size, 0, self.populate)));
+        let hostmap = try!(MemMap::new(file.fd, hostmem_size, 1, self.populate));
+        if self.lock {
+            try!(prumap.lock());
+            try!(hostmap.lock());
+        }
 
         // Create and initialize the interrupt controller.
-        let mut intc = Intc::new(unsafe { prumap.base.offset(INTC_OFFSET as isize) as *mut u32 });
-        intc.map_interrupts(intc_config);
+        let mut intc = Intc::new(prumap.clone(),
+                                 unsafe { prumap.base.offset(INTC_OFFSET as isize) as *mut u32 },
+                                 self.irq_control,
+                                 self.paths.evtout_prefix.clone());
+        intc.map_compiled_interrupts(intc_config);
 
         // Create the PRU code loaders.
         let pru0 =
-            PruLoader::new(unsafe { prumap.base.offset(PRU0CTRL_OFFSET as isize) as *mut u32 },
+            PruLoader::new(PruCore::Pru0,
+                           prumap.base,
+                           unsafe { prumap.base.offset(PRU0CTRL_OFFSET as isize) as *mut u32 },
                            unsafe { prumap.base.offset(IRAM0_OFFSET as isize) },
                            IRAM0_SIZE);
         let pru1 =
-            PruLoader::new(unsafe { prumap.base.offset(PRU1CTRL_OFFSET as isize) as *mut u32 },
+            PruLoader::new(PruCore::Pru1,
+                           prumap.base,
+                           unsafe { prumap.base.offset(PRU1CTRL_OFFSET as isize) as *mut u32 },
                            unsafe { prumap.base.offset(IRAM1_OFFSET as isize) },
                            IRAM1_SIZE);
 
@@ -173,6 +508,7 @@ impl<'a> Pruss<'a> {
         Ok(Pruss {
             _prumap: prumap,
             _hostmap: hostmap,
+            hostmem_phys_addr: hostmem_phys_addr,
             intc: intc,
             pru0: pru0,
             pru1: pru1,
@@ -184,95 +520,128 @@ impl<'a> Pruss<'a> {
     }
 }
 
-impl<'a> Drop for Pruss<'a> {
-    fn drop(&mut self) {
-        // Stop instruction executions in both PRUs
-        self.pru0.reset();
-        self.pru1.reset();
-
-        // Allow another PRU subsystem context to be instantiated.
-        PRUSS_IS_INSTANTIATED.store(false, Ordering::Release);
-    }
-}
-
-unsafe impl<'a> Send for Pruss<'a> {}
-
-unsafe impl<'a> Sync for Pruss<'a> {}
-
 
 
 /// The PRU interrupt controller.
 pub struct Intc {
     intc_reg: *mut u32,
+    prumap: Arc<MemMap>,
+    irq_control: IrqControl,
+    evtout_prefix: String,
+    event_counters: Arc<EventCounters>,
 }
 
 impl Intc {
     /// Creates a driver context with sane interrupt intc mapping defaults.
-    fn new(intc_reg: *mut u32) -> Self {
-        let intc = Intc { intc_reg: intc_reg };
+    fn new(prumap: Arc<MemMap>,
+           intc_reg: *mut u32,
+           irq_control: IrqControl,
+           evtout_prefix: String)
+           -> Self {
+        let intc = Intc {
+            intc_reg: intc_reg,
+            prumap: prumap,
+            irq_control: irq_control,
+            evtout_prefix: evtout_prefix,
+            event_counters: Arc::new(EventCounters::new()),
+        };
 
         intc
     }
 
     /// Maps PRU interrupts according to the provided configuration.
+    ///
+    /// Use `remap` to change the mapping while the PRU may be signaling events.
     pub fn map_interrupts(&mut self, interrupts: &IntcConfig) {
+        self.map_compiled_interrupts(&interrupts.compile());
+    }
+
+    /// Maps PRU interrupts according to the provided precompiled configuration.
+    pub fn map_compiled_interrupts(&mut self, image: &CompiledIntcConfig) {
         unsafe {
-            // Set the polarity of system interrupts to high.
-            ptr::write_volatile(self.intc_reg.offset(SIPR1_REG), 0xffffffff);
-            ptr::write_volatile(self.intc_reg.offset(SIPR2_REG), 0xffffffff);
+            // Set the polarity of system interrupts, high unless configured otherwise.
+            ptr::write_volatile(self.intc_reg.offset(SIPR1_REG), image.sipr[0]);
+            ptr::write_volatile(self.intc_reg.offset(SIPR2_REG), image.sipr[1]);
 
-            // Clear all channel map registers and assign system events to channels.
-            for cmrx in 0..NUM_CMRX {
-                ptr::write_volatile(self.intc_reg.offset(CMR_REG + cmrx), 0);
-            }
-            for m in &interrupts.sysevt_to_channel_map {
-                let cmrx = (m.sysevt >> 2) as isize;
-                debug_assert!(cmrx < NUM_CMRX);
-                let val = ptr::read_volatile(self.intc_reg.offset(CMR_REG + cmrx));
-                ptr::write_volatile(self.intc_reg.offset(CMR_REG + cmrx),
-                                    val | (m.channel as u32) << ((m.sysevt as u32 & 0b11) * 8));
+            // Assign system events to channels.
+            for (cmrx, &val) in image.cmr.iter().enumerate() {
+                ptr::write_volatile(self.intc_reg.offset(CMR_REG + cmrx as isize), val);
             }
 
-            // Clear all host map registers and assign channels to hosts.
-            for hmrx in 0..NUM_HMRX {
-                ptr::write_volatile(self.intc_reg.offset(HMR_REG + hmrx), 0);
-            }
-            for m in &interrupts.channel_to_host_map {
-                let hmrx = (m.channel >> 2) as isize;
-                debug_assert!(hmrx < NUM_HMRX);
-                let val = ptr::read_volatile(self.intc_reg.offset(HMR_REG + hmrx));
-                ptr::write_volatile(self.intc_reg.offset(HMR_REG + hmrx),
-                                    val | (m.host as u32) << ((m.channel as u32 & 0b11) * 8));
+            // Assign channels to hosts.
+            for (hmrx, &val) in image.hmr.iter().enumerate() {
+                ptr::write_volatile(self.intc_reg.offset(HMR_REG + hmrx as isize), val);
             }
 
-            // Set the type of system interrupts to pulse.
-            ptr::write_volatile(self.intc_reg.offset(SITR1_REG), 0x0);
-            ptr::write_volatile(self.intc_reg.offset(SITR2_REG), 0x0);
+            // Set the type of system interrupts, pulse unless configured otherwise.
+            ptr::write_volatile(self.intc_reg.offset(SITR1_REG), image.sitr[0]);
+            ptr::write_volatile(self.intc_reg.offset(SITR2_REG), image.sitr[1]);
 
             // Enable and clear system events.
-            let (mut mask1, mut mask2) = (0u32, 0u32);
-            for se in &interrupts.sysevt_enable {
-                match *se {
-                    0...31 => mask1 |= 1u32 << se,
-                    32...63 => mask2 |= 1u32 << (se - 32),
-                    _ => unreachable!(),
-                };
-            }
-            ptr::write_volatile(self.intc_reg.offset(ESR1_REG), mask1);
-            ptr::write_volatile(self.intc_reg.offset(SECR1_REG), mask1);
-            ptr::write_volatile(self.intc_reg.offset(ESR2_REG), mask2);
-            ptr::write_volatile(self.intc_reg.offset(SECR2_REG), mask2);
+            ptr::write_volatile(self.intc_reg.offset(ESR1_REG), image.esr[0]);
+            ptr::write_volatile(self.intc_reg.offset(SECR1_REG), image.esr[0]);
+            ptr::write_volatile(self.intc_reg.offset(ESR2_REG), image.esr[1]);
+            ptr::write_volatile(self.intc_reg.offset(SECR2_REG), image.esr[1]);
 
             // Enable host interrupts.
-            for h in &interrupts.host_enable {
+            for h in &image.host_enable {
                 ptr::write_volatile(self.intc_reg.offset(HIEISR_REG), *h as u32);
             }
             ptr::write_volatile(self.intc_reg.offset(GER_REG), 0x1);
         }
     }
-    
+
+    /// Safely changes the mapping of PRU interrupts on a live system.
+    ///
+    /// Unlike `map_interrupts`, which only adds to the current configuration, this globally
+    /// disables host interrupts, disables and clears all system events and host interrupts, and
+    /// only then applies the new configuration and re-enables host interrupts. This makes it
+    /// possible to change the mapping between firmware phases without spuriously delivering or
+    /// dropping in-flight events on the new mapping.
+    ///
+    /// Events signaled while the configuration is changed are discarded.
+    pub fn remap(&mut self, interrupts: &IntcConfig) {
+        let image = interrupts.compile();
+        self.global_disable();
+        unsafe {
+            // Disable and clear all system events and disable all host interrupts.
+            ptr::write_volatile(self.intc_reg.offset(ECR1_REG), 0xffffffff);
+            ptr::write_volatile(self.intc_reg.offset(ECR2_REG), 0xffffffff);
+            ptr::write_volatile(self.intc_reg.offset(SECR1_REG), 0xffffffff);
+            ptr::write_volatile(self.intc_reg.offset(SECR2_REG), 0xffffffff);
+            ptr::write_volatile(self.intc_reg.offset(HIER_REG), 0x0);
+        }
+        self.map_compiled_interrupts(&image);
+    }
+
+
+    /// Globally disables host interrupts.
+    ///
+    /// No host interrupt is signaled until `global_enable` is called, which makes it possible to
+    /// briefly gate all PRU interrupts around critical sections, e.g. while re-staging shared
+    /// buffers. System events are still latched in the meantime, so enabled events raised while
+    /// host interrupts are disabled are signaled once they are re-enabled.
+    pub fn global_disable(&self) {
+        unsafe {
+            ptr::write_volatile(self.intc_reg.offset(GER_REG), 0x0);
+        }
+    }
+
+    /// Globally enables host interrupts.
+    pub fn global_enable(&self) {
+        unsafe {
+            ptr::write_volatile(self.intc_reg.offset(GER_REG), 0x1);
+        }
+    }
+
+    /// Checks whether host interrupts are globally enabled.
+    pub fn is_globally_enabled(&self) -> bool {
+        unsafe { ptr::read_volatile(self.intc_reg.offset(GER_REG)) & 0x1 != 0 }
+    }
+
     /// Triggers a system event.
-    pub fn send_sysevt(&self, sysevt: Sysevt) {
+    pub fn send_sysevt<T: Into<Sysevt>>(&self, sysevt: T) {
+        let sysevt: Sysevt = sysevt.into();
         unsafe {
             match sysevt as u8 {
                 se @ 0...31 => ptr::write_volatile(self.intc_reg.offset(SRSR1_REG),
@@ -285,26 +654,78 @@ impl Intc {
     }
 
     /// Clears a system event.
-    pub fn clear_sysevt(&self, sysevt: Sysevt) {
+    pub fn clear_sysevt<T: Into<Sysevt>>(&self, sysevt: T) {
+        let sysevt: Sysevt = sysevt.into();
         unsafe {
             ptr::write_volatile(self.intc_reg.offset(SICR_REG), sysevt as u32);
         }
+        let (low, high) = sysevt_masks(&[sysevt]);
+        self.event_counters.record(low, high);
+    }
+
+    /// Clears several system events at once.
+    ///
+    /// The system events are cleared with at most one write to each of the SECR registers, which
+    /// is cheaper than clearing them one by one with `clear_sysevt`.
+    pub fn clear_sysevts(&self, sysevts: &[Sysevt]) {
+        self.write_sysevt_masks(sysevts, SECR1_REG, SECR2_REG);
+        let (low, high) = sysevt_masks(sysevts);
+        self.event_counters.record(low, high);
+    }
+
+    /// Starts counting how many times each system event is cleared, resetting the counts.
+    ///
+    /// The counts are shared with the interrupt controller handles used by the other functions
+    /// of this crate, such as an `IrqDispatcher`. Counting is disabled by default.
+    pub fn enable_event_counts(&self) {
+        self.event_counters.set_enabled(true);
+    }
+
+    /// Stops counting system events.
+    pub fn disable_event_counts(&self) {
+        self.event_counters.set_enabled(false);
+    }
+
+    /// Returns how many times each system event was cleared since counting was enabled, or
+    /// `None` if counting is disabled.
+    pub fn event_counts(&self) -> Option<EventCounts> {
+        self.event_counters.counts()
     }
 
     /// Enables a system event.
-    pub fn enable_sysevt(&self, sysevt: Sysevt) {
+    pub fn enable_sysevt<T: Into<Sysevt>>(&self, sysevt: T) {
+        let sysevt: Sysevt = sysevt.into();
         unsafe {
             ptr::write_volatile(self.intc_reg.offset(EISR_REG), sysevt as u32 );
         }
     }
 
     /// Disables a system event.
-    pub fn disable_sysevt(&self, sysevt: Sysevt) {
+    pub fn disable_sysevt<T: Into<Sysevt>>(&self, sysevt: T) {
+        let sysevt: Sysevt = sysevt.into();
         unsafe {
             ptr::write_volatile(self.intc_reg.offset(EICR_REG), sysevt as u32 );
         }
     }
 
+    /// Enables several system events at once.
+    ///
+    /// The system events are enabled with at most one write to each of the ESR registers, so
+    /// that all system events numbered 0 to 31 on the one hand and 32 to 63 on the other hand
+    /// are enabled simultaneously.
+    pub fn enable_sysevts(&self, sysevts: &[Sysevt]) {
+        self.write_sysevt_masks(sysevts, ESR1_REG, ESR2_REG);
+    }
+
+    /// Disables several system events at once.
+    ///
+    /// The system events are disabled with at most one write to each of the ECR registers, so
+    /// that all system events numbered 0 to 31 on the one hand and 32 to 63 on the other hand
+    /// are disabled simultaneously.
+    pub fn disable_sysevts(&self, sysevts: &[Sysevt]) {
+        self.write_sysevt_masks(sysevts, ECR1_REG, ECR2_REG);
+    }
+
     /// Enables or re-enables a host interrupt.
     ///
     /// Beware: calling this function before the triggering system event was cleared will trigger
@@ -316,6 +737,32 @@ impl Intc {
         }
     }
 
+    /// Acknowledges a system event by clearing it and re-enabling the host interrupt, in this
+    /// order.
+    ///
+    /// This is what an event out handler must do after servicing the event. The two register
+    /// writes are not atomic: should the system event be raised again in between, the host
+    /// interrupt is signaled again as soon as it is re-enabled, so the new event is not lost.
+    /// Use `ack_checked` to find out whether this occurred.
+    pub fn ack<S: Into<Sysevt>, H: Into<Host>>(&self, sysevt: S, host: H) {
+        self.clear_sysevt(sysevt);
+        self.enable_host(host);
+    }
+
+    /// Acknowledges a system event like `ack`, checking whether the system event was raised
+    /// again before the host interrupt was re-enabled.
+    ///
+    /// Returns `false` if the system event was raised again after being cleared, in which case
+    /// the host interrupt will be signaled again immediately.
+    pub fn ack_checked<S: Into<Sysevt>, H: Into<Host>>(&self, sysevt: S, host: H) -> bool {
+        let sysevt: Sysevt = sysevt.into();
+        self.clear_sysevt(sysevt);
+        let raised = self.is_pending(sysevt);
+        self.enable_host(host);
+
+        !raised
+    }
+
     /// Disables a host interrupt.
     pub fn disable_host<T: Into<Host>>(&self, host: T) {
         let host: Host = host.into();
@@ -324,6 +771,197 @@ impl Intc {
         }
     }
 
+    /// Busy-waits until a system event is pending.
+    ///
+    /// This function spins on the raw status of the system event, irrespective of whether it is
+    /// enabled or mapped to a host interrupt. It returns `true` if the system event became
+    /// pending or `false` if the spin budget of the policy was exhausted first.
+    ///
+    /// The system event is not cleared: `clear_sysevt` should be called once the event has been
+    /// serviced. Note also that if the system event is mapped to an enabled event out, the
+    /// corresponding host interrupt is still raised and will be seen by a subsequent
+    /// `EvtoutIrq::try_wait`.
+    pub fn spin_wait_sysevt<T: Into<Sysevt>>(&self, sysevt: T, policy: &SpinPolicy) -> bool {
+        let sysevt: Sysevt = sysevt.into();
+        wait::spin_until(policy, || self.is_pending(sysevt))
+    }
+
+    /// Checks whether a system event is pending.
+    ///
+    /// This reads the raw (unmasked) status of the system event from the SRSR registers,
+    /// irrespective of whether it is enabled or mapped to a host interrupt, so that the host can
+    /// busy-poll for PRU events without the system call overhead of `EvtoutIrq::try_wait`. The
+    /// system event is not cleared.
+    pub fn is_pending<T: Into<Sysevt>>(&self, sysevt: T) -> bool {
+        let sysevt: Sysevt = sysevt.into();
+        unsafe {
+            match sysevt as u8 {
+                se @ 0...31 => ptr::read_volatile(self.intc_reg.offset(SRSR1_REG))
+                               & (1u32 << se) != 0,
+                se @ 32...63 => ptr::read_volatile(self.intc_reg.offset(SRSR2_REG))
+                                & (1u32 << (se - 32)) != 0,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Returns the highest-priority pending system event of a host interrupt.
+    ///
+    /// This reads the HIPIR register of the host, which identifies the system event which
+    /// caused the host interrupt when several system events are mapped to the same host. `None`
+    /// is returned when no enabled system event mapped to the host is pending.
+    pub fn pending_sysevt<T: Into<Host>>(&self, host: T) -> Option<Sysevt> {
+        let host: Host = host.into();
+        let hipir = unsafe { ptr::read_volatile(self.intc_reg.offset(HIPIR_REG + host as isize)) };
+        match hipir {
+            hipir if hipir & HIPIR_NONE_HINT != 0 => None,
+            hipir => u8::try_from(hipir).ok().and_then(|sysevt| Sysevt::try_from(sysevt).ok()),
+        }
+    }
+
+    /// Sets the nesting level of a host interrupt.
+    ///
+    /// While the nesting level is set, only channels with a higher priority than the level,
+    /// i.e. with a number lower than the level, can signal the host interrupt. This makes it
+    /// possible to temporarily mask a host interrupt at a given priority level during critical
+    /// host-side sections. The reset value of the nesting level is 256, which masks nothing.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the level is greater than 511.
+    pub fn set_nesting_level<T: Into<Host>>(&self, host: T, level: u16) {
+        assert!(level as u32 <= HINLR_NEST_LVL, "nesting level out of range");
+        let host: Host = host.into();
+        unsafe {
+            ptr::write_volatile(self.intc_reg.offset(HINLR_REG + host as isize), level as u32);
+        }
+    }
+
+    /// Sets the nesting level of a host interrupt to the priority of the highest-priority
+    /// pending channel, so that only channels with a higher priority can interrupt its
+    /// servicing.
+    pub fn override_nesting_level<T: Into<Host>>(&self, host: T) {
+        let host: Host = host.into();
+        unsafe {
+            ptr::write_volatile(self.intc_reg.offset(HINLR_REG + host as isize),
+                                HINLR_AUTO_OVERRIDE);
+        }
+    }
+
+    /// Returns the current nesting level of a host interrupt.
+    pub fn nesting_level<T: Into<Host>>(&self, host: T) -> u16 {
+        let host: Host = host.into();
+        let hinlr = unsafe { ptr::read_volatile(self.intc_reg.offset(HINLR_REG + host as isize)) };
+
+        (hinlr & HINLR_NEST_LVL) as u16
+    }
+
+    /// Writes the masks of the system events to a pair of write-1 registers, skipping the
+    /// registers for which the mask is empty.
+    fn write_sysevt_masks(&self, sysevts: &[Sysevt], low_reg: isize, high_reg: isize) {
+        let (low, high) = sysevt_masks(sysevts);
+        unsafe {
+            if low != 0 {
+                ptr::write_volatile(self.intc_reg.offset(low_reg), low);
+            }
+            if high != 0 {
+                ptr::write_volatile(self.intc_reg.offset(high_reg), high);
+            }
+        }
+    }
+
+    /// Reads back the current configuration of the interrupt controller.
+    ///
+    /// This decodes the channel map, host map, system event enable, host interrupt enable,
+    /// polarity and type registers into an `IntcConfig`, which makes it possible to attach to a
+    /// PRU subsystem configured by another process without resetting its mapping, or to check
+    /// that the interrupt controller matches the intended configuration by comparing the
+    /// compiled images.
+    ///
+    /// Since the mapping registers cannot tell an unmapped system event from an event mapped to
+    /// channel 0, a system event is deemed assigned to a channel if it is enabled or if its
+    /// channel is not 0. Likewise, a channel is deemed mapped to a host if it is targeted by an
+    /// assigned system event or if its host is not 0.
+    pub fn current_config(&self) -> IntcConfig {
+        self.read_image().decode()
+    }
+
+    /// Returns the enabled system events which are routed to an event out.
+    ///
+    /// The routing is read from the interrupt controller as with `current_config`, so that event
+    /// handling code can find out which system events to clear without hard-coding them even if
+    /// the mapping varies between firmwares. The system events are sorted by number.
+    pub fn sysevts_for(&self, e: Evtout) -> Vec<Sysevt> {
+        self.host_sysevts(e.into())
+    }
+
+    /// Triggers the system event which notifies a PRU core.
+    ///
+    /// This is the conventional host to PRU system event, namely `Sysevt::S21` for PRU0 and
+    /// `Sysevt::S22` for PRU1, provided that it is routed to the host interrupt of the core by
+    /// the configuration in effect. Otherwise, the lowest-numbered enabled system event routed
+    /// to the core is triggered.
+    ///
+    /// Returns the system event that was triggered, or `None` if no system event is routed to
+    /// the core.
+    pub fn kick(&self, core: PruCore) -> Option<Sysevt> {
+        let (host, conventional) = match core {
+            PruCore::Pru0 => (Host::Pru0, Sysevt::S21),
+            PruCore::Pru1 => (Host::Pru1, Sysevt::S22),
+        };
+        let sysevts = self.host_sysevts(host);
+        let sysevt = if sysevts.contains(&conventional) {
+            Some(conventional)
+        } else {
+            sysevts.first().cloned()
+        };
+        if let Some(sysevt) = sysevt {
+            self.send_sysevt(sysevt);
+        }
+
+        sysevt
+    }
+
+    /// Returns the enabled system events routed to a host interrupt, sorted by number.
+    fn host_sysevts(&self, host: Host) -> Vec<Sysevt> {
+        let config = self.current_config();
+        config.sysevt_to_channel_map
+            .iter()
+            .filter(|sc| {
+                config.sysevt_enable.contains(&sc.sysevt) &&
+                config.channel_to_host_map
+                    .iter()
+                    .any(|ch| ch.channel == sc.channel && ch.host == host as u8)
+            })
+            .map(|sc| Sysevt::try_from(sc.sysevt).unwrap())
+            .collect()
+    }
+
+    /// Reads back the register image of the interrupt controller.
+    fn read_image(&self) -> CompiledIntcConfig {
+        unsafe {
+            let reg = |offset: isize| ptr::read_volatile(self.intc_reg.offset(offset));
+            let mut image = CompiledIntcConfig {
+                cmr: [0; NUM_CMRX as usize],
+                hmr: [0; NUM_HMRX as usize],
+                esr: [reg(ESR1_REG), reg(ESR2_REG)],
+                sipr: [reg(SIPR1_REG), reg(SIPR2_REG)],
+                sitr: [reg(SITR1_REG), reg(SITR2_REG)],
+                host_enable: Vec::new(),
+            };
+            for (cmrx, val) in image.cmr.iter_mut().enumerate() {
+                *val = reg(CMR_REG + cmrx as isize);
+            }
+            for (hmrx, val) in image.hmr.iter_mut().enumerate() {
+                *val = reg(HMR_REG + hmrx as isize);
+            }
+            let hier = reg(HIER_REG);
+            image.host_enable = (0..NUM_HOSTS).filter(|&h| hier & (1 << h) != 0).collect();
+
+            image
+        }
+    }
+
     /// Returns a synchronization primitive for event out host interrupts.
     ///
     /// Important: this function should be called before any corresponding event out is triggered.
@@ -332,9 +970,22 @@ impl Intc {
     ///
     /// This function should not panic provided that the uio_pruss kernel module is loaded, which
     /// is theoretically guaranteed at this point since `Pruss` could not have been created
-    /// otherwise.
+    /// otherwise. See `try_register_irq` for a non-panicking version.
     pub fn register_irq(&self, e: Evtout) -> EvtoutIrq {
-        EvtoutIrq::new(e)
+        self.try_register_irq(e).unwrap()
+    }
+
+    /// Returns a synchronization primitive for event out host interrupts, or an error if the
+    /// event out device cannot be opened.
+    ///
+    /// Important: this function should be called before any corresponding event out is triggered.
+    ///
+    /// # Errors
+    ///
+    /// IO errors that may occur while opening the event out device are forwarded.
+    pub fn try_register_irq(&self, e: Evtout) -> io::Result<EvtoutIrq> {
+        let path = format!("{}{}", self.evtout_prefix, e as usize);
+        EvtoutIrq::open(e, &path, self.prumap.clone(), self.intc_reg, self.irq_control)
     }
 }
 
@@ -342,15 +993,24 @@ impl Intc {
 
 /// PRU instruction code loader.
 pub struct PruLoader {
+    core: PruCore,
+    prumem_base: *mut u8,
     pructrl_reg: *mut u32,
     iram_base: *mut u8,
     iram_size: usize,
 }
 
 impl PruLoader {
-    fn new(pructrl_reg: *mut u32, iram_base: *mut u8, iram_size: usize) -> PruLoader {
+    fn new(core: PruCore,
+           prumem_base: *mut u8,
+           pructrl_reg: *mut u32,
+           iram_base: *mut u8,
+           iram_size: usize)
+           -> PruLoader {
 
         PruLoader {
+            core: core,
+            prumem_base: prumem_base,
             pructrl_reg: pructrl_reg,
             iram_base: iram_base,
             iram_size: iram_size,
@@ -362,44 +1022,277 @@ impl PruLoader {
     /// This function proceeds as follows:
     ///
     /// * a soft PRU reset is forced,
-    /// * the code is written to the PRU instruction RAM.
+    /// * the code is read until the end of the buffer and written to the PRU instruction RAM.
     ///
     /// The code can be subsequently started and stopped using the returned `PruCode` handle.
     ///
     /// # Errors
     ///
-    /// IO errors that may occur while reading the buffer are forwarded.
-    /// If the buffer cannot be read entirely because the code does not fit into the instruction
-    /// RAM, an error of the kind `ErrorKind::InvalidInput` is returned.
-    pub fn load_code<R: Read>(&mut self, code: &mut R) -> io::Result<PruCode> {
+    /// IO errors that may occur while reading the buffer are forwarded as `LoadError::Io`.
+    /// If the code does not fit into the instruction RAM, `LoadError::CodeTooLarge` is returned.
+    pub fn load_code<R: Read>(&mut self, code: &mut R) -> result::Result<PruCode, LoadError> {
         // Invoke a soft reset of the PRU to make sure no code is currently running.
         self.reset();
-        // Write the code to the instruction RAM.
-        let n: usize = try!(code.read( unsafe {
-            std::slice::from_raw_parts_mut(self.iram_base, self.iram_size)
-        }));
-        // Make sure the whole buffer was read, otherwise return an InvalidInput error kind.
-        match n {
-            0 => {
-                Err(io::Error::new(io::ErrorKind::InvalidInput,
-                                   "size of PRU code exceeding instruction RAM capacity"))
-            }
-            _ => {
-                // Introduce a fence to ensure that IRAM writes are not reordered past the
-                // call to PruCode::run().
-                // Does it actually work? Who knows, we did what we could.
-                compiler_fence(Ordering::Release);
-                Ok(PruCode::new(self.pructrl_reg))
-            }
+        self.write_iram(code)
+    }
+
+    /// Replaces the code of the PRU and restarts it, leaving the data RAM untouched.
+    ///
+    /// This function proceeds as follows:
+    ///
+    /// * the new code is read until the end of the buffer and its size is validated,
+    /// * the PRU is halted without clearing its control register,
+    /// * the new code is written to the PRU instruction RAM,
+    /// * the new code is started at address 0.
+    ///
+    /// The data RAMs are never written, so that state maintained by the firmware in data RAM
+    /// survives a firmware upgrade provided that the new code does not reinitialize it.
+    ///
+    /// # Errors
+    ///
+    /// The errors are the same as for `load_code`. The PRU is left untouched if an error occurs.
+    ///
+    /// # Safety
+    ///
+    /// This runs a binary code that has unrestricted access to pretty much all the processor
+    /// memory and peripherals.
+    pub unsafe fn swap_code<R: Read>(&mut self,
+                                     code: &mut R)
+                                     -> result::Result<PruCode<'_>, LoadError> {
+        let mut image = Vec::new();
+        try!(code.read_to_end(&mut image));
+        if image.len() > self.iram_size {
+            return Err(LoadError::CodeTooLarge {
+                size: image.len(),
+                capacity: self.iram_size,
+            });
         }
+        self.halt();
+        volatile_copy_to(self.iram_base, &image);
+        // Make sure that IRAM writes are not reordered past the call to PruCode::run().
+        compiler_fence(Ordering::Release);
+        let mut code = PruCode::new(self, 0, image.len().div_ceil(4));
+        code.run();
+
+        Ok(code)
     }
 
-    /// Resets the PRU.
+    /// Loads a binary of opcodes held in memory to the PRU without executing it.
+    ///
+    /// This function behaves like `load_code` but is better suited to firmwares embedded in the
+    /// host executable with `include_bytes!`: the size of the code is validated before the PRU is
+    /// reset, and the code is written to the instruction RAM with word-wide volatile stores.
+    ///
+    /// # Errors
+    ///
+    /// `LoadError::CodeTooLarge` is returned if the code does not fit into the instruction RAM,
+    /// in which case the PRU is left untouched.
+    pub fn load_code_from_slice(&mut self, code: &[u8]) -> result::Result<PruCode<'_>, LoadError> {
+        if code.len() > self.iram_size {
+            return Err(LoadError::CodeTooLarge {
+                size: code.len(),
+                capacity: self.iram_size,
+            });
+        }
+        // Invoke a soft reset of the PRU to make sure no code is currently running.
+        self.reset();
+        unsafe {
+            volatile_copy_to(self.iram_base, code);
+        }
+        // Make sure that IRAM writes are not reordered past the call to PruCode::run().
+        compiler_fence(Ordering::Release);
+
+        Ok(PruCode::new(self, 0, code.len().div_ceil(4)))
+    }
+
+    /// Loads a pasm instruction image together with its data image to the PRU without executing
+    /// it.
+    ///
+    /// This function proceeds as follows:
+    ///
+    /// * both images are read until the end of their buffer and their sizes are validated,
+    /// * a soft PRU reset is forced,
+    /// * the instruction image is written to the PRU instruction RAM,
+    /// * the data image is written at the beginning of the data RAM of the core, i.e. the data
+    ///   RAM which is located at local address 0 (DRAM0 for PRU0 and DRAM1 for PRU1).
+    ///
+    /// The data image is written through the `dram` segment, which must span the location of the
+    /// data image, typically `pruss.dram0` for PRU0 and `pruss.dram1` for PRU1. Borrowing the
+    /// segment mutably ensures that no object allocated in the data RAM is alive while it is
+    /// overwritten.
+    ///
+    /// The data image is what pasm emits in the `.dat` file alongside the `.bin` instruction
+    /// image. The code can be subsequently started and stopped using the returned `PruCode`
+    /// handle.
+    ///
+    /// # Errors
+    ///
+    /// IO errors that may occur while reading the buffers are forwarded as `LoadError::Io`.
+    /// `LoadError::CodeTooLarge` is returned if the code does not fit into the instruction RAM
+    /// and `LoadError::DataTooLarge` if the data image does not fit into the data RAM. The PRU
+    /// is left untouched if an error occurs.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the data image location is not within the `dram` segment.
+    pub fn load_code_with_data<R: Read, D: Read>(&mut self,
+                                                 code: &mut R,
+                                                 data: &mut D,
+                                                 dram: &mut MemSegment)
+                                                 -> result::Result<PruCode<'_>, LoadError> {
+        let mut image = Vec::new();
+        try!(code.read_to_end(&mut image));
+        let mut data_image = Vec::new();
+        try!(data.read_to_end(&mut data_image));
+        if image.len() > self.iram_size {
+            return Err(LoadError::CodeTooLarge {
+                size: image.len(),
+                capacity: self.iram_size,
+            });
+        }
+        let dram_size = match self.core {
+            PruCore::Pru0 => DRAM0_SIZE,
+            PruCore::Pru1 => DRAM1_SIZE,
+        };
+        if data_image.len() > dram_size {
+            return Err(LoadError::DataTooLarge {
+                size: data_image.len(),
+                capacity: dram_size,
+            });
+        }
+        let dram_addr = PruAddr::from_local(self.core, 0);
+        let PruAddr(dram_offset) = dram_addr;
+        assert!(dram_offset >= dram.from && dram_offset + data_image.len() <= dram.to,
+                "the data image location is not within the data RAM segment");
+        let code = try!(self.load_code_from_slice(&image));
+        dram.write_at(dram_addr, &data_image);
+        // Make sure that DRAM writes are not reordered past the call to PruCode::run().
+        compiler_fence(Ordering::Release);
+
+        Ok(code)
+    }
+
+    /// Loads a binary of opcodes to a halted PRU without resetting it.
+    ///
+    /// Unlike `load_code`, this function does not clear the PRU control register: it only clears
+    /// its enable bit to make sure that the PRU is halted before the code is written to the
+    /// instruction RAM. The program counter, the cycle and stall counters and the other control
+    /// settings are thus preserved, which is mostly useful for debugging purposes.
+    ///
+    /// The code can be subsequently started and stopped using the returned `PruCode` handle.
+    ///
+    /// # Errors
+    ///
+    /// IO errors that may occur while reading the buffer are forwarded as `LoadError::Io`.
+    /// If the code does not fit into the instruction RAM, `LoadError::CodeTooLarge` is returned.
+    pub fn load_code_halted<R: Read>(&mut self,
+                                     code: &mut R)
+                                     -> result::Result<PruCode<'_>, LoadError> {
+        // Clear the enable bit only to make sure no code is currently running.
+        self.halt();
+        self.write_iram(code)
+    }
+
+    /// Loads a binary of opcodes to the PRU after verifying its checksum.
+    ///
+    /// This function behaves like `load_code` but first computes the CRC-32 checksum of the whole
+    /// code with `util::crc32` and compares it against the expected value, so that a corrupted
+    /// firmware file is detected before it is written to the PRU.
+    ///
+    /// # Errors
+    ///
+    /// The errors are the same as for `load_code`. In addition, `LoadError::ChecksumMismatch` is
+    /// returned if the checksum differs from the expected value, in which case the PRU is left
+    /// untouched.
+    pub fn load_code_checked<R: Read>(&mut self,
+                                      code: &mut R,
+                                      crc: u32)
+                                      -> result::Result<PruCode<'_>, LoadError> {
+        let mut image = Vec::new();
+        try!(code.read_to_end(&mut image));
+        let actual = util::crc32(&image);
+        if actual != crc {
+            return Err(LoadError::ChecksumMismatch {
+                expected: crc,
+                actual: actual,
+            });
+        }
+
+        self.load_code_from_slice(&image)
+    }
+
+    /// Loads a binary of opcodes to the PRU and verifies the content of the instruction RAM.
+    ///
+    /// This function behaves like `load_code` but additionally reads the instruction RAM back
+    /// after the code has been written and compares it against the code, so that failed writes
+    /// to the instruction RAM are detected before the code is run.
+    ///
+    /// # Errors
+    ///
+    /// The errors are the same as for `load_code`. In addition, `LoadError::VerificationFailed`
+    /// is returned if the content of the instruction RAM differs from the code.
+    pub fn load_code_verified<R: Read>(&mut self,
+                                       code: &mut R)
+                                       -> result::Result<PruCode<'_>, LoadError> {
+        let mut image = Vec::new();
+        try!(code.read_to_end(&mut image));
+        let iram_base = self.iram_base;
+        let code = try!(self.load_code_from_slice(&image));
+        let mut readback = vec![0u8; image.len()];
+        unsafe {
+            volatile_copy_from(&mut readback, iram_base);
+        }
+        match image.iter().zip(&readback).position(|(expected, actual)| expected != actual) {
+            Some(offset) => Err(LoadError::VerificationFailed { offset: offset }),
+            None => Ok(code),
+        }
+    }
+
+    /// Writes the code to the instruction RAM.
+    fn write_iram<R: Read>(&mut self, code: &mut R) -> result::Result<PruCode<'_>, LoadError> {
+        let iram = unsafe { std::slice::from_raw_parts_mut(self.iram_base, self.iram_size) };
+        let mut n = 0;
+        while n < iram.len() {
+            match code.read(&mut iram[n..]) {
+                Ok(0) => break,
+                Ok(len) => n += len,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(LoadError::Io(err)),
+            }
+        }
+        // Make sure the whole buffer was read, otherwise report the actual size of the code.
+        if n == iram.len() {
+            let excess = try!(io::copy(code, &mut io::sink())) as usize;
+            if excess != 0 {
+                return Err(LoadError::CodeTooLarge {
+                    size: n + excess,
+                    capacity: iram.len(),
+                });
+            }
+        }
+        // Introduce a fence to ensure that IRAM writes are not reordered past the
+        // call to PruCode::run().
+        // Does it actually work? Who knows, we did what we could.
+        compiler_fence(Ordering::Release);
+
+        Ok(PruCode::new(self, 0, n.div_ceil(4)))
+    }
+
+    /// Resets the PRU.
     ///
     /// Invokes a soft reset by clearing the PRU control register.
     fn reset(&mut self) {
         unsafe {
-            ptr::write_volatile(self.pructrl_reg, 0);
+            PruCtrl::default().write(self.pructrl_reg);
+        }
+    }
+
+    /// Halts the PRU.
+    ///
+    /// Clears the enable bit of the PRU control register, leaving other bits untouched.
+    fn halt(&mut self) {
+        unsafe {
+            PruCtrl::read(self.pructrl_reg).with_enable(false).write(self.pructrl_reg);
         }
     }
 }
@@ -413,22 +1306,28 @@ impl PruLoader {
 /// borrowing segments upon object allocation, thus preventing further splitting and allocation
 /// until the allocated object goes out of scope. For this reason, segments are neither copyable
 /// nor clonable.
-pub struct MemSegment<'a> {
+///
+/// Positions within the segment are expressed with the address type `A`, which is `PruAddr` for
+/// segments of the PRU memory, `IramAddr` for segments of an instruction RAM, `HostOffset` for
+/// segments of the host memory and `DmaBufOffset` for segments of a `DmaBuf`.
+pub struct MemSegment<'a, A: SegmentAddr = PruAddr> {
     // It is necessary to keep the `from` index rather than offset the `base` pointer because
     // alignment must be checked when allocating memory for arbitrary types.
     base: *mut u8,
     from: usize,
     to: usize,
     _memory_marker: PhantomData<&'a [u8]>,
+    _addr_marker: PhantomData<A>,
 }
 
-impl<'a> MemSegment<'a> {
-    fn new<'b>(base: *mut u8, from: usize, to: usize) -> MemSegment<'b> {
+impl<'a, A: SegmentAddr> MemSegment<'a, A> {
+    fn new<'b>(base: *mut u8, from: usize, to: usize) -> MemSegment<'b, A> {
         MemSegment {
             base: base,
             from: from,
             to: to,
             _memory_marker: PhantomData,
+            _addr_marker: PhantomData,
         }
     }
     
@@ -449,9 +1348,8 @@ impl<'a> MemSegment<'a> {
     /// Allocates an object at the begining of the segment without initializing it.
     ///
     /// This can save some unecessary initialization if the PRU is anyway going to initialize
-    /// memory before it will be read by the host. In some cases, it can also be used to avoid
-    /// trashing the stack with a large temporary initialization object if for some reason the
-    /// compiler cannot inline the call to `alloc`.
+    /// memory before it will be read by the host. Large objects that must be initialized by the
+    /// host should rather be allocated with `alloc_with`.
     ///
     /// # Undefined Behavior
     ///
@@ -462,22 +1360,176 @@ impl<'a> MemSegment<'a> {
     /// This function will panic if the beginning of the segment is not properly aligned
     /// for type T or if the size of T exceeds its capacity.
     pub unsafe fn alloc_uninitialized<T: Copy>(&mut self) -> &mut T {
-        // Make sure the begining of the memory region is properly aligned for type T.
-        assert!(self.from % mem::align_of::<T>() == 0);
-        // Make sure the region is large enough to hold type T.
-        assert!(self.to - self.from >= mem::size_of::<T>());
+        &mut *self.alloc_ptr()
+    }
+
+    /// Allocates an object at the beginning of the segment and initializes it in place.
+    ///
+    /// The closure is given the uninitialized object, which it can initialize piecewise, e.g.
+    /// element by element through `MaybeUninit::as_mut_ptr`. Unlike with `alloc`, no temporary
+    /// object is constructed on the stack, so this is the way to initialize large objects such
+    /// as DMA buffers.
+    ///
+    /// # Safety
+    ///
+    /// The object must be fully initialized when the closure returns, since reading an
+    /// uninitialized object is undefined behavior (even for Copy types).
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the beginning of the segment is not properly aligned
+    /// for type T or if the size of T exceeds its capacity.
+    pub unsafe fn alloc_with<T, F>(&mut self, init: F) -> &mut T
+        where T: Copy,
+              F: FnOnce(&mut MaybeUninit<T>)
+    {
+        let target = self.alloc_ptr::<T>() as *mut MaybeUninit<T>;
+        init(&mut *target);
+
+        &mut *(target as *mut T)
+    }
+
+    /// Allocates a plain-old-data object at the beginning of the segment.
+    ///
+    /// This behaves like `alloc`, but the `Pod` bound guarantees that the object has no padding
+    /// bytes and no invalid bit patterns, so that any value written by the PRU firmware can be
+    /// safely read by the host and vice versa.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the beginning of the segment is not properly aligned
+    /// for type T or if the size of T exceeds its capacity.
+    #[cfg(feature = "bytemuck")]
+    pub fn alloc_pod<T: bytemuck::Pod>(&mut self, source: T) -> &mut T {
+        self.alloc(source)
+    }
+
+    /// Allocates a zero-initialized object at the beginning of the segment.
+    ///
+    /// The memory is zeroed in place, so no temporary object is constructed on the stack. The
+    /// `Zeroable` bound guarantees that the all-zero bit pattern is a valid value of T.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the beginning of the segment is not properly aligned
+    /// for type T or if the size of T exceeds its capacity.
+    #[cfg(feature = "bytemuck")]
+    pub fn alloc_zeroed<T: bytemuck::Zeroable + Copy>(&mut self) -> &mut T {
+        unsafe { self.alloc_with(|target| ptr::write_bytes(target.as_mut_ptr(), 0, 1)) }
+    }
+
+    /// Returns a view of an object located at the given byte position within the segment.
+    ///
+    /// This gives access to objects whose location is dictated by the firmware rather than by
+    /// the allocation order. The `FromBytes` and `AsBytes` bounds guarantee that any memory
+    /// content is a valid value of T and that T has no padding bytes. As for `split_at`, the
+    /// position is absolute.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the position is not properly aligned for type T or if the
+    /// object does not lie within the segment.
+    #[cfg(feature = "zerocopy")]
+    pub fn view_at<T>(&mut self, position: A) -> &mut T
+        where T: zerocopy::FromBytes + zerocopy::AsBytes
+    {
+        unsafe { &mut *self.ptr_at(position.into(), 1) }
+    }
+
+    /// Returns a view of a slice of `len` objects located at the given byte position within the
+    /// segment.
+    ///
+    /// This behaves like `view_at`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the position is not properly aligned for type T or if the
+    /// slice does not lie within the segment.
+    #[cfg(feature = "zerocopy")]
+    pub fn view_slice_at<T>(&mut self, position: A, len: usize) -> &mut [T]
+        where T: zerocopy::FromBytes + zerocopy::AsBytes
+    {
+        unsafe { slice::from_raw_parts_mut(self.ptr_at(position.into(), len), len) }
+    }
+
+    /// Copies bytes into the segment at the given byte position using volatile stores.
+    ///
+    /// This is convenient to stage variable-size data such as lookup tables without allocating
+    /// an object of matching type. As for `split_at`, the position is absolute.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the bytes do not fit within the segment at this position.
+    pub fn write_at(&mut self, position: A, data: &[u8]) {
+        let dst = self.ptr_at::<u8>(position.into(), data.len());
+        unsafe {
+            volatile_copy_to(dst, data);
+        }
+    }
+
+    /// Copies bytes out of the segment at the given byte position using volatile loads.
+    ///
+    /// As for `split_at`, the position is absolute.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the buffer is larger than the part of the segment which
+    /// follows the position.
+    pub fn read_at(&self, position: A, buf: &mut [u8]) {
+        let src = self.ptr_at::<u8>(position.into(), buf.len());
+        unsafe {
+            volatile_copy_from(buf, src);
+        }
+    }
+
+    /// Sets all bytes of the segment to the specified value using volatile stores.
+    pub fn fill(&mut self, byte: u8) {
+        unsafe {
+            volatile_fill(self.base.offset(self.from as isize), self.to - self.from, byte);
+        }
+    }
 
-        &mut *(self.base.offset(self.from as isize) as *mut T)
+    /// Sets all bytes of the segment to zero using volatile stores.
+    ///
+    /// This is typically used to clear a data RAM before the firmware is started.
+    pub fn zero(&mut self) {
+        self.fill(0);
+    }
+
+    /// Returns a pointer to the beginning of the segment after checking that it can hold an
+    /// object of type T.
+    fn alloc_ptr<T>(&mut self) -> *mut T {
+        let from = self.from;
+
+        self.ptr_at(from, 1)
+    }
+
+    /// Returns a pointer to the given position after checking that the segment can hold `len`
+    /// objects of type T at this position.
+    fn ptr_at<T>(&self, position: usize, len: usize) -> *mut T {
+        // Make sure the position is properly aligned for type T.
+        assert!(position.is_multiple_of(mem::align_of::<T>()));
+        // Make sure the region is large enough to hold the objects.
+        assert!(position >= self.from && position <= self.to);
+        let size = mem::size_of::<T>().checked_mul(len);
+        assert!(size.is_some_and(|size| self.to - position >= size));
+
+        unsafe { self.base.offset(position as isize) as *mut T }
     }
 
-    /// Position at which the segment starts (in bytes).
-    pub fn begin(&self) -> usize {
-        self.from
+    /// Returns a cursor writing objects sequentially from the beginning of the segment.
+    pub fn cursor<'s>(&'s mut self) -> SegmentCursor<'s, 'a, A> {
+        SegmentCursor::new(self)
     }
 
-    /// Position at which the segment ends (in bytes).
-    pub fn end(&self) -> usize {
-        self.to
+    /// Position at which the segment starts.
+    pub fn begin(&self) -> A {
+        self.from.into()
+    }
+
+    /// Position at which the segment ends.
+    pub fn end(&self) -> A {
+        self.to.into()
     }
 
     /// Splits the memory segment into two at the given byte position.
@@ -485,26 +1537,67 @@ impl<'a> MemSegment<'a> {
     /// Note that positions (addresses) are absolute and remain valid after the splitting
     /// operation. If for instance a segment is split at 0x00001000, the `begin` method of
     /// the second segment hence created will return 0x00001000 and not 0x00000000.
-    pub fn split_at(&mut self, position: usize) -> (MemSegment, MemSegment) {
+    pub fn split_at(&mut self, position: A) -> (MemSegment<A>, MemSegment<A>) {
+        let position: usize = position.into();
         assert!(position >= self.from && position <= self.to);
         (MemSegment {
             base: self.base,
             from: self.from,
             to: position,
             _memory_marker: PhantomData,
+            _addr_marker: PhantomData,
         },
          MemSegment {
             base: self.base,
             from: position,
             to: self.to,
             _memory_marker: PhantomData,
+            _addr_marker: PhantomData,
         })
     }
 }
 
-unsafe impl<'a> Send for MemSegment<'a> {}
+unsafe impl<'a, A: SegmentAddr> Send for MemSegment<'a, A> {}
+
+unsafe impl<'a, A: SegmentAddr> Sync for MemSegment<'a, A> {}
+
+/// Reads bytes from the beginning of the segment using volatile loads.
+///
+/// Similarly to the implementation of `Read` for `&[u8]`, the bytes which have been read are
+/// removed from the segment, which then shrinks from the front. A segment may be split first if
+/// the remaining part is meant to be used later.
+impl<'a, A: SegmentAddr> Read for MemSegment<'a, A> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = cmp::min(buf.len(), self.to - self.from);
+        unsafe {
+            volatile_copy_from(&mut buf[..n], self.base.offset(self.from as isize));
+        }
+        self.from += n;
+
+        Ok(n)
+    }
+}
+
+/// Writes bytes at the beginning of the segment using volatile stores.
+///
+/// Similarly to the implementation of `Write` for `&mut [u8]`, the bytes which have been written
+/// are removed from the segment, which then shrinks from the front. A segment may be split first
+/// if the remaining part is meant to be used later.
+impl<'a, A: SegmentAddr> Write for MemSegment<'a, A> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = cmp::min(buf.len(), self.to - self.from);
+        unsafe {
+            volatile_copy_to(self.base.offset(self.from as isize), &buf[..n]);
+        }
+        self.from += n;
+
+        Ok(n)
+    }
 
-unsafe impl<'a> Sync for MemSegment<'a> {}
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
 
 
@@ -513,13 +1606,16 @@ unsafe impl<'a> Sync for MemSegment<'a> {}
 /// A call to the `new_populated` method automatically initializes the data with the same defaults
 /// as the PRUSS_INTC_INITDATA macro of the C prussdrv library. Alternatively, a blank-state
 /// initialization data structure can be created with `new_empty` and then populated with the
-/// dedicated methods.
+/// dedicated methods. Presets such as `for_evtout_per_pru` or `pru_crosstalk_only` cover other
+/// common mappings.
 #[derive(Clone)]
 pub struct IntcConfig {
     sysevt_to_channel_map: Vec<SysevtToChannel>,
     channel_to_host_map: Vec<ChannelToHost>,
     sysevt_enable: Vec<u8>,
     host_enable: Vec<u8>,
+    active_low: Vec<u8>,
+    level: Vec<u8>,
 }
 
 impl IntcConfig {
@@ -530,6 +1626,8 @@ impl IntcConfig {
             channel_to_host_map: Vec::new(),
             sysevt_enable: Vec::new(),
             host_enable: Vec::new(),
+            active_low: Vec::new(),
+            level: Vec::new(),
         }
     }
 
@@ -584,19 +1682,76 @@ impl IntcConfig {
         config_data
     }
 
+    /// Constructs a configuration in which each PRU signals the host through its own event out.
+    ///
+    /// Following the conventions of `new_populated`, it routes:
+    ///
+    /// * `Sysevt::S19` to `Host::Evtout0` through `Channel::C2`,
+    /// * `Sysevt::S20` to `Host::Evtout1` through `Channel::C3`.
+    ///
+    /// No system event is routed to the PRUs.
+    pub fn for_evtout_per_pru() -> IntcConfig {
+        IntcConfig::builder()
+            .route(Sysevt::S19, Channel::C2, Host::Evtout0)
+            .route(Sysevt::S20, Channel::C3, Host::Evtout1)
+            .build()
+            .unwrap()
+    }
+
+    /// Constructs a configuration in which all event outs can be signaled.
+    ///
+    /// It routes `Sysevt::S19` to `Sysevt::S26` to `Host::Evtout0` to `Host::Evtout7` through
+    /// `Channel::C2` to `Channel::C9`, respectively. No system event is routed to the PRUs.
+    pub fn for_all_evtouts() -> IntcConfig {
+        let mut builder = IntcConfig::builder();
+        for i in 0..NUM_EVTOUTS {
+            builder = builder.route(Sysevt::try_from(19 + i).unwrap(),
+                                    Channel::try_from(2 + i).unwrap(),
+                                    Host::try_from(2 + i).unwrap());
+        }
+
+        builder.build().unwrap()
+    }
+
+    /// Constructs a configuration in which the PRUs can only signal each other.
+    ///
+    /// Following the conventions of `new_populated`, it routes:
+    ///
+    /// * `Sysevt::S17` to `Host::Pru1` through `Channel::C1`,
+    /// * `Sysevt::S18` to `Host::Pru0` through `Channel::C0`.
+    pub fn pru_crosstalk_only() -> IntcConfig {
+        IntcConfig::builder()
+            .route(Sysevt::S17, Channel::C1, Host::Pru1)
+            .route(Sysevt::S18, Channel::C0, Host::Pru0)
+            .build()
+            .unwrap()
+    }
+
     /// Enables the specified system events.
     ///
     /// # Panics
     ///
     /// This will panic if a system event is enabled several times.
     pub fn enable_sysevts(&mut self, sysevts: &[Sysevt]) {
-        let mut bitfield = BitField64::new(NUM_SYSEVTS);
-        self.sysevt_enable = sysevts.iter()
-            .map(|&sysevt| {
-                assert!(bitfield.try_set(sysevt as u8));
-                sysevt as u8
-            })
-            .collect();
+        if let Err(err) = self.try_enable_sysevts(sysevts) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Enables the specified system events, failing if a system event is enabled several times.
+    ///
+    /// # Errors
+    ///
+    /// `ConfigError::DuplicateSysevt` is returned if a system event is enabled several times, in
+    /// which case the configuration is left unchanged.
+    pub fn try_enable_sysevts(&mut self, sysevts: &[Sysevt]) -> result::Result<(), ConfigError> {
+        let sysevts: Vec<u8> = sysevts.iter().map(|&sysevt| sysevt as u8).collect();
+        if let Some(sysevt) = find_duplicate(NUM_SYSEVTS, &sysevts) {
+            return Err(ConfigError::DuplicateSysevt(sysevt));
+        }
+        self.sysevt_enable = sysevts;
+
+        Ok(())
     }
 
     /// Enables the specified host interrupts.
@@ -605,13 +1760,26 @@ impl IntcConfig {
     ///
     /// This will panic if a host interrupt is enabled several times.
     pub fn enable_hosts(&mut self, hosts: &[Host]) {
-        let mut bitfield = BitField32::new(NUM_HOSTS);
-        self.host_enable = hosts.iter()
-            .map(|&host| {
-                assert!(bitfield.try_set(host as u8));
-                host as u8
-            })
-            .collect()
+        if let Err(err) = self.try_enable_hosts(hosts) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Enables the specified host interrupts, failing if a host interrupt is enabled several
+    /// times.
+    ///
+    /// # Errors
+    ///
+    /// `ConfigError::DuplicateHost` is returned if a host interrupt is enabled several times, in
+    /// which case the configuration is left unchanged.
+    pub fn try_enable_hosts(&mut self, hosts: &[Host]) -> result::Result<(), ConfigError> {
+        let hosts: Vec<u8> = hosts.iter().map(|&host| host as u8).collect();
+        if let Some(host) = find_duplicate(NUM_HOSTS, &hosts) {
+            return Err(ConfigError::DuplicateHost(host));
+        }
+        self.host_enable = hosts;
+
+        Ok(())
     }
 
     /// Automatically enables system events that are already assigned to a channel.
@@ -638,16 +1806,36 @@ impl IntcConfig {
     ///
     /// This will panic if a system event is mapped to several channels simultaneously.
     pub fn map_sysevts_to_channels(&mut self, scmap: &[(Sysevt, Channel)]) {
-        let mut bitfield = BitField64::new(NUM_SYSEVTS);
-        self.sysevt_to_channel_map = scmap.iter()
+        if let Err(err) = self.try_map_sysevts_to_channels(scmap) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Assigns system events to channels, failing if a system event is mapped to several
+    /// channels.
+    ///
+    /// # Errors
+    ///
+    /// `ConfigError::DuplicateSysevt` is returned if a system event is mapped to several
+    /// channels, in which case the configuration is left unchanged.
+    pub fn try_map_sysevts_to_channels(&mut self,
+                                       scmap: &[(Sysevt, Channel)])
+                                       -> result::Result<(), ConfigError> {
+        let map: Vec<SysevtToChannel> = scmap.iter()
             .map(|&(s, c)| {
-                assert!(bitfield.try_set(s as u8));
                 SysevtToChannel {
                     sysevt: s as u8,
                     channel: c as u8,
                 }
             })
             .collect();
+        let sysevts: Vec<u8> = map.iter().map(|m| m.sysevt).collect();
+        if let Some(sysevt) = find_duplicate(NUM_SYSEVTS, &sysevts) {
+            return Err(ConfigError::DuplicateSysevt(sysevt));
+        }
+        self.sysevt_to_channel_map = map;
+
+        Ok(())
     }
 
     /// Assigns channel numbers to host interrupts.
@@ -659,67 +1847,711 @@ impl IntcConfig {
     ///
     /// This will panic if a channel is mapped to several hosts.
     pub fn map_channels_to_hosts(&mut self, chmap: &[(Channel, Host)]) {
-        let mut bitfield = BitField32::new(NUM_CHANNELS);
-        self.channel_to_host_map = chmap.iter()
+        if let Err(err) = self.try_map_channels_to_hosts(chmap) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Assigns channel numbers to host interrupts, failing if a channel is mapped to several
+    /// hosts.
+    ///
+    /// # Errors
+    ///
+    /// `ConfigError::DuplicateChannel` is returned if a channel is mapped to several hosts, in
+    /// which case the configuration is left unchanged.
+    pub fn try_map_channels_to_hosts(&mut self,
+                                     chmap: &[(Channel, Host)])
+                                     -> result::Result<(), ConfigError> {
+        let map: Vec<ChannelToHost> = chmap.iter()
             .map(|&(c, h)| {
-                assert!(bitfield.try_set(c as u8));
                 ChannelToHost {
                     channel: c as u8,
                     host: h as u8,
                 }
             })
             .collect();
+        let channels: Vec<u8> = map.iter().map(|m| m.channel).collect();
+        if let Some(channel) = find_duplicate(NUM_CHANNELS, &channels) {
+            return Err(ConfigError::DuplicateChannel(channel));
+        }
+        self.channel_to_host_map = map;
+
+        Ok(())
     }
-}
 
+    /// Sets the polarity of the specified system events.
+    ///
+    /// The other system events are active high, which is what the PRU cores generate. A
+    /// different polarity may be required by events generated by other peripherals.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the polarity of a system event is set several times.
+    pub fn set_sysevt_polarities(&mut self, polarities: &[(Sysevt, SysevtPolarity)]) {
+        if let Err(err) = self.try_set_sysevt_polarities(polarities) {
+            panic!("{}", err);
+        }
+    }
 
+    /// Sets the polarity of the specified system events, failing if the polarity of a system
+    /// event is set several times.
+    ///
+    /// # Errors
+    ///
+    /// `ConfigError::DuplicateSysevt` is returned if the polarity of a system event is set
+    /// several times, in which case the configuration is left unchanged.
+    pub fn try_set_sysevt_polarities(&mut self,
+                                     polarities: &[(Sysevt, SysevtPolarity)])
+                                     -> result::Result<(), ConfigError> {
+        let sysevts: Vec<u8> = polarities.iter().map(|&(sysevt, _)| sysevt as u8).collect();
+        if let Some(sysevt) = find_duplicate(NUM_SYSEVTS, &sysevts) {
+            return Err(ConfigError::DuplicateSysevt(sysevt));
+        }
+        self.active_low = polarities.iter()
+            .filter(|&&(_, polarity)| polarity == SysevtPolarity::ActiveLow)
+            .map(|&(sysevt, _)| sysevt as u8)
+            .collect();
 
-/// Synchronization primitive that can be used to wait for an event out.
-pub struct EvtoutIrq {
-    file: File,
-    event: Evtout,
-}
+        Ok(())
+    }
 
-impl EvtoutIrq {
-    // This function should not panic as long as the UIO module is loaded.
-    fn new(e: Evtout) -> EvtoutIrq {
-        EvtoutIrq {
-            file: File::open(format!("{}{}", EVTOUT_DEVICE_ROOT_PATH, e as usize)).unwrap(),
-            event: e,
+    /// Sets the type of the specified system events.
+    ///
+    /// The other system events are pulse events, which is what the PRU cores generate. Events
+    /// generated by other peripherals, such as the eCAP, UART or IEP events routed into the
+    /// interrupt controller, may require level semantics.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the type of a system event is set several times.
+    pub fn set_sysevt_types(&mut self, types: &[(Sysevt, SysevtType)]) {
+        if let Err(err) = self.try_set_sysevt_types(types) {
+            panic!("{}", err);
         }
     }
 
-    /// Waits until the associated event out is triggered.
+    /// Sets the type of the specified system events, failing if the type of a system event is
+    /// set several times.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// This function should not panic as long as the UIO module is loaded, which is theoretically
-    /// guaranteed at this point since `Pruss` could not have been created otherwise.
-    pub fn wait(&self) -> u32 {
-        let mut buffer = [0u8; 4];
-        (&mut &(self.file)).read_exact(&mut buffer).unwrap();
-        unsafe { mem::transmute::<[u8; 4], u32>(buffer) }
+    /// `ConfigError::DuplicateSysevt` is returned if the type of a system event is set several
+    /// times, in which case the configuration is left unchanged.
+    pub fn try_set_sysevt_types(&mut self,
+                                types: &[(Sysevt, SysevtType)])
+                                -> result::Result<(), ConfigError> {
+        let sysevts: Vec<u8> = types.iter().map(|&(sysevt, _)| sysevt as u8).collect();
+        if let Some(sysevt) = find_duplicate(NUM_SYSEVTS, &sysevts) {
+            return Err(ConfigError::DuplicateSysevt(sysevt));
+        }
+        self.level = types.iter()
+            .filter(|&&(_, sysevt_type)| sysevt_type == SysevtType::Level)
+            .map(|&(sysevt, _)| sysevt as u8)
+            .collect();
+
+        Ok(())
     }
 
-    /// Returns the associated event out.
-    pub fn get_evtout(&self) -> Evtout {
-        self.event
+    /// Computes the register image of the configuration.
+    pub fn compile(&self) -> CompiledIntcConfig {
+        CompiledIntcConfig::new(self)
+    }
+
+    /// Returns a builder which expresses the configuration as routes from system events to host
+    /// interrupts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use prusst::{Channel, Host, IntcConfig, Sysevt};
+    /// let config = IntcConfig::builder()
+    ///     .route(Sysevt::S19, Channel::C2, Host::Evtout0)
+    ///     .route(Sysevt::S20, Channel::C3, Host::Evtout1)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> IntcConfigBuilder {
+        IntcConfigBuilder::new()
     }
 }
 
 
+/// The default configuration is the one of `new_populated`.
+impl Default for IntcConfig {
+    fn default() -> IntcConfig {
+        IntcConfig::new_populated()
+    }
+}
 
-/// Handle to a binary code loaded in the PRU.
-pub struct PruCode<'a> {
-    pructrl_reg: *mut u32,
-    _pructrl_marker: PhantomData<&'a mut u32>,
+
+
+/// Builder for PRU interrupt controller configurations.
+///
+/// Each route assigns a system event to a channel and the channel to a host interrupt, and
+/// enables both the system event and the host interrupt, so that an enabled system event cannot
+/// go unnoticed for lack of a mapping.
+#[derive(Clone, Default)]
+pub struct IntcConfigBuilder {
+    routes: Vec<(Sysevt, Channel, Host)>,
+    polarities: Vec<(Sysevt, SysevtPolarity)>,
+    types: Vec<(Sysevt, SysevtType)>,
 }
 
-impl<'a> PruCode<'a> {
-    fn new<'b>(pructrl_reg: *mut u32) -> PruCode<'b> {
-        PruCode {
-            pructrl_reg: pructrl_reg,
-            _pructrl_marker: PhantomData,
+impl IntcConfigBuilder {
+    /// Creates a builder without routes.
+    pub fn new() -> IntcConfigBuilder {
+        IntcConfigBuilder {
+            routes: Vec::new(),
+            polarities: Vec::new(),
+            types: Vec::new(),
+        }
+    }
+
+    /// Routes a system event to a host interrupt through a channel.
+    ///
+    /// Several system events may be routed through the same channel provided that they target
+    /// the same host interrupt.
+    pub fn route(mut self, sysevt: Sysevt, channel: Channel, host: Host) -> IntcConfigBuilder {
+        self.routes.push((sysevt, channel, host));
+        self
+    }
+
+    /// Sets the polarity of a system event, which is active high by default.
+    pub fn polarity(mut self, sysevt: Sysevt, polarity: SysevtPolarity) -> IntcConfigBuilder {
+        self.polarities.push((sysevt, polarity));
+        self
+    }
+
+    /// Sets the type of a system event, which is a pulse by default.
+    pub fn sysevt_type(mut self, sysevt: Sysevt, sysevt_type: SysevtType) -> IntcConfigBuilder {
+        self.types.push((sysevt, sysevt_type));
+        self
+    }
+
+    /// Creates the configuration.
+    ///
+    /// # Errors
+    ///
+    /// `ConfigError::DuplicateSysevt` is returned if a system event is routed several times or
+    /// if its polarity or type is set several times, and `ConfigError::DuplicateChannel` if a
+    /// channel is routed to several host interrupts.
+    pub fn build(&self) -> result::Result<IntcConfig, ConfigError> {
+        let mut chmap: Vec<(Channel, Host)> = Vec::new();
+        let mut hosts: Vec<Host> = Vec::new();
+        for &(_, channel, host) in &self.routes {
+            match chmap.iter().find(|&&(c, _)| c == channel) {
+                Some(&(_, h)) if h != host => {
+                    return Err(ConfigError::DuplicateChannel(channel as u8));
+                }
+                Some(_) => {}
+                None => chmap.push((channel, host)),
+            }
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        let scmap: Vec<(Sysevt, Channel)> = self.routes.iter().map(|&(s, c, _)| (s, c)).collect();
+
+        let mut config = IntcConfig::new_empty();
+        try!(config.try_map_sysevts_to_channels(&scmap));
+        try!(config.try_map_channels_to_hosts(&chmap));
+        try!(config.try_set_sysevt_polarities(&self.polarities));
+        try!(config.try_set_sysevt_types(&self.types));
+        config.auto_enable_sysevts();
+        try!(config.try_enable_hosts(&hosts));
+
+        Ok(config)
+    }
+}
+
+
+
+/// Precompiled PRU interrupt controller configuration.
+///
+/// This is the raw register image of an `IntcConfig`, as produced by `IntcConfig::compile`. Since
+/// the image is computed beforehand, the interrupt controller can be configured with plain
+/// consecutive word stores rather than with read-modify-write cycles. Applications which
+/// frequently re-initialize the PRU subsystem can compile their configuration once and re-use it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompiledIntcConfig {
+    cmr: [u32; NUM_CMRX as usize],
+    hmr: [u32; NUM_HMRX as usize],
+    esr: [u32; 2],
+    sipr: [u32; 2],
+    sitr: [u32; 2],
+    host_enable: Vec<u8>,
+}
+
+impl CompiledIntcConfig {
+    fn new(interrupts: &IntcConfig) -> CompiledIntcConfig {
+        let mut image = CompiledIntcConfig {
+            cmr: [0; NUM_CMRX as usize],
+            hmr: [0; NUM_HMRX as usize],
+            esr: [0; 2],
+            sipr: [0xffffffff; 2],
+            sitr: [0; 2],
+            host_enable: interrupts.host_enable.clone(),
+        };
+
+        for m in &interrupts.sysevt_to_channel_map {
+            let cmrx = (m.sysevt >> 2) as usize;
+            debug_assert!(cmrx < NUM_CMRX as usize);
+            image.cmr[cmrx] |= (m.channel as u32) << ((m.sysevt as u32 & 0b11) * 8);
+        }
+
+        for m in &interrupts.channel_to_host_map {
+            let hmrx = (m.channel >> 2) as usize;
+            debug_assert!(hmrx < NUM_HMRX as usize);
+            image.hmr[hmrx] |= (m.host as u32) << ((m.channel as u32 & 0b11) * 8);
+        }
+
+        for se in &interrupts.sysevt_enable {
+            match *se {
+                0...31 => image.esr[0] |= 1u32 << se,
+                32...63 => image.esr[1] |= 1u32 << (se - 32),
+                _ => unreachable!(),
+            };
+        }
+
+        // Active-low events have their polarity bit cleared, level events their type bit set.
+        let (low, high) = sysevt_bits(&interrupts.active_low);
+        image.sipr = [!low, !high];
+        let (low, high) = sysevt_bits(&interrupts.level);
+        image.sitr = [low, high];
+
+        image
+    }
+
+    /// Decodes the register image, see `Intc::current_config`.
+    fn decode(&self) -> IntcConfig {
+        let is_set = |regs: &[u32; 2], se: u8| regs[(se / 32) as usize] & 1 << (se % 32) != 0;
+        let mut config = IntcConfig::new_empty();
+
+        config.sysevt_to_channel_map = (0..NUM_SYSEVTS)
+            .map(|se| {
+                SysevtToChannel {
+                    sysevt: se,
+                    channel: (self.cmr[(se >> 2) as usize] >> ((se & 0b11) * 8)) as u8,
+                }
+            })
+            .filter(|m| m.channel != 0 || is_set(&self.esr, m.sysevt))
+            .collect();
+
+        config.channel_to_host_map = (0..NUM_CHANNELS)
+            .map(|ch| {
+                ChannelToHost {
+                    channel: ch,
+                    host: (self.hmr[(ch >> 2) as usize] >> ((ch & 0b11) * 8)) as u8,
+                }
+            })
+            .filter(|m| {
+                m.host != 0 ||
+                config.sysevt_to_channel_map.iter().any(|sc| sc.channel == m.channel)
+            })
+            .collect();
+
+        config.sysevt_enable = (0..NUM_SYSEVTS).filter(|&se| is_set(&self.esr, se)).collect();
+        config.host_enable = self.host_enable.clone();
+        config.active_low = (0..NUM_SYSEVTS).filter(|&se| !is_set(&self.sipr, se)).collect();
+        config.level = (0..NUM_SYSEVTS).filter(|&se| is_set(&self.sitr, se)).collect();
+
+        config
+    }
+}
+
+
+
+/// Synchronization primitive that can be used to wait for an event out.
+///
+/// The underlying event out device can be monitored with `poll`, `epoll` or `select` through
+/// `AsRawFd`/`AsFd`: the descriptor becomes readable when the event out is triggered, at which
+/// point `try_wait` returns without blocking.
+pub struct EvtoutIrq {
+    // The asynchronous registration must be released before the file is closed.
+    #[cfg(feature = "async")]
+    async_fd: OnceLock<tokio::io::unix::AsyncFd<RawFd>>,
+    file: File,
+    event: Evtout,
+    intc_reg: *mut u32,
+    policy: WaitPolicy,
+    irq_control: IrqControl,
+    last_count: Mutex<Option<u32>>,
+    latency: Mutex<Option<LatencyRecorder>>,
+    // Whether the driver supports interrupt control, once known.
+    irq_control_support: Mutex<Option<bool>>,
+    // The PRU memory map must outlive the pointer to the interrupt controller registers.
+    _prumap: Arc<MemMap>,
+}
+
+impl EvtoutIrq {
+    fn open(e: Evtout,
+            path: &str,
+            prumap: Arc<MemMap>,
+            intc_reg: *mut u32,
+            irq_control: IrqControl)
+            -> io::Result<EvtoutIrq> {
+        // The device file must be writable if interrupts are re-armed through it. Otherwise it
+        // is opened for writing only if permitted, for use by `rearm` and `mask`.
+        let writable = OpenOptions::new().read(true).write(true).open(path);
+        let file = match irq_control {
+            IrqControl::Rearm => try!(writable),
+            IrqControl::Off => try!(writable.or_else(|_| File::open(path))),
+        };
+
+        Ok(EvtoutIrq {
+            #[cfg(feature = "async")]
+            async_fd: OnceLock::new(),
+            file: file,
+            event: e,
+            intc_reg: intc_reg,
+            policy: WaitPolicy::Block,
+            irq_control: irq_control,
+            last_count: Mutex::new(None),
+            latency: Mutex::new(None),
+            irq_control_support: Mutex::new(None),
+            _prumap: prumap,
+        })
+    }
+
+    /// Sets the strategy used by `try_wait` to detect the event out.
+    pub fn set_wait_policy(&mut self, policy: WaitPolicy) {
+        self.policy = policy;
+    }
+
+    /// Returns the strategy used by `try_wait` to detect the event out.
+    pub fn wait_policy(&self) -> WaitPolicy {
+        self.policy
+    }
+
+    /// Waits until the associated event out is triggered.
+    ///
+    /// Depending on the wait policy, the host interrupt status of the interrupt controller may
+    /// be polled for some time before blocking on the event out device. The wait is resumed if
+    /// it is interrupted by a signal.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the event out device fails, see `try_wait`.
+    #[deprecated(note = "use `try_wait`, which does not panic on device errors")]
+    pub fn wait(&self) -> u32 {
+        self.try_wait().unwrap()
+    }
+
+    /// Waits until the associated event out is triggered and returns the total interrupt count.
+    ///
+    /// Depending on the wait policy, the host interrupt status of the interrupt controller may
+    /// be polled for some time before blocking on the event out device. The wait is resumed if
+    /// it is interrupted by a signal.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the interrupt cannot be re-armed or if the event out device
+    /// fails, for instance because the UIO module was unloaded.
+    pub fn try_wait(&self) -> result::Result<u32, WaitError> {
+        self.try_wait_counted().map(|(_, total)| total)
+    }
+
+    /// Waits until the associated event out is triggered or the timeout expires.
+    ///
+    /// Returns the total interrupt count, or `None` if the timeout expired. Depending on the wait
+    /// policy, the host interrupt status of the interrupt controller may be polled for some time
+    /// before blocking on the event out device. The wait is resumed with the remaining time if
+    /// it is interrupted by a signal.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the event out device fails, see `try_wait_timeout`.
+    #[deprecated(note = "use `try_wait_timeout`, which does not panic on device errors")]
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<u32> {
+        self.try_wait_timeout(timeout).unwrap()
+    }
+
+    /// Waits until the associated event out is triggered or the timeout expires.
+    ///
+    /// This behaves like `try_wait`, but returns `None` if the timeout expired.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned as by `try_wait`.
+    pub fn try_wait_timeout(&self, timeout: Duration) -> result::Result<Option<u32>, WaitError> {
+        self.wait_and_count_timeout(Some(timeout)).map(|count| count.map(|(_, total)| total))
+    }
+
+    /// Waits until the associated event out is triggered and returns the number of interrupts
+    /// since the previous wait together with the total interrupt count.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the event out device fails, see `try_wait_counted`.
+    #[deprecated(note = "use `try_wait_counted`, which does not panic on device errors")]
+    pub fn wait_counted(&self) -> (u32, u32) {
+        self.try_wait_counted().unwrap()
+    }
+
+    /// Waits until the associated event out is triggered and returns the number of interrupts
+    /// since the previous wait together with the total interrupt count.
+    ///
+    /// The number of interrupts is computed from the total count returned by the previous call
+    /// to any of the wait functions and is assumed to be 1 on the first call. A value greater
+    /// than 1 means that the event out was triggered several times since the previous wait, i.e.
+    /// that the host is falling behind the PRU and that `n - 1` events were missed.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned as by `try_wait`.
+    pub fn try_wait_counted(&self) -> result::Result<(u32, u32), WaitError> {
+        self.wait_and_count_timeout(None).map(|count| count.unwrap())
+    }
+
+    /// Waits until the associated event out is triggered, then clears the system event and
+    /// re-enables the host interrupt.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the event out device fails, see `try_drain`.
+    #[deprecated(note = "use `try_drain`, which does not panic on device errors")]
+    pub fn drain<T: Into<Sysevt>>(&self, intc: &Intc, sysevt: T) -> u32 {
+        self.try_drain(intc, sysevt).unwrap()
+    }
+
+    /// Waits until the associated event out is triggered, then clears the system event and
+    /// re-enables the host interrupt.
+    ///
+    /// The returned value is the number of interrupts signaled by the kernel since the previous
+    /// call to `try_wait` or `try_drain`, which may be greater than 1 if several interrupts
+    /// accumulated in the meantime. A single clear/re-enable cycle is performed irrespective of
+    /// the number of accumulated interrupts. The count is assumed to be 1 on the first call.
+    ///
+    /// # Errors
+    ///
+    /// Errors are returned as by `try_wait`, in which case the system event is not cleared.
+    pub fn try_drain<T: Into<Sysevt>>(&self,
+                                      intc: &Intc,
+                                      sysevt: T)
+                                      -> result::Result<u32, WaitError> {
+        let (n, _) = try!(self.try_wait_counted());
+        intc.ack(sysevt, self.event);
+
+        Ok(n)
+    }
+
+    /// Re-arms the interrupt through the event out device.
+    ///
+    /// This writes 1 to the device file, which unmasks the interrupt for UIO drivers that
+    /// implement interrupt control and mask the interrupt each time it fires, such as
+    /// `uio_pdrv_genirq`. With `IrqControl::Rearm`, this is already done at the beginning of each
+    /// wait.
+    ///
+    /// Returns `false` if the driver does not support interrupt control, as is the case of
+    /// `uio_pruss`, or if the device file could not be opened for writing. Support is detected
+    /// on the first call to `rearm` or `mask`, and subsequent calls return `false` without
+    /// accessing the device if it is not supported.
+    ///
+    /// # Errors
+    ///
+    /// IO errors other than the lack of support are forwarded.
+    pub fn rearm(&self) -> io::Result<bool> {
+        self.write_irq_control(1)
+    }
+
+    /// Masks the interrupt through the event out device.
+    ///
+    /// This writes 0 to the device file so that the event out device does not signal interrupts
+    /// until `rearm` is called.
+    ///
+    /// Returns `false` if the driver does not support interrupt control, see `rearm`.
+    ///
+    /// # Errors
+    ///
+    /// IO errors other than the lack of support are forwarded.
+    pub fn mask(&self) -> io::Result<bool> {
+        self.write_irq_control(0)
+    }
+
+    /// Returns the associated event out.
+    pub fn get_evtout(&self) -> Evtout {
+        self.event
+    }
+
+    /// Starts collecting latency statistics, discarding previously collected statistics.
+    ///
+    /// The inter-event and service latencies are accumulated in histograms of `buckets` buckets
+    /// of width `bucket_width`. Timestamping each wakeup has a small cost, so statistics are
+    /// disabled by default.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `bucket_width` or `buckets` is zero.
+    pub fn enable_latency_stats(&mut self, bucket_width: Duration, buckets: usize) {
+        *self.latency.lock().unwrap() = Some(LatencyRecorder::new(bucket_width, buckets));
+    }
+
+    /// Stops collecting latency statistics.
+    pub fn disable_latency_stats(&mut self) {
+        *self.latency.lock().unwrap() = None;
+    }
+
+    /// Returns the latency statistics collected so far, or `None` if statistics are disabled.
+    pub fn latency_stats(&self) -> Option<LatencyStats> {
+        self.latency.lock().unwrap().as_ref().map(|latency| latency.stats().clone())
+    }
+
+    /// Same as `try_wait_counted`, but gives up and returns `None` if the event out is not
+    /// triggered before the timeout, if any, expires.
+    fn wait_and_count_timeout(&self,
+                              timeout: Option<Duration>)
+                              -> result::Result<Option<(u32, u32)>, WaitError> {
+        let start = Instant::now();
+        try!(self.begin_wait().map_err(WaitError::Rearm));
+        if let WaitPolicy::SpinThenBlock(ref spin) = self.policy {
+            let mut spin = *spin;
+            if let Some(timeout) = timeout {
+                spin.budget = Some(spin.budget.map_or(timeout, |budget| cmp::min(budget, timeout)));
+            }
+            wait::spin_until(&spin, || self.host_pending());
+        }
+        if let Some(timeout) = timeout {
+            loop {
+                let remaining = timeout.checked_sub(start.elapsed()).unwrap_or_default();
+                let ms = wait::poll_timeout_ms(remaining);
+                let mut pollfd = libc::pollfd {
+                    fd: self.file.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                match unsafe { libc::poll(&mut pollfd, 1, ms) } {
+                    0 => return Ok(None),
+                    // Resume waiting with the remaining time if interrupted by a signal.
+                    n if n < 0 => {
+                        let err = io::Error::last_os_error();
+                        if err.kind() != io::ErrorKind::Interrupted {
+                            return Err(WaitError::Io(err));
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        // Reads interrupted by a signal are transparently restarted.
+        Ok(Some(try!(self.read_count())))
+    }
+
+    /// Prepares a wait by recording the service latency if statistics are enabled and by
+    /// re-arming the interrupt through the event out device if required by the interrupt control
+    /// mode.
+    fn begin_wait(&self) -> io::Result<()> {
+        if let Some(ref mut latency) = *self.latency.lock().unwrap() {
+            latency.wait(Instant::now());
+        }
+        if self.irq_control == IrqControl::Rearm {
+            try!((&mut &(self.file)).write_all(&1u32.to_ne_bytes()));
+        }
+
+        Ok(())
+    }
+
+    /// Writes an interrupt control word to the event out device.
+    ///
+    /// Returns `false` if the driver does not support interrupt control, which UIO signals with
+    /// `ENOSYS`, or if the device file is not writable.
+    fn write_irq_control(&self, word: u32) -> io::Result<bool> {
+        let mut support = self.irq_control_support.lock().unwrap();
+        if *support == Some(false) {
+            return Ok(false);
+        }
+        match (&mut &(self.file)).write_all(&word.to_ne_bytes()) {
+            Ok(()) => {
+                *support = Some(true);
+                Ok(true)
+            }
+            Err(ref err) if err.raw_os_error() == Some(libc::ENOSYS) ||
+                            err.raw_os_error() == Some(libc::EBADF) => {
+                *support = Some(false);
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reads the total interrupt count from the event out device and returns the number of
+    /// interrupts since the previous read together with the total count.
+    ///
+    /// This blocks until the event out is triggered.
+    fn read_count(&self) -> io::Result<(u32, u32)> {
+        let mut buffer = [0u8; 4];
+        try!((&mut &(self.file)).read_exact(&mut buffer));
+        let total = unsafe { mem::transmute::<[u8; 4], u32>(buffer) };
+        if let Some(ref mut latency) = *self.latency.lock().unwrap() {
+            latency.wakeup(Instant::now());
+        }
+
+        let mut last_count = self.last_count.lock().unwrap();
+        let n = match *last_count {
+            Some(last) => total.wrapping_sub(last),
+            None => 1,
+        };
+        *last_count = Some(total);
+
+        Ok((n, total))
+    }
+
+    /// Checks whether an interrupt is pending for the host of the event out.
+    fn host_pending(&self) -> bool {
+        let host: Host = self.event.into();
+        unsafe {
+            let hipir = ptr::read_volatile(self.intc_reg.offset(HIPIR_REG + host as isize));
+            hipir & HIPIR_NONE_HINT == 0
+        }
+    }
+}
+
+impl AsRawFd for EvtoutIrq {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for EvtoutIrq {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+unsafe impl Send for EvtoutIrq {}
+
+unsafe impl Sync for EvtoutIrq {}
+
+
+
+// Polling interval of the PRU run state (in microseconds).
+const HALT_POLL_US: u64 = 100;
+
+
+
+/// Handle to a binary code loaded in the PRU.
+pub struct PruCode<'a> {
+    core: PruCore,
+    pructrl_reg: *mut u32,
+    prumem_base: *mut u8,
+    iram_offset: usize,
+    iram_size: usize,
+    entry: u16,
+    words: usize,
+    _pructrl_marker: PhantomData<&'a mut u32>,
+}
+
+impl<'a> PruCode<'a> {
+    fn new<'b>(loader: &PruLoader, entry: u16, words: usize) -> PruCode<'b> {
+        PruCode {
+            core: loader.core,
+            pructrl_reg: loader.pructrl_reg,
+            prumem_base: loader.prumem_base,
+            iram_offset: loader.iram_base as usize - loader.prumem_base as usize,
+            iram_size: loader.iram_size,
+            entry: entry,
+            words: words,
+            _pructrl_marker: PhantomData,
         }
     }
 
@@ -728,25 +2560,85 @@ impl<'a> PruCode<'a> {
     /// This function writes 1 to the enable bit of the PRU control register, which allows
     /// the loaded code to be started or, if it had been stopped, to resume its execution.
     ///
+    /// The program counter reset value is set to the entry point of the code and the soft reset
+    /// bit is written as 0 as with the prussdrv library, while the counter enable and single step
+    /// bits are preserved. See `resume` and `restart` for explicit resume and restart semantics.
+    ///
     /// # Safety
     ///
     /// This runs a binary code that has unrestricted access to pretty much all the processor memory
     /// and peripherals. What could possibly go wrong?
     pub unsafe fn run(&mut self) {
+        let entry = self.entry;
+        self.run_at(entry);
+    }
+
+    /// Executes the code loaded in the PRU from the specified instruction word.
+    ///
+    /// This function behaves like `run` except that the program counter reset value is set to
+    /// `start_word`, i.e. the byte address `4 * start_word` of the instruction RAM, rather than
+    /// to the entry point of the code. This allows images with several entry routines to be
+    /// started at the routine of choice.
+    ///
+    /// # Safety
+    ///
+    /// See `PruCode::run`. The start word must designate a valid instruction of the loaded code.
+    pub unsafe fn run_at(&mut self, start_word: u16) {
         // Set the enable bit of the PRU control register to start or resume code execution.
-        ptr::write_volatile(self.pructrl_reg, 2);
+        let ctrl = PruCtrl::read(self.pructrl_reg);
+        PruCtrl::default()
+            .with_counter_enable(ctrl.counter_enable())
+            .with_single_step(ctrl.single_step())
+            .with_pctr_rst_val(start_word)
+            .with_enable(true)
+            .write(self.pructrl_reg);
+    }
+
+    /// Resumes the execution of code halted in the PRU.
+    ///
+    /// Unlike `run`, this function only sets the enable bit of the PRU control register without
+    /// writing the soft reset bit as 0 or modifying the program counter reset value, so that
+    /// execution continues from the current program counter with the registers intact. This is
+    /// typically used after `halt`, or after the code was reloaded with
+    /// `PruLoader::load_code_halted`.
+    ///
+    /// # Safety
+    ///
+    /// See `PruCode::run`.
+    pub unsafe fn resume(&mut self) {
+        PruCtrl::read(self.pructrl_reg)
+            .with_soft_rst_n(true)
+            .with_enable(true)
+            .write(self.pructrl_reg);
+    }
+
+    /// Resets the PRU and executes the code from its entry point.
+    ///
+    /// Unlike `run`, the whole control register is cleared first, so the counter enable and
+    /// single step bits are not preserved.
+    ///
+    /// # Safety
+    ///
+    /// See `PruCode::run`.
+    pub unsafe fn restart(&mut self) {
+        self.reset();
+        self.run();
     }
 
     /// Halts the execution of code running in the PRU.
     ///
     /// This function simply writes 0 to the enable bit of the PRU Control Register. If code was
     /// currently running, it will be stopped. Execution of the code can be resumed with a
-    /// subsequent call to `run`.
+    /// subsequent call to `resume` or
+    /// `run`.
     pub fn halt(&mut self) {
         // Clear the enable bit of the PRU control register to start or resume code execution
         // without resetting the PRU.
         unsafe {
-            ptr::write_volatile(self.pructrl_reg, 1);
+            PruCtrl::read(self.pructrl_reg)
+                .with_soft_rst_n(true)
+                .with_enable(false)
+                .write(self.pructrl_reg);
         }
     }
 
@@ -755,7 +2647,153 @@ impl<'a> PruCode<'a> {
     /// Invokes a soft reset by clearing the PRU control register.
     pub fn reset(&mut self) {
         unsafe {
-            ptr::write_volatile(self.pructrl_reg, 0);
+            PruCtrl::default().write(self.pructrl_reg);
+        }
+    }
+
+    /// Returns the entry point of the code as a byte address in the instruction RAM.
+    ///
+    /// The entry point is 0 unless the code was loaded from an ELF executable.
+    pub fn entry_point(&self) -> u32 {
+        self.entry as u32 * 4
+    }
+
+    /// Returns the number of instruction words written to the instruction RAM when the code was
+    /// loaded.
+    pub fn word_count(&self) -> usize {
+        self.words
+    }
+
+    /// Waits until the PRU stops executing instructions, e.g. after a `HALT` instruction.
+    ///
+    /// The RUNSTATE bit of the control register is polled at short intervals until it is
+    /// cleared or until the timeout expires. Returns `true` if the PRU stopped.
+    pub fn wait_halted(&self, timeout: Duration) -> bool {
+        let start = Instant::now();
+        loop {
+            if !self.ctrl().runstate() {
+                return true;
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return false;
+            }
+            std::thread::sleep(cmp::min(timeout - elapsed, Duration::from_micros(HALT_POLL_US)));
+        }
+    }
+
+    /// Halts the PRU and returns a memory segment spanning its instruction RAM.
+    ///
+    /// This allows the loaded code to be patched, for instance to modify immediate constants.
+    /// Positions within the segment are offsets from the start of the instruction RAM, so that
+    /// the instruction at word address `n` is located at `IramAddr(4 * n)`.
+    ///
+    /// The PRU remains halted while the segment is borrowed and its execution can be resumed
+    /// with `run` once the patches are applied.
+    pub fn iram<'s>(&'s mut self) -> MemSegment<'s, IramAddr> {
+        self.halt();
+
+        MemSegment::new(unsafe { self.prumem_base.offset(self.iram_offset as isize) },
+                        0,
+                        self.iram_size)
+    }
+
+    /// Returns the current program counter of the PRU, i.e. the instruction word address being
+    /// executed.
+    ///
+    /// The program counter is read from the status register and can be sampled while the code
+    /// is running, e.g. to find out where a firmware is stuck.
+    pub fn pc(&self) -> u16 {
+        unsafe { ptr::read_volatile(self.pructrl_reg.offset(PRUSTATUS_REG)) as u16 }
+    }
+
+    /// Reads the PRU control register.
+    pub fn ctrl(&self) -> PruCtrl {
+        unsafe { PruCtrl::read(self.pructrl_reg) }
+    }
+
+    /// Modifies the PRU control register.
+    ///
+    /// The closure is passed the current content of the register and returns the new content.
+    ///
+    /// # Safety
+    ///
+    /// Setting the enable bit runs the loaded code: see `PruCode::run`.
+    pub unsafe fn modify_ctrl<F: FnOnce(PruCtrl) -> PruCtrl>(&mut self, f: F) {
+        f(PruCtrl::read(self.pructrl_reg)).write(self.pructrl_reg);
+    }
+
+    /// Enables or disables the cycle and stall counters without affecting the other bits of the
+    /// control register.
+    pub fn set_counter_enable(&mut self, enable: bool) {
+        unsafe {
+            self.modify_ctrl(|ctrl| ctrl.with_soft_rst_n(true).with_counter_enable(enable));
+        }
+    }
+
+    /// Enables the cycle and stall counters.
+    ///
+    /// This is a shorthand for `set_counter_enable(true)`.
+    pub fn enable_cycle_counter(&mut self) {
+        self.set_counter_enable(true);
+    }
+
+    /// Returns the number of cycles counted since the counters were last reset.
+    ///
+    /// The counter only advances while the counters are enabled and saturates at `u32::MAX`.
+    pub fn cycle_count(&self) -> u32 {
+        unsafe { ptr::read_volatile(self.pructrl_reg.offset(PRUCYCLE_REG)) }
+    }
+
+    /// Returns the number of cycles the PRU was stalled since the counters were last reset.
+    ///
+    /// The counter only advances while the counters are enabled and saturates at `u32::MAX`.
+    pub fn stall_count(&self) -> u32 {
+        unsafe { ptr::read_volatile(self.pructrl_reg.offset(PRUSTALL_REG)) }
+    }
+
+    /// Clears the cycle and stall counters.
+    ///
+    /// Since the counters can only be written while they are disabled, they are briefly disabled
+    /// and then re-enabled if they were enabled.
+    pub fn reset_counters(&mut self) {
+        let enabled = self.ctrl().counter_enable();
+        self.set_counter_enable(false);
+        unsafe {
+            ptr::write_volatile(self.pructrl_reg.offset(PRUCYCLE_REG), 0);
+            ptr::write_volatile(self.pructrl_reg.offset(PRUSTALL_REG), 0);
+        }
+        if enabled {
+            self.set_counter_enable(true);
+        }
+    }
+
+    /// Asks the firmware to stop and halts the PRU once the firmware has acknowledged.
+    ///
+    /// This function triggers the `sysevt` system event to notify the firmware that it should
+    /// wrap up, then waits for the firmware to acknowledge by triggering the event out monitored
+    /// by `ack`. If the acknowledgement is received before the timeout expires, the PRU is
+    /// halted; otherwise, or if waiting on the event out device fails, it is forcibly stopped
+    /// with a soft reset.
+    ///
+    /// Returns `true` if the firmware acknowledged the request. The system event that triggered
+    /// the event out is not cleared and the host interrupt is not re-enabled.
+    pub fn request_stop<T: Into<Sysevt>>(&mut self,
+                                         intc: &Intc,
+                                         sysevt: T,
+                                         ack: &EvtoutIrq,
+                                         timeout: Duration)
+                                         -> bool {
+        intc.send_sysevt(sysevt);
+        match ack.try_wait_timeout(timeout) {
+            Ok(Some(_)) => {
+                self.halt();
+                true
+            }
+            Ok(None) | Err(_) => {
+                self.reset();
+                false
+            }
         }
     }
 }
@@ -790,13 +2828,11 @@ struct SyncFile {
 }
 
 impl SyncFile {
-    fn new(path: &str) -> io::Result<SyncFile> {
-        let fd = unsafe {
-            libc::open(CString::new(path).unwrap().as_ptr(),
-                       libc::O_RDWR | libc::O_SYNC)
-        };
+    fn new<P: AsRef<Path>>(path: P) -> io::Result<SyncFile> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes()).unwrap();
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDWR | libc::O_SYNC) };
         match fd {
-            err if err < 0 => Err(io::Error::from_raw_os_error(err as i32)),
+            err if err < 0 => Err(io::Error::last_os_error()),
             _ => Ok(SyncFile { fd: fd }),
         }
     }
@@ -819,12 +2855,16 @@ struct MemMap {
 }
 
 impl MemMap {
-    fn new(fd: libc::c_int, size: usize, page: isize) -> io::Result<MemMap> {
+    fn new(fd: libc::c_int, size: usize, page: isize, populate: bool) -> io::Result<MemMap> {
+        let flags = match populate {
+            true => libc::MAP_SHARED | libc::MAP_POPULATE,
+            false => libc::MAP_SHARED,
+        };
         unsafe {
             let base = libc::mmap(ptr::null_mut(),
                                   size as libc::size_t,
                                   libc::PROT_READ | libc::PROT_WRITE,
-                                  libc::MAP_SHARED,
+                                  flags,
                                   fd,
                                   (PAGE_SIZE * page) as libc::off_t);
             if base == libc::MAP_FAILED {
@@ -837,8 +2877,20 @@ impl MemMap {
             }
         }
     }
+
+    /// Locks the mapped pages in RAM.
+    fn lock(&self) -> io::Result<()> {
+        match unsafe { libc::mlock(self.base as *const libc::c_void, self.size as libc::size_t) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
 }
 
+unsafe impl Send for MemMap {}
+
+unsafe impl Sync for MemMap {}
+
 impl Drop for MemMap {
     fn drop(&mut self) {
         unsafe {
@@ -884,6 +2936,89 @@ impl<T: Eq + BitOrAssign + From<u8> + Copy + Shl<u8, Output = T>> BitField<T> {
     }
 }
 
-type BitField32 = BitField<u32>;
-
 type BitField64 = BitField<u64>;
+
+
+/// Returns the first number which occurs several times in a list of numbers lower than `width`.
+fn find_duplicate(width: u8, numbers: &[u8]) -> Option<u8> {
+    let mut bitfield = BitField64::new(width);
+
+    numbers.iter().cloned().find(|&n| !bitfield.try_set(n))
+}
+
+
+
+/// Copies bytes to a memory location with volatile stores, using word-wide stores where aligned.
+unsafe fn volatile_copy_to(dst: *mut u8, src: &[u8]) {
+    let word_size = mem::size_of::<u32>();
+    let mut i = 0;
+    while i < src.len() && !(dst as usize + i).is_multiple_of(word_size) {
+        ptr::write_volatile(dst.offset(i as isize), src[i]);
+        i += 1;
+    }
+    while i + word_size <= src.len() {
+        let word = ptr::read_unaligned(src.as_ptr().offset(i as isize) as *const u32);
+        ptr::write_volatile(dst.offset(i as isize) as *mut u32, word);
+        i += word_size;
+    }
+    while i < src.len() {
+        ptr::write_volatile(dst.offset(i as isize), src[i]);
+        i += 1;
+    }
+}
+
+/// Copies bytes from a memory location with volatile loads, using word-wide loads where aligned.
+unsafe fn volatile_copy_from(dst: &mut [u8], src: *const u8) {
+    let word_size = mem::size_of::<u32>();
+    let mut i = 0;
+    while i < dst.len() && !(src as usize + i).is_multiple_of(word_size) {
+        dst[i] = ptr::read_volatile(src.offset(i as isize));
+        i += 1;
+    }
+    while i + word_size <= dst.len() {
+        let word = ptr::read_volatile(src.offset(i as isize) as *const u32);
+        ptr::write_unaligned(dst.as_mut_ptr().offset(i as isize) as *mut u32, word);
+        i += word_size;
+    }
+    while i < dst.len() {
+        dst[i] = ptr::read_volatile(src.offset(i as isize));
+        i += 1;
+    }
+}
+
+/// Fills a memory location with a byte using volatile stores, using word-wide stores where
+/// aligned.
+unsafe fn volatile_fill(dst: *mut u8, len: usize, byte: u8) {
+    let word_size = mem::size_of::<u32>();
+    let word = u32::from_ne_bytes([byte; 4]);
+    let mut i = 0;
+    while i < len && !(dst as usize + i).is_multiple_of(word_size) {
+        ptr::write_volatile(dst.offset(i as isize), byte);
+        i += 1;
+    }
+    while i + word_size <= len {
+        ptr::write_volatile(dst.offset(i as isize) as *mut u32, word);
+        i += word_size;
+    }
+    while i < len {
+        ptr::write_volatile(dst.offset(i as isize), byte);
+        i += 1;
+    }
+}
+
+/// Returns the masks of the system events 0 to 31 and 32 to 63.
+fn sysevt_masks(sysevts: &[Sysevt]) -> (u32, u32) {
+    sysevts.iter().fold((0, 0), |(low, high), &sysevt| set_sysevt_bit(low, high, sysevt as u8))
+}
+
+/// Same as `sysevt_masks`, with system event numbers.
+fn sysevt_bits(sysevts: &[u8]) -> (u32, u32) {
+    sysevts.iter().fold((0, 0), |(low, high), &sysevt| set_sysevt_bit(low, high, sysevt))
+}
+
+fn set_sysevt_bit(low: u32, high: u32, sysevt: u8) -> (u32, u32) {
+    match sysevt {
+        se @ 0..=31 => (low | 1 << se, high),
+        se => (low, high | 1 << (se - 32)),
+    }
+}
@@ -0,0 +1,517 @@
+//! Import of the memory placement of clpru-linked firmwares.
+//!
+//! The TI PRU linker places the sections and global variables of a firmware according to a
+//! linker command file (`.cmd`) and reports the resulting placement in a map file (`.map`). The
+//! placement can be imported on the host side so that memory segments shared with the firmware
+//! are allocated exactly where the firmware expects them and never overlap its data sections:
+//!
+//! ```no_run
+//! # use prusst::{Pruss, IntcConfig, PruCore};
+//! # use prusst::linker::LinkerMap;
+//! # let mut pruss = Pruss::new(&IntcConfig::new_populated()).unwrap();
+//! let map = LinkerMap::open("firmware/app.map").unwrap();
+//! let (_firmware_data, _free) = map.split_reserved(PruCore::Pru0, &mut pruss.dram0);
+//! ```
+//!
+//! Addresses in command and map files are local addresses of the PRU core the firmware was
+//! linked for (see `PruAddr::local_to`). Program memory (page 0) is distinguished from data
+//! memory (other pages) by its page number.
+
+use {MemSegment, PruAddr, PruCore};
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+
+
+/// Memory range declared in the `MEMORY` directive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryRange {
+    /// Name of the range.
+    pub name: String,
+    /// Page number, 0 being the program memory.
+    pub page: u32,
+    /// Start address.
+    pub origin: u32,
+    /// Length in bytes.
+    pub length: u32,
+}
+
+
+
+/// Placement of an output section declared in the `SECTIONS` directive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Placement {
+    /// Name of the output section.
+    pub section: String,
+    /// Name of the memory range or address at which the section is placed.
+    pub target: String,
+    /// Page number, if specified.
+    pub page: Option<u32>,
+}
+
+
+
+/// Linker command file.
+///
+/// Only the `MEMORY` and `SECTIONS` directives are interpreted; linker options and complex
+/// section specifications (`GROUP`, `UNION`...) are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct LinkerCommand {
+    /// Memory ranges.
+    pub memory: Vec<MemoryRange>,
+    /// Section placements.
+    pub sections: Vec<Placement>,
+}
+
+impl LinkerCommand {
+    /// Reads a linker command file.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded. An error of the kind `ErrorKind::InvalidData` is returned if the
+    /// file does not contain a valid `MEMORY` directive.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<LinkerCommand> {
+        LinkerCommand::parse(&try!(read_file(path)))
+    }
+
+    /// Parses a linker command file.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the file does not contain a
+    /// valid `MEMORY` directive.
+    pub fn parse(text: &str) -> io::Result<LinkerCommand> {
+        let text = strip_comments(text);
+        let memory = try!(directive(&text, "MEMORY")
+            .ok_or_else(|| error("missing MEMORY directive")));
+        let mut command = LinkerCommand::default();
+
+        let mut page = 0;
+        for line in memory.lines() {
+            let mut line = line.trim();
+            if let Some(rest) = line.strip_prefix("PAGE") {
+                let colon = try!(rest.find(':').ok_or_else(|| error("malformed PAGE specifier")));
+                page = try!(parse_number(rest[..colon].trim())
+                    .ok_or_else(|| error("malformed PAGE specifier")));
+                line = rest[colon + 1..].trim();
+            }
+            if line.is_empty() {
+                continue;
+            }
+            command.memory.push(try!(memory_range(line, page)
+                .ok_or_else(|| error(&format!("malformed memory range: {}", line)))));
+        }
+
+        if let Some(sections) = directive(&text, "SECTIONS") {
+            for line in sections.lines() {
+                if let Some(placement) = placement(line) {
+                    command.sections.push(placement);
+                }
+            }
+        }
+
+        Ok(command)
+    }
+
+    /// Returns the memory range with the specified name, if any.
+    pub fn memory_range(&self, name: &str) -> Option<&MemoryRange> {
+        self.memory.iter().find(|range| range.name == name)
+    }
+}
+
+
+
+/// Output section reported in a map file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    /// Name of the output section.
+    pub name: String,
+    /// Page number, 0 being the program memory.
+    pub page: u32,
+    /// Start address.
+    pub origin: u32,
+    /// Length in bytes.
+    pub length: u32,
+}
+
+
+
+/// Global symbol reported in a map file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// Name of the symbol.
+    pub name: String,
+    /// Page number, if reported.
+    pub page: Option<u32>,
+    /// Address of the symbol.
+    pub address: u32,
+}
+
+
+
+/// Linker map file.
+#[derive(Clone, Debug, Default)]
+pub struct LinkerMap {
+    /// Memory configuration.
+    pub memory: Vec<MemoryRange>,
+    /// Allocated output sections.
+    pub sections: Vec<Section>,
+    /// Global symbols.
+    pub symbols: Vec<Symbol>,
+}
+
+impl LinkerMap {
+    /// Reads a map file.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded. An error of the kind `ErrorKind::InvalidData` is returned if the
+    /// file does not contain a memory configuration.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<LinkerMap> {
+        LinkerMap::parse(&try!(read_file(path)))
+    }
+
+    /// Parses a map file.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the file does not contain a
+    /// memory configuration.
+    pub fn parse(text: &str) -> io::Result<LinkerMap> {
+        enum Part {
+            Preamble,
+            Memory,
+            Sections,
+            Symbols,
+        }
+
+        let mut map = LinkerMap::default();
+        let mut part = Part::Preamble;
+        let mut page = 0;
+        let mut pending_name: Option<String> = None;
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("MEMORY CONFIGURATION") {
+                part = Part::Memory;
+                continue;
+            }
+            if trimmed.starts_with("SECTION ALLOCATION MAP") {
+                part = Part::Sections;
+                continue;
+            }
+            if trimmed.starts_with("GLOBAL SYMBOLS") {
+                part = Part::Symbols;
+                continue;
+            }
+            if trimmed.starts_with("LINKER GENERATED") {
+                part = Part::Preamble;
+                continue;
+            }
+            match part {
+                Part::Preamble => {}
+                Part::Memory => {
+                    let mut fields = trimmed;
+                    if let Some(rest) = fields.strip_prefix("PAGE") {
+                        if let Some(colon) = rest.find(':') {
+                            if let Some(p) = parse_number(rest[..colon].trim()) {
+                                page = p;
+                            }
+                            fields = rest[colon + 1..].trim();
+                        }
+                    }
+                    let words: Vec<&str> = fields.split_whitespace().collect();
+                    if words.len() >= 3 {
+                        if let (Some(origin), Some(length)) = (parse_hex(words[1]),
+                                                               parse_hex(words[2])) {
+                            map.memory.push(MemoryRange {
+                                name: words[0].to_string(),
+                                page: page,
+                                origin: origin,
+                                length: length,
+                            });
+                        }
+                    }
+                }
+                Part::Sections => {
+                    // Input sections are indented and are not reported.
+                    if line.starts_with(char::is_whitespace) || trimmed.is_empty() {
+                        continue;
+                    }
+                    let words: Vec<&str> = trimmed.split_whitespace().collect();
+                    // Long section names are reported on their own line, the rest of the
+                    // description following on the next line after a `*` placeholder.
+                    if words.len() == 1 {
+                        pending_name = Some(words[0].to_string());
+                        continue;
+                    }
+                    let name = match pending_name.take() {
+                        Some(name) if words[0] == "*" => name,
+                        _ => words[0].to_string(),
+                    };
+                    if words.len() >= 4 {
+                        if let (Some(page), Some(origin), Some(length)) =
+                               (parse_number(words[1]), parse_hex(words[2]), parse_hex(words[3])) {
+                            map.sections.push(Section {
+                                name: name,
+                                page: page,
+                                origin: origin,
+                                length: length,
+                            });
+                        }
+                    }
+                }
+                Part::Symbols => {
+                    let words: Vec<&str> = trimmed.split_whitespace().collect();
+                    let symbol = match words.len() {
+                        2 => {
+                            parse_hex(words[0]).map(|address| {
+                                Symbol {
+                                    name: words[1].to_string(),
+                                    page: None,
+                                    address: address,
+                                }
+                            })
+                        }
+                        3 => {
+                            match (parse_number(words[0]), parse_hex(words[1])) {
+                                (Some(page), Some(address)) => {
+                                    Some(Symbol {
+                                        name: words[2].to_string(),
+                                        page: Some(page),
+                                        address: address,
+                                    })
+                                }
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    // Symbols are listed twice, sorted by name and by address.
+                    if let Some(symbol) = symbol {
+                        if map.symbol(&symbol.name).is_none() {
+                            map.symbols.push(symbol);
+                        }
+                    }
+                }
+            }
+        }
+
+        if map.memory.is_empty() {
+            return Err(error("missing memory configuration"));
+        }
+
+        Ok(map)
+    }
+
+    /// Returns the memory range with the specified name, if any.
+    pub fn memory_range(&self, name: &str) -> Option<&MemoryRange> {
+        self.memory.iter().find(|range| range.name == name)
+    }
+
+    /// Returns the output section with the specified name, if any.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// Returns the global symbol with the specified name, if any.
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Returns the address of a global symbol in the local address space of PRU0, given the core
+    /// the firmware was linked for.
+    pub fn symbol_addr(&self, core: PruCore, name: &str) -> Option<PruAddr> {
+        self.symbol(name).map(|symbol| PruAddr::from_local(core, symbol.address))
+    }
+
+    /// Returns the address ranges occupied by non-empty data sections in the local address space
+    /// of PRU0, given the core the firmware was linked for.
+    ///
+    /// Ranges are sorted by start address.
+    pub fn reserved_ranges(&self, core: PruCore) -> Vec<(PruAddr, PruAddr)> {
+        let mut ranges: Vec<(PruAddr, PruAddr)> = self.sections
+            .iter()
+            .filter(|section| section.page != 0 && section.length != 0)
+            .map(|section| {
+                let begin = PruAddr::from_local(core, section.origin);
+                (begin, begin + section.length as usize)
+            })
+            .collect();
+        ranges.sort();
+
+        ranges
+    }
+
+    /// Splits a data RAM segment at the end of the last data section located within the segment.
+    ///
+    /// The first segment spans the data sections of the firmware and the second segment the
+    /// memory left unused by the firmware, which can be safely allocated by the host. If no data
+    /// section lies within the segment, the first segment is empty.
+    pub fn split_reserved<'b, 'a>(&self,
+                                  core: PruCore,
+                                  segment: &'b mut MemSegment<'a, PruAddr>)
+                                  -> (MemSegment<'b, PruAddr>, MemSegment<'b, PruAddr>) {
+        let begin = segment.begin();
+        let end = segment.end();
+        let position = self.reserved_ranges(core)
+            .into_iter()
+            .filter(|&(from, to)| from < end && to > begin)
+            .map(|(_, to)| if to < end { to } else { end })
+            .max()
+            .unwrap_or(begin);
+
+        segment.split_at(position)
+    }
+}
+
+
+
+// Parses a memory range specification such as `NAME (RWX) : org = 0x0, len = 0x2000`.
+fn memory_range(line: &str, page: u32) -> Option<MemoryRange> {
+    let colon = line.find(':')?;
+    let name = line[..colon].split(|c: char| c.is_whitespace() || c == '(').next().unwrap_or("");
+    let spec = line[colon + 1..].replace('=', " = ").replace(',', " ");
+    let words: Vec<&str> = spec.split_whitespace().collect();
+    let mut origin = None;
+    let mut length = None;
+    for assignment in words.windows(3).filter(|w| w[1] == "=") {
+        let value = parse_number(assignment[2]);
+        match assignment[0] {
+            "o" | "org" | "origin" => origin = value,
+            "l" | "len" | "length" => length = value,
+            _ => {}
+        }
+    }
+
+    match (name.is_empty(), origin, length) {
+        (false, Some(origin), Some(length)) => {
+            Some(MemoryRange {
+                name: name.to_string(),
+                page: page,
+                origin: origin,
+                length: length,
+            })
+        }
+        _ => None,
+    }
+}
+
+
+// Parses a section placement such as `.bss > PRU_DMEM_0_1, PAGE 1`.
+fn placement(line: &str) -> Option<Placement> {
+    let arrow = line.find('>')?;
+    let section = line[..arrow].trim().trim_end_matches(':').trim();
+    if section.is_empty() || section.contains(['{', '}']) {
+        return None;
+    }
+    let mut parts = line[arrow + 1..].split(',');
+    let target = parts.next().unwrap_or("").trim();
+    let page = parts.filter_map(|part| part.trim().strip_prefix("PAGE"))
+        .filter_map(|page| parse_number(page.trim()))
+        .next();
+
+    Some(Placement {
+        section: section.to_string(),
+        target: target.to_string(),
+        page: page,
+    })
+}
+
+
+// Returns the content of the braces following the directive keyword.
+fn directive<'t>(text: &'t str, keyword: &str) -> Option<&'t str> {
+    let mut start = 0;
+    while let Some(pos) = text[start..].find(keyword) {
+        let pos = start + pos;
+        start = pos + keyword.len();
+        let preceded = text[..pos]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let rest = text[start..].trim_start();
+        if preceded || !rest.starts_with('{') {
+            continue;
+        }
+        let open = text.len() - rest.len();
+        let mut depth = 0;
+        for (i, c) in text[open..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(&text[open + 1..open + i]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        return None;
+    }
+
+    None
+}
+
+
+// Removes C and C++ style comments.
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    loop {
+        let block = rest.find("/*");
+        let line = rest.find("//");
+        match (block, line) {
+            (Some(b), l) if l.is_none_or(|l| b < l) => {
+                stripped.push_str(&rest[..b]);
+                rest = match rest[b + 2..].find("*/") {
+                    Some(end) => &rest[b + 2 + end + 2..],
+                    None => "",
+                };
+            }
+            (_, Some(l)) => {
+                stripped.push_str(&rest[..l]);
+                rest = match rest[l..].find('\n') {
+                    Some(end) => &rest[l + end..],
+                    None => "",
+                };
+            }
+            _ => {
+                stripped.push_str(rest);
+                return stripped;
+            }
+        }
+    }
+}
+
+
+// Parses a decimal or hexadecimal (`0x` prefix or `h` suffix) number.
+fn parse_number(word: &str) -> Option<u32> {
+    if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = word.strip_suffix('h').or_else(|| word.strip_suffix('H')) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        word.parse().ok()
+    }
+}
+
+
+// Parses a hexadecimal number without prefix, as reported in map files.
+fn parse_hex(word: &str) -> Option<u32> {
+    u32::from_str_radix(word, 16).ok()
+}
+
+
+fn read_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+
+    Ok(text)
+}
+
+
+fn error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,120 @@
+//! Mutual exclusion between the host and a PRU firmware.
+//!
+//! Short critical sections over structures shared with the firmware can be protected with a
+//! `SpinLock` allocated in PRU memory. Since neither the PRU nor the host can perform an atomic
+//! read-modify-write access on PRU memory, the lock does not rely on a test-and-set instruction
+//! but implements Peterson's algorithm, which only requires plain loads and stores of three
+//! words:
+//!
+//! ```c
+//! struct spinlock {
+//!     volatile uint32_t host;   /* 1 while the host wants or holds the lock */
+//!     volatile uint32_t pru;    /* 1 while the PRU wants or holds the lock */
+//!     volatile uint32_t turn;   /* party with priority if both want it: 0 = host, 1 = PRU */
+//! };
+//! ```
+//!
+//! Each party gives the priority to the other one when requesting the lock, so the host spins
+//! while the PRU wants the lock and `turn` is 1, and the PRU spins while the host wants the lock
+//! and `turn` is 0. The firmware must acquire and release the lock as follows, with all accesses
+//! performed in program order, which is guaranteed by the PRU for volatile accesses:
+//!
+//! ```c
+//! lock->pru = 1;
+//! lock->turn = 0;
+//! while (lock->host == 1 && lock->turn == 0)
+//!     ;
+//! /* critical section */
+//! lock->pru = 0;
+//! ```
+//!
+//! The lock only supports a single host thread and a single PRU core.
+
+use util::VolatileCell;
+use wait::{self, SpinPolicy};
+
+use std::sync::atomic::{self, Ordering};
+use std::time::Duration;
+
+
+// Values of the `turn` word.
+const TURN_HOST: u32 = 0;
+const TURN_PRU: u32 = 1;
+
+
+
+/// Lock shared between the host and a PRU firmware.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SpinLock {
+    host: VolatileCell<u32>,
+    pru: VolatileCell<u32>,
+    turn: VolatileCell<u32>,
+}
+
+impl SpinLock {
+    /// Creates an unlocked lock, typically to be allocated with `MemSegment::alloc` before the
+    /// firmware is started.
+    pub fn new() -> SpinLock {
+        SpinLock {
+            host: VolatileCell::new(0),
+            pru: VolatileCell::new(0),
+            turn: VolatileCell::new(TURN_HOST),
+        }
+    }
+
+    /// Acquires the lock, spinning until it is released by the firmware.
+    pub fn lock<'l>(&'l mut self) -> SpinLockGuard<'l> {
+        self.acquire(None).unwrap()
+    }
+
+    /// Acquires the lock, spinning at most for the specified duration.
+    ///
+    /// Returns `None` if the lock is still held by the firmware when the timeout expires.
+    pub fn try_lock_for<'l>(&'l mut self, timeout: Duration) -> Option<SpinLockGuard<'l>> {
+        self.acquire(Some(timeout))
+    }
+
+    fn acquire<'l>(&'l mut self, timeout: Option<Duration>) -> Option<SpinLockGuard<'l>> {
+        self.host.set(1);
+        self.turn.set(TURN_PRU);
+        // The stores above must be visible to the PRU before the flag of the PRU is read.
+        atomic::fence(Ordering::SeqCst);
+        let policy = SpinPolicy {
+            budget: timeout,
+            pause: true,
+        };
+        let acquired = {
+            let lock: &SpinLock = self;
+            wait::spin_until(&policy, || lock.pru.get() == 0 || lock.turn.get() != TURN_PRU)
+        };
+        if !acquired {
+            self.host.set(0);
+            return None;
+        }
+        atomic::fence(Ordering::SeqCst);
+
+        Some(SpinLockGuard { lock: self })
+    }
+}
+
+impl Default for SpinLock {
+    fn default() -> SpinLock {
+        SpinLock::new()
+    }
+}
+
+
+
+/// Scoped ownership of a `SpinLock`, released when the guard is dropped.
+pub struct SpinLockGuard<'l> {
+    lock: &'l mut SpinLock,
+}
+
+impl<'l> Drop for SpinLockGuard<'l> {
+    fn drop(&mut self) {
+        // Accesses made within the critical section must complete before the lock is released.
+        atomic::fence(Ordering::SeqCst);
+        self.lock.host.set(0);
+    }
+}
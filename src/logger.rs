@@ -0,0 +1,256 @@
+//! Logging of streamed PRU samples to disk.
+//!
+//! A `DataLogger` drains a `StreamReader` into host memory buffers which are written to their
+//! destination on a background thread, so that slow I/O does not delay the draining of the ring
+//! buffer. Two buffers are used: one is filled by the acquisition thread while the other is
+//! being written. When the writer falls behind, the logger stops draining the ring buffer until a
+//! buffer becomes available again and accounts for the stall in its statistics.
+//!
+//! For use in time-critical loops, the buffers can be pre-faulted and locked in RAM with
+//! `DataLogger::locked` so that filling them never incurs a page fault.
+
+use stream::StreamReader;
+
+use libc;
+
+use std::io::{self, Write};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+
+
+/// Statistics of a data logger.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoggerStats {
+    /// Number of bytes drained from the ring buffer.
+    pub bytes_logged: u64,
+    /// Number of buffers handed over to the writer thread.
+    pub buffers_submitted: u64,
+    /// Number of times the ring buffer could not be drained because both buffers were full.
+    pub stalls: u64,
+    /// Number of bytes dropped by the firmware because the ring buffer was full, as last reported
+    /// by the ring buffer.
+    pub overruns: u32,
+    /// High watermark of the ring buffer, as last reported by the ring buffer.
+    pub high_watermark: usize,
+}
+
+
+
+// Logger buffer, unlocked when dropped if it was locked in RAM.
+struct Buffer {
+    data: Vec<u8>,
+    locked: bool,
+}
+
+impl Buffer {
+    fn new(size: usize) -> Buffer {
+        Buffer {
+            data: Vec::with_capacity(size),
+            locked: false,
+        }
+    }
+
+    fn locked(size: usize) -> io::Result<Buffer> {
+        let mut buffer = Buffer::new(size);
+        // Pre-fault the pages before locking them.
+        buffer.data.resize(size, 0);
+        buffer.data.clear();
+        let data = buffer.data.as_ptr() as *const libc::c_void;
+        if unsafe { libc::mlock(data, buffer.data.capacity() as libc::size_t) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buffer.locked = true;
+
+        Ok(buffer)
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if self.locked {
+            unsafe {
+                libc::munlock(self.data.as_ptr() as *const libc::c_void,
+                              self.data.capacity() as libc::size_t);
+            }
+        }
+    }
+}
+
+
+
+/// Double-buffered logger writing streamed samples on a background thread.
+pub struct DataLogger<W: Write + Send + 'static> {
+    current: Buffer,
+    buffer_size: usize,
+    filled: Option<SyncSender<Buffer>>,
+    free: Receiver<Buffer>,
+    writer: Option<JoinHandle<io::Result<W>>>,
+    stats: LoggerStats,
+}
+
+impl<W: Write + Send + 'static> DataLogger<W> {
+    /// Creates a logger with two buffers of `buffer_size` bytes and spawns its writer thread.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the buffer size is zero.
+    pub fn new(writer: W, buffer_size: usize) -> DataLogger<W> {
+        let current = Buffer::new(buffer_size);
+        let free = Buffer::new(buffer_size);
+
+        DataLogger::with_buffers(writer, buffer_size, current, free)
+    }
+
+    /// Creates a logger with two buffers of `buffer_size` bytes pre-faulted and locked in RAM,
+    /// and spawns its writer thread.
+    ///
+    /// This uses `mlock` to make sure that the buffer pages are resident and remain so. Note that
+    /// locking memory may require elevated privileges or a sufficient `RLIMIT_MEMLOCK` limit.
+    ///
+    /// # Errors
+    ///
+    /// OS errors that may occur while locking the buffers are forwarded.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the buffer size is zero.
+    pub fn locked(writer: W, buffer_size: usize) -> io::Result<DataLogger<W>> {
+        let current = try!(Buffer::locked(buffer_size));
+        let free = try!(Buffer::locked(buffer_size));
+
+        Ok(DataLogger::with_buffers(writer, buffer_size, current, free))
+    }
+
+    fn with_buffers(writer: W, buffer_size: usize, current: Buffer, free: Buffer) -> DataLogger<W> {
+        assert!(buffer_size != 0);
+        let (filled_tx, filled_rx) = mpsc::sync_channel::<Buffer>(2);
+        let (free_tx, free_rx) = mpsc::channel();
+        free_tx.send(free).unwrap();
+        let handle = thread::spawn(move || {
+            let mut writer = writer;
+            for mut buf in filled_rx {
+                try!(writer.write_all(&buf.data));
+                buf.data.clear();
+                let _ = free_tx.send(buf);
+            }
+            try!(writer.flush());
+
+            Ok(writer)
+        });
+
+        DataLogger {
+            current: current,
+            buffer_size: buffer_size,
+            filled: Some(filled_tx),
+            free: free_rx,
+            writer: Some(handle),
+            stats: LoggerStats::default(),
+        }
+    }
+
+    /// Drains the ring buffer and returns the number of bytes drained.
+    ///
+    /// This function never blocks and should be called often enough to prevent the ring buffer
+    /// from overflowing.
+    ///
+    /// # Errors
+    ///
+    /// This function returns the error encountered by the writer thread, if any.
+    pub fn poll(&mut self, reader: &mut StreamReader) -> io::Result<usize> {
+        let mut drained = 0;
+        loop {
+            if self.current.data.len() == self.buffer_size && !try!(self.submit(false)) {
+                self.stats.stalls += 1;
+                break;
+            }
+            let start = self.current.data.len();
+            self.current.data.resize(self.buffer_size, 0);
+            let n = reader.read_available(&mut self.current.data[start..]);
+            self.current.data.truncate(start + n);
+            drained += n;
+            if n == 0 {
+                break;
+            }
+        }
+        self.stats.bytes_logged += drained as u64;
+        self.stats.overruns = reader.overruns();
+        self.stats.high_watermark = reader.high_watermark();
+
+        Ok(drained)
+    }
+
+    /// Hands the partially filled buffer over to the writer thread, waiting for a buffer to
+    /// become available if necessary.
+    ///
+    /// # Errors
+    ///
+    /// This function returns the error encountered by the writer thread, if any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.current.data.is_empty() {
+            try!(self.submit(true));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the logger statistics.
+    pub fn stats(&self) -> LoggerStats {
+        self.stats
+    }
+
+    /// Flushes the logger, waits until all data has been written and returns the writer together
+    /// with the final statistics.
+    ///
+    /// # Errors
+    ///
+    /// This function returns the error encountered by the writer thread, if any.
+    pub fn finish(mut self) -> io::Result<(W, LoggerStats)> {
+        try!(self.flush());
+        self.filled = None;
+        let writer = try!(self.join());
+
+        Ok((writer, self.stats))
+    }
+
+    // Swaps the current buffer with a free buffer and submits it to the writer thread. Returns
+    // `false` if no buffer is available and `wait` is not set.
+    fn submit(&mut self, wait: bool) -> io::Result<bool> {
+        let buf = if wait {
+            self.free.recv().ok()
+        } else {
+            match self.free.try_recv() {
+                Ok(buf) => Some(buf),
+                Err(TryRecvError::Empty) => return Ok(false),
+                Err(TryRecvError::Disconnected) => None,
+            }
+        };
+        match buf {
+            Some(buf) => {
+                let full = mem::replace(&mut self.current, buf);
+                if self.filled.as_ref().unwrap().send(full).is_err() {
+                    return self.join().map(|_| false);
+                }
+                self.stats.buffers_submitted += 1;
+
+                Ok(true)
+            }
+            // The writer thread has terminated, which only happens on error.
+            None => self.join().map(|_| false),
+        }
+    }
+
+    // Waits for the termination of the writer thread and returns its result.
+    fn join(&mut self) -> io::Result<W> {
+        match self.writer.take() {
+            Some(handle) => {
+                match handle.join() {
+                    Ok(result) => result,
+                    Err(_) => Err(io::Error::other("data logger writer thread panicked")),
+                }
+            }
+            None => Err(io::Error::other("data logger writer thread has terminated")),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Macros.
+
+
+/// Declares semantically named system events.
+///
+/// Each name is turned into a unit struct that converts into its `Sysevt` and implements
+/// `Display` with the name of the event, so that application code and logs can refer to
+/// events by their meaning rather than by their raw system event number. The underlying system
+/// event is also available as the `SYSEVT` associated constant.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate prusst;
+///
+/// use prusst::Sysevt;
+///
+/// pru_events! {
+///     /// Raised by PRU0 each time the LED is switched on.
+///     pub LedBlinked = Sysevt::S19,
+///     pub Done = Sysevt::S20,
+/// }
+///
+/// fn main() {
+///     assert_eq!(LedBlinked::SYSEVT as u8, 19);
+///     assert_eq!(format!("{}", Done), "Done");
+/// }
+/// ```
+///
+/// Since the `Intc` methods dealing with system events accept any type convertible into a
+/// `Sysevt`, the named events can be used directly, e.g. `pruss.intc.clear_sysevt(LedBlinked)`.
+#[macro_export]
+macro_rules! pru_events {
+    ($($(#[$attr:meta])* $vis:vis $name:ident = $sysevt:expr),* $(,)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Copy, Clone)]
+            $vis struct $name;
+
+            impl $name {
+                /// The underlying system event.
+                #[allow(dead_code)]
+                pub const SYSEVT: $crate::Sysevt = $sysevt;
+            }
+
+            impl ::std::convert::From<$name> for $crate::Sysevt {
+                fn from(_: $name) -> $crate::Sysevt {
+                    $sysevt
+                }
+            }
+
+            impl ::std::fmt::Display for $name {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                    f.write_str(stringify!($name))
+                }
+            }
+        )*
+    };
+}
@@ -0,0 +1,624 @@
+//! Manifest-driven application bring-up.
+//!
+//! A manifest describes in a single TOML file everything needed to bring up a PRU application:
+//! the firmware of each PRU, the interrupt controller mapping, the names of the memory areas
+//! shared with the firmware and the pin multiplexing requirements. For instance:
+//!
+//! ```toml
+//! [pru0]
+//! firmware = "myapp-pru0"     # firmware name or path (see `Firmware`)
+//! digest = "crc32:1c291ca3"   # optional digest of the firmware file (see `Digest`)
+//!
+//! [pru1]
+//! firmware = "myapp-pru1"
+//! run = false                 # load the code but do not start it (default: true)
+//!
+//! [intc]
+//! channels = [[16, 2], [17, 0]]   # system event to channel mapping
+//! hosts = [[2, 2], [0, 0]]        # channel to host mapping
+//! sysevts = [16, 17]              # enabled system events (default: all mapped)
+//! enabled_hosts = [2, 0]          # enabled host interrupts (default: all mapped)
+//!
+//! [memory.samples]
+//! ram = "dram2"                   # one of "dram0", "dram1", "dram2" and "hostram"
+//! offset = 0x100
+//! size = 4096
+//!
+//! [pins]
+//! P9_31 = "pruout"
+//! ```
+//!
+//! When the `intc` table is omitted, the default mapping of `IntcConfig::new_populated` is used.
+//!
+//! A firmware can also ship its own interrupt requirements in a `FirmwareManifest`, which only
+//! holds an `intc` table with the format above. The requirements are then kept alongside the
+//! firmware rather than duplicated in each application.
+//!
+//! Manifests are parsed with the `toml` crate, so this module requires the `manifest` feature.
+
+use {Pruss, PrussBuilder, IntcConfig, Intc, Firmware, PruLoader, PruCode, Sysevt, Channel, Host,
+     OutOfRangeError};
+use digest::Digest;
+use def::{CMR_REG, HMR_REG, ESR1_REG, ESR2_REG, HIER_REG};
+
+use serde::Deserialize;
+use toml;
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::ptr;
+
+
+// Path of the pin multiplexing state attribute of the BeagleBone cape-universal overlay.
+static PINMUX_STATE_PATH: &'static str = "/sys/devices/platform/ocp/ocp:{}_pinmux/state";
+
+
+
+/// Firmware of a PRU.
+#[derive(Clone, Debug)]
+pub struct PruImage {
+    /// Name or path of the firmware; names are resolved with `Firmware::load` and values
+    /// containing a `/` are interpreted as paths.
+    pub firmware: String,
+    /// Expected digest of the firmware file, if any.
+    pub digest: Option<Digest>,
+    /// Whether the code should be started once loaded.
+    pub run: bool,
+}
+
+
+
+/// PRU memory holding a memory area.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ram {
+    Dram0,
+    Dram1,
+    Dram2,
+    Hostram,
+}
+
+
+
+/// Named memory area shared with the firmware.
+#[derive(Clone, Debug)]
+pub struct MemoryArea {
+    /// Name of the area.
+    pub name: String,
+    /// Memory holding the area.
+    pub ram: Ram,
+    /// Offset of the area from the beginning of the memory (in bytes).
+    pub offset: usize,
+    /// Size of the area (in bytes).
+    pub size: usize,
+}
+
+
+
+/// Application manifest.
+#[derive(Clone)]
+pub struct Manifest {
+    /// Firmware of PRU0, if any.
+    pub pru0: Option<PruImage>,
+    /// Firmware of PRU1, if any.
+    pub pru1: Option<PruImage>,
+    /// Interrupt controller configuration.
+    pub intc: IntcConfig,
+    /// Named memory areas, sorted by name.
+    pub memory: Vec<MemoryArea>,
+    /// Required pin multiplexing modes, by pin name.
+    pub pins: Vec<(String, String)>,
+}
+
+impl Manifest {
+    /// Reads a manifest from a file.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded. An error of the kind `ErrorKind::InvalidData` is returned if the
+    /// manifest is malformed.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Manifest> {
+        let mut text = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut text));
+
+        Manifest::parse(&text)
+    }
+
+    /// Parses a manifest.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the manifest is malformed.
+    pub fn parse(text: &str) -> io::Result<Manifest> {
+        let file: ManifestFile = try!(toml::from_str(text).map_err(|err| error(err.to_string())));
+
+        Ok(Manifest {
+            pru0: match file.pru0 {
+                Some(table) => Some(try!(table.into_image("pru0"))),
+                None => None,
+            },
+            pru1: match file.pru1 {
+                Some(table) => Some(try!(table.into_image("pru1"))),
+                None => None,
+            },
+            intc: match file.intc {
+                Some(table) => try!(table.into_manifest()).intc_config(),
+                None => IntcConfig::new_populated(),
+            },
+            memory: file.memory
+                .into_iter()
+                .map(|(name, table)| {
+                    MemoryArea {
+                        name: name,
+                        ram: table.ram,
+                        offset: table.offset,
+                        size: table.size,
+                    }
+                })
+                .collect(),
+            pins: file.pins.into_iter().collect(),
+        })
+    }
+
+    /// Returns the memory area with the specified name, if any.
+    pub fn memory_area(&self, name: &str) -> Option<&MemoryArea> {
+        self.memory.iter().find(|area| area.name == name)
+    }
+
+    /// Checks that all memory areas fit within their memory in the specified PRU subsystem.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if a memory area overflows its
+    /// memory.
+    pub fn check_memory(&self, pruss: &Pruss) -> io::Result<()> {
+        for area in &self.memory {
+            let (len, ram) = match area.ram {
+                Ram::Dram0 => (pruss.dram0.to - pruss.dram0.from, "dram0"),
+                Ram::Dram1 => (pruss.dram1.to - pruss.dram1.from, "dram1"),
+                Ram::Dram2 => (pruss.dram2.to - pruss.dram2.from, "dram2"),
+                Ram::Hostram => (pruss.hostram.to - pruss.hostram.from, "hostram"),
+            };
+            if area.offset.checked_add(area.size).is_none_or(|end| end > len) {
+                return Err(error(format!("memory area `{}` overflows {} ({} bytes)",
+                                         area.name,
+                                         ram,
+                                         len)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that all pins are set to their required multiplexing mode.
+    ///
+    /// Pin modes are read from the state attributes of the BeagleBone cape-universal overlay.
+    ///
+    /// # Errors
+    ///
+    /// IO errors that occur while reading a state attribute are forwarded. An error of the kind
+    /// `ErrorKind::InvalidInput` is returned if a pin is not set to its required mode.
+    pub fn check_pins(&self) -> io::Result<()> {
+        for (pin, mode) in &self.pins {
+            let mut state = String::new();
+            let path = PINMUX_STATE_PATH.replace("{}", pin);
+            try!(try!(File::open(path)).read_to_string(&mut state));
+            if state.trim() != mode {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("pin {} is in mode `{}` rather than `{}`",
+                                                  pin,
+                                                  state.trim(),
+                                                  mode)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+
+impl<'a> Pruss<'a> {
+    /// Brings up the application described by a manifest.
+    ///
+    /// This is equivalent to `PrussBuilder::new().launch(manifest)`.
+    ///
+    /// # Errors
+    ///
+    /// See `PrussBuilder::launch`.
+    ///
+    /// # Safety
+    ///
+    /// This runs binary codes that have unrestricted access to pretty much all the processor
+    /// memory and peripherals.
+    pub unsafe fn launch(manifest: &Manifest) -> io::Result<Pruss<'a>> {
+        PrussBuilder::new().launch(manifest)
+    }
+}
+
+
+
+impl PrussBuilder {
+    /// Brings up the application described by a manifest.
+    ///
+    /// This function proceeds as follows:
+    ///
+    /// * the pin multiplexing requirements are checked,
+    /// * both firmwares are loaded and verified against their digest, if any, which ensures
+    ///   that the PRU subsystem is not instantiated if a firmware cannot be found or is
+    ///   corrupted,
+    /// * the PRU subsystem is instantiated with the interrupt controller mapping of the manifest,
+    /// * the memory areas are checked against the size of their memory,
+    /// * the firmwares are written to the PRUs,
+    /// * the PRUs are started back-to-back, except those with the `run` flag cleared.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `Manifest::check_pins`, `Firmware::load`, `Firmware::verify`,
+    /// `PrussBuilder::build`, `Manifest::check_memory` and `PruLoader::load_code` are forwarded.
+    ///
+    /// # Safety
+    ///
+    /// This runs binary codes that have unrestricted access to pretty much all the processor
+    /// memory and peripherals.
+    pub unsafe fn launch<'a>(&self, manifest: &Manifest) -> io::Result<Pruss<'a>> {
+        try!(manifest.check_pins());
+        let firmware0 = match manifest.pru0 {
+            Some(ref image) => Some(try!(firmware(image))),
+            None => None,
+        };
+        let firmware1 = match manifest.pru1 {
+            Some(ref image) => Some(try!(firmware(image))),
+            None => None,
+        };
+
+        let mut pruss = try!(self.build(&manifest.intc));
+        try!(manifest.check_memory(&pruss));
+        {
+            let mut code0 = match firmware0 {
+                Some(ref f) => Some(try!(pruss.pru0.load_code(&mut f.code()))),
+                None => None,
+            };
+            let mut code1 = match firmware1 {
+                Some(ref f) => Some(try!(pruss.pru1.load_code(&mut f.code()))),
+                None => None,
+            };
+            if manifest.pru0.as_ref().is_some_and(|image| image.run) {
+                if let Some(ref mut code) = code0 {
+                    code.run();
+                }
+            }
+            if manifest.pru1.as_ref().is_some_and(|image| image.run) {
+                if let Some(ref mut code) = code1 {
+                    code.run();
+                }
+            }
+        }
+
+        Ok(pruss)
+    }
+}
+
+
+/// Interrupt controller requirements shipped with a firmware.
+///
+/// The manifest holds a single `intc` table with the same format as in application manifests.
+#[derive(Clone)]
+pub struct FirmwareManifest {
+    /// System event to channel mapping.
+    pub channels: Vec<(Sysevt, Channel)>,
+    /// Channel to host mapping.
+    pub hosts: Vec<(Channel, Host)>,
+    /// Enabled system events, by default all mapped system events.
+    pub sysevts: Vec<Sysevt>,
+    /// Enabled host interrupts, by default all mapped host interrupts.
+    pub enabled_hosts: Vec<Host>,
+}
+
+impl FirmwareManifest {
+    /// Reads a firmware manifest from a file.
+    ///
+    /// # Errors
+    ///
+    /// IO errors are forwarded. An error of the kind `ErrorKind::InvalidData` is returned if the
+    /// manifest is malformed.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FirmwareManifest> {
+        let mut text = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut text));
+
+        FirmwareManifest::parse(&text)
+    }
+
+    /// Reads the manifest shipped alongside a firmware file, i.e. the file with the same name
+    /// as the firmware file but with a `.toml` extension, if it exists.
+    ///
+    /// # Errors
+    ///
+    /// See `FirmwareManifest::open`.
+    pub fn find(firmware: &Firmware) -> io::Result<Option<FirmwareManifest>> {
+        let path = firmware.path().with_extension("toml");
+        if path.is_file() {
+            FirmwareManifest::open(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parses a firmware manifest, e.g. one embedded in the host executable with
+    /// `include_str!`.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the manifest is malformed.
+    pub fn parse(text: &str) -> io::Result<FirmwareManifest> {
+        let file: FirmwareManifestFile =
+            try!(toml::from_str(text).map_err(|err| error(err.to_string())));
+
+        file.intc.into_manifest()
+    }
+
+    /// Returns an interrupt controller configuration implementing the requirements.
+    pub fn intc_config(&self) -> IntcConfig {
+        let mut config = IntcConfig::new_empty();
+        config.map_sysevts_to_channels(&self.channels);
+        config.map_channels_to_hosts(&self.hosts);
+        config.enable_sysevts(&self.sysevts);
+        config.enable_hosts(&self.enabled_hosts);
+
+        config
+    }
+}
+
+
+
+impl Intc {
+    /// Checks that the current interrupt controller mapping satisfies the requirements of a
+    /// firmware manifest.
+    ///
+    /// Mappings and enabled events that are not mentioned in the manifest are ignored.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidInput` describing the first unmet requirement is
+    /// returned if the mapping does not satisfy the requirements.
+    pub fn check_manifest(&self, manifest: &FirmwareManifest) -> io::Result<()> {
+        let reg = |offset: isize| unsafe { ptr::read_volatile(self.intc_reg.offset(offset)) };
+        let unmet = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+
+        for &(sysevt, channel) in &manifest.channels {
+            let (sysevt, channel) = (sysevt as u32, channel as u32);
+            let cmr = reg(CMR_REG + (sysevt >> 2) as isize);
+            let actual = (cmr >> ((sysevt & 0b11) * 8)) & 0x0f;
+            if actual != channel {
+                return unmet(format!("system event {} is mapped to channel {} rather than {}",
+                                     sysevt,
+                                     actual,
+                                     channel));
+            }
+        }
+        for &(channel, host) in &manifest.hosts {
+            let (channel, host) = (channel as u32, host as u32);
+            let hmr = reg(HMR_REG + (channel >> 2) as isize);
+            let actual = (hmr >> ((channel & 0b11) * 8)) & 0x0f;
+            if actual != host {
+                return unmet(format!("channel {} is mapped to host {} rather than {}",
+                                     channel,
+                                     actual,
+                                     host));
+            }
+        }
+        let esr = [reg(ESR1_REG), reg(ESR2_REG)];
+        for &sysevt in &manifest.sysevts {
+            let sysevt = sysevt as u32;
+            if esr[(sysevt >> 5) as usize] & (1 << (sysevt & 31)) == 0 {
+                return unmet(format!("system event {} is not enabled", sysevt));
+            }
+        }
+        let hier = reg(HIER_REG);
+        for &host in &manifest.enabled_hosts {
+            if hier & (1 << host as u32) == 0 {
+                return unmet(format!("host interrupt {} is not enabled", host as u32));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+
+impl PruLoader {
+    /// Loads a firmware after checking that the interrupt controller mapping satisfies the
+    /// requirements of its manifest.
+    ///
+    /// When the PRU subsystem is instantiated for the firmware, the mapping can be built from the
+    /// manifest with `FirmwareManifest::intc_config`. When it is shared with other firmwares, this
+    /// function ensures that the firmware is not loaded if the mapping does not suit it.
+    ///
+    /// # Errors
+    ///
+    /// The errors of `Intc::check_manifest` and `PruLoader::load_code` are forwarded. No code is
+    /// loaded if the requirements are not met.
+    pub fn load_with_manifest(&mut self,
+                              intc: &Intc,
+                              firmware: &Firmware,
+                              manifest: &FirmwareManifest)
+                              -> io::Result<PruCode<'_>> {
+        try!(intc.check_manifest(manifest));
+
+        self.load_code_from_slice(firmware.code()).map_err(io::Error::from)
+    }
+}
+
+
+fn firmware(image: &PruImage) -> io::Result<Firmware> {
+    let firmware = if image.firmware.contains('/') {
+        try!(Firmware::open(&image.firmware))
+    } else {
+        try!(Firmware::load(&image.firmware))
+    };
+    if let Some(ref digest) = image.digest {
+        try!(firmware.verify(digest));
+    }
+
+    Ok(firmware)
+}
+
+
+
+// Contents of an application manifest file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    pru0: Option<PruTable>,
+    pru1: Option<PruTable>,
+    intc: Option<IntcTable>,
+    #[serde(default)]
+    memory: BTreeMap<String, MemoryTable>,
+    #[serde(default)]
+    pins: BTreeMap<String, String>,
+}
+
+
+
+// Contents of a firmware manifest file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FirmwareManifestFile {
+    intc: IntcTable,
+}
+
+
+
+// Entries of the `pru0` and `pru1` tables.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PruTable {
+    firmware: String,
+    digest: Option<String>,
+    #[serde(default = "default_run")]
+    run: bool,
+}
+
+impl PruTable {
+    fn into_image(self, table: &str) -> io::Result<PruImage> {
+        let digest = match self.digest {
+            Some(digest) => {
+                Some(try!(Digest::parse(&digest).map_err(|_| invalid(table, "digest"))))
+            }
+            None => None,
+        };
+
+        Ok(PruImage {
+            firmware: self.firmware,
+            digest: digest,
+            run: self.run,
+        })
+    }
+}
+
+
+
+// Entries of a `memory` table.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MemoryTable {
+    ram: Ram,
+    #[serde(default)]
+    offset: usize,
+    size: usize,
+}
+
+
+
+// Entries of the `intc` table.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IntcTable {
+    #[serde(default)]
+    channels: Vec<(u8, u8)>,
+    #[serde(default)]
+    hosts: Vec<(u8, u8)>,
+    sysevts: Option<Vec<u8>>,
+    enabled_hosts: Option<Vec<u8>>,
+}
+
+impl IntcTable {
+    fn into_manifest(self) -> io::Result<FirmwareManifest> {
+        let channels: Vec<(Sysevt, Channel)> =
+            try!(self.channels.iter().map(|&p| pair("channels", p)).collect());
+        let hosts: Vec<(Channel, Host)> =
+            try!(self.hosts.iter().map(|&p| pair("hosts", p)).collect());
+        let sysevts: Vec<Sysevt> = match self.sysevts {
+            Some(sysevts) => try!(sysevts.iter().map(|&s| number("sysevts", s)).collect()),
+            None => channels.iter().map(|&(s, _)| s).collect(),
+        };
+        let enabled_hosts: Vec<Host> = match self.enabled_hosts {
+            Some(hosts) => try!(hosts.iter().map(|&h| number("enabled_hosts", h)).collect()),
+            None => {
+                let mut hosts: Vec<Host> = hosts.iter().map(|&(_, h)| h).collect();
+                hosts.sort();
+                hosts.dedup();
+                hosts
+            }
+        };
+        try!(check_unique(channels.iter().map(|&(s, _)| s as u8), "system event"));
+        try!(check_unique(hosts.iter().map(|&(c, _)| c as u8), "channel"));
+        try!(check_unique(sysevts.iter().map(|&s| s as u8), "system event"));
+        try!(check_unique(enabled_hosts.iter().map(|&h| h as u8), "host"));
+
+        Ok(FirmwareManifest {
+            channels: channels,
+            hosts: hosts,
+            sysevts: sysevts,
+            enabled_hosts: enabled_hosts,
+        })
+    }
+}
+
+
+fn default_run() -> bool {
+    true
+}
+
+
+fn error(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+
+fn invalid(table: &str, key: &str) -> io::Error {
+    error(format!("invalid value for `{}` in table [{}]", key, table))
+}
+
+
+// Converts a number of the `intc` table to a system event, channel or host.
+fn number<T: TryFrom<u8, Error = OutOfRangeError>>(key: &str, n: u8) -> io::Result<T> {
+    T::try_from(n).map_err(|err| {
+        error(format!("invalid value for `{}` in table [intc]: {}", key, err))
+    })
+}
+
+
+fn pair<T, U>(key: &str, (a, b): (u8, u8)) -> io::Result<(T, U)>
+    where T: TryFrom<u8, Error = OutOfRangeError>,
+          U: TryFrom<u8, Error = OutOfRangeError>
+{
+    Ok((try!(number(key, a)), try!(number(key, b))))
+}
+
+
+fn check_unique<I: Iterator<Item = u8>>(items: I, what: &str) -> io::Result<()> {
+    let mut seen = 0u64;
+    for item in items {
+        if seen & (1 << item) != 0 {
+            return Err(error(format!("{} {} is mapped or enabled several times", what, item)));
+        }
+        seen |= 1 << item;
+    }
+
+    Ok(())
+}
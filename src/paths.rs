@@ -0,0 +1,49 @@
+//! Location of the UIO device files.
+
+use def::{PRUSS_DEVICE_PATH, EVTOUT_DEVICE_ROOT_PATH, UIO_SYSFS_PATH};
+
+use std::path::{Path, PathBuf};
+
+
+/// Location of the UIO device files and sysfs attributes.
+///
+/// The default paths are those of the `uio_pruss` driver. Other paths can be set with
+/// `PrussBuilder::paths`, for instance to run against a fake device tree in tests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DevicePaths {
+    /// Device file providing the PRU memory (map 0) and host memory (map 1) mappings.
+    pub device: PathBuf,
+    /// Prefix of the event out device files, to which the event out number is appended.
+    pub evtout_prefix: String,
+    /// Sysfs directory of the UIO device, containing the `maps` directory.
+    pub sysfs: PathBuf,
+}
+
+impl DevicePaths {
+    /// Returns the default paths relocated below the specified root directory.
+    pub fn with_root<P: AsRef<Path>>(root: P) -> DevicePaths {
+        let root = root.as_ref();
+        let relocate = |path: &str| root.join(&path[1..]);
+
+        DevicePaths {
+            device: relocate(PRUSS_DEVICE_PATH),
+            evtout_prefix: relocate(EVTOUT_DEVICE_ROOT_PATH).to_string_lossy().into_owned(),
+            sysfs: relocate(UIO_SYSFS_PATH),
+        }
+    }
+
+    /// Path of an attribute of a memory map, e.g. `size` or `addr`.
+    pub(crate) fn map_attr(&self, map: usize, attr: &str) -> PathBuf {
+        self.sysfs.join(format!("maps/map{}/{}", map, attr))
+    }
+}
+
+impl Default for DevicePaths {
+    fn default() -> DevicePaths {
+        DevicePaths {
+            device: PathBuf::from(PRUSS_DEVICE_PATH),
+            evtout_prefix: EVTOUT_DEVICE_ROOT_PATH.to_string(),
+            sysfs: PathBuf::from(UIO_SYSFS_PATH),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Platform performance measurements.
+//!
+//! The functions of this module measure the achievable memory bandwidth and event latency on the
+//! current system, so that applications can check at startup that the platform meets their
+//! timing budget.
+//!
+//! An `EvtoutIrq` can also collect statistics on the inter-event and service latencies of a
+//! running application; see `EvtoutIrq::enable_latency_stats`. Likewise, an `Intc` can count
+//! how many times each system event was cleared; see `Intc::enable_event_counts`.
+
+use {Intc, EvtoutIrq, MemSegment, SegmentAddr, Sysevt, WaitError};
+use def::NUM_SYSEVTS;
+
+use std::cmp;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::result;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+
+/// Memory throughput measurement.
+#[derive(Copy, Clone, Debug)]
+pub struct Throughput {
+    /// Number of bytes transferred in each direction.
+    pub bytes: usize,
+    /// Host-to-memory write throughput (in bytes per second).
+    pub write: f64,
+    /// Memory-to-host read throughput (in bytes per second).
+    pub read: f64,
+}
+
+
+/// Latency measurement summary.
+#[derive(Copy, Clone, Debug)]
+pub struct Latency {
+    /// Number of samples.
+    pub samples: usize,
+    /// Minimum latency.
+    pub min: Duration,
+    /// Maximum latency.
+    pub max: Duration,
+    /// Mean latency.
+    pub mean: Duration,
+}
+
+
+/// Distribution of durations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution {
+    /// Number of samples.
+    pub samples: u64,
+    /// Minimum duration, or zero if there are no samples.
+    pub min: Duration,
+    /// Maximum duration.
+    pub max: Duration,
+    /// Mean duration, or zero if there are no samples.
+    pub mean: Duration,
+    /// Width of the histogram buckets.
+    pub bucket_width: Duration,
+    /// Number of samples in each bucket: bucket `i` counts the durations within
+    /// `[i * bucket_width, (i + 1) * bucket_width)`, except for the last bucket which also counts
+    /// all longer durations.
+    pub histogram: Vec<u64>,
+}
+
+impl Distribution {
+    fn new(bucket_width: Duration, buckets: usize) -> Distribution {
+        Distribution {
+            samples: 0,
+            min: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+            mean: Duration::from_secs(0),
+            bucket_width: bucket_width,
+            histogram: vec![0; buckets],
+        }
+    }
+
+    fn record(&mut self, sample: Duration, total: &mut Duration) {
+        if self.samples == 0 || sample < self.min {
+            self.min = sample;
+        }
+        if sample > self.max {
+            self.max = sample;
+        }
+        self.samples += 1;
+        *total += sample;
+        self.mean = Duration::from_nanos((total.as_nanos() / self.samples as u128) as u64);
+        let bucket = (sample.as_nanos() / self.bucket_width.as_nanos()) as usize;
+        let last = self.histogram.len() - 1;
+        self.histogram[cmp::min(bucket, last)] += 1;
+    }
+}
+
+
+/// Latency statistics collected by an `EvtoutIrq`.
+///
+/// Wakeups are timestamped with the monotonic clock when a wait function of the `EvtoutIrq`
+/// returns an interrupt count.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencyStats {
+    /// Time elapsed between consecutive wakeups.
+    pub inter_event: Distribution,
+    /// Time elapsed between a wakeup and the next call to a wait function, i.e. the time spent
+    /// by the application servicing the event.
+    pub service: Distribution,
+}
+
+
+// Accumulator of latency statistics.
+pub(crate) struct LatencyRecorder {
+    stats: LatencyStats,
+    inter_event_total: Duration,
+    service_total: Duration,
+    last_wakeup: Option<Instant>,
+    servicing: bool,
+}
+
+impl LatencyRecorder {
+    pub(crate) fn new(bucket_width: Duration, buckets: usize) -> LatencyRecorder {
+        assert!(bucket_width > Duration::from_secs(0) && buckets > 0);
+        LatencyRecorder {
+            stats: LatencyStats {
+                inter_event: Distribution::new(bucket_width, buckets),
+                service: Distribution::new(bucket_width, buckets),
+            },
+            inter_event_total: Duration::from_secs(0),
+            service_total: Duration::from_secs(0),
+            last_wakeup: None,
+            servicing: false,
+        }
+    }
+
+    // Records the beginning of a wait.
+    pub(crate) fn wait(&mut self, now: Instant) {
+        if self.servicing {
+            if let Some(wakeup) = self.last_wakeup {
+                self.stats.service.record(now - wakeup, &mut self.service_total);
+            }
+            self.servicing = false;
+        }
+    }
+
+    // Records a wakeup.
+    pub(crate) fn wakeup(&mut self, now: Instant) {
+        if let Some(wakeup) = self.last_wakeup {
+            self.stats.inter_event.record(now - wakeup, &mut self.inter_event_total);
+        }
+        self.last_wakeup = Some(now);
+        self.servicing = true;
+    }
+
+    pub(crate) fn stats(&self) -> &LatencyStats {
+        &self.stats
+    }
+}
+
+
+/// Number of times each system event was cleared by an `Intc`.
+///
+/// A system event is counted each time it is cleared with `Intc::clear_sysevt`,
+/// `Intc::clear_sysevts` or any function relying on them, such as `Intc::ack`, an
+/// `IrqDispatcher` or `Intc::transaction`. A steadily growing count hints at an interrupt storm.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventCounts {
+    counts: Vec<u64>,
+}
+
+impl EventCounts {
+    /// Returns the number of times a system event was cleared.
+    pub fn get<T: Into<Sysevt>>(&self, sysevt: T) -> u64 {
+        let sysevt: Sysevt = sysevt.into();
+        self.counts[sysevt as usize]
+    }
+
+    /// Returns the number of times any system event was cleared.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the system events that were cleared at least once with their counts, sorted by
+    /// system event number.
+    pub fn nonzero(&self) -> Vec<(Sysevt, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count != 0)
+            .map(|(sysevt, &count)| (Sysevt::try_from(sysevt as u8).unwrap(), count))
+            .collect()
+    }
+}
+
+
+// Per-system event counters shared by an `Intc` and its clones.
+pub(crate) struct EventCounters {
+    enabled: AtomicBool,
+    counts: Vec<AtomicU64>,
+}
+
+impl EventCounters {
+    pub(crate) fn new() -> EventCounters {
+        EventCounters {
+            enabled: AtomicBool::new(false),
+            counts: (0..NUM_SYSEVTS).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    // Resets the counts and starts or stops counting.
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        for count in &self.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.enabled.store(enabled, Ordering::Release);
+    }
+
+    // Counts the system events of the masks of events 0 to 31 and 32 to 63.
+    pub(crate) fn record(&self, low: u32, high: u32) {
+        if !self.enabled.load(Ordering::Acquire) {
+            return;
+        }
+        let mask = (high as u64) << 32 | low as u64;
+        for (sysevt, count) in self.counts.iter().enumerate() {
+            if mask & (1 << sysevt) != 0 {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn counts(&self) -> Option<EventCounts> {
+        if !self.enabled.load(Ordering::Acquire) {
+            return None;
+        }
+
+        Some(EventCounts {
+            counts: self.counts.iter().map(|count| count.load(Ordering::Relaxed)).collect(),
+        })
+    }
+}
+
+
+/// Measures the host write and read throughput to and from a memory segment.
+///
+/// The whole segment is written and read back `passes` times with volatile accesses. The
+/// previous content of the segment is overwritten.
+///
+/// # Panics
+///
+/// This function will panic if `passes` is zero.
+pub fn throughput<A: SegmentAddr>(segment: &mut MemSegment<A>, passes: usize) -> Throughput {
+    assert!(passes > 0);
+    let begin = segment.begin();
+    let size = segment.end().into() - begin.into();
+    let mut buffer = vec![0xa5u8; size];
+
+    let start = Instant::now();
+    for _ in 0..passes {
+        let (_, mut view) = segment.split_at(begin);
+        view.write_all(&buffer).unwrap();
+    }
+    let write_time = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..passes {
+        let (_, mut view) = segment.split_at(begin);
+        view.read_exact(&mut buffer).unwrap();
+    }
+    let read_time = start.elapsed();
+
+    let bytes = size * passes;
+    Throughput {
+        bytes: bytes,
+        write: bytes as f64 / write_time.as_secs_f64(),
+        read: bytes as f64 / read_time.as_secs_f64(),
+    }
+}
+
+
+/// Measures the latency between the triggering of a system event and its notification to the
+/// host.
+///
+/// The system event is triggered by the host itself, so no PRU code is needed. The system event
+/// must however be enabled and mapped to the event out of `irq`, and must not be triggered
+/// concurrently by the PRU. Each sample performs a full trigger/wait/clear/re-enable cycle;
+/// the latency is measured from the trigger to the return of `EvtoutIrq::try_wait_timeout`.
+///
+/// # Errors
+///
+/// An error is returned if the interrupt cannot be re-armed or if the event out device fails. An
+/// IO error of the kind `ErrorKind::TimedOut` is returned if the event out is not triggered
+/// within `timeout` of a trigger, e.g. because the system event is not mapped to it.
+///
+/// # Panics
+///
+/// This function will panic if `samples` is zero.
+pub fn event_latency<T>(intc: &Intc,
+                        irq: &EvtoutIrq,
+                        sysevt: T,
+                        samples: usize,
+                        timeout: Duration)
+                        -> result::Result<Latency, WaitError>
+    where T: Into<Sysevt>
+{
+    assert!(samples > 0);
+    let sysevt: Sysevt = sysevt.into();
+    let mut min = Duration::from_secs(u64::MAX);
+    let mut max = Duration::from_secs(0);
+    let mut total = Duration::from_secs(0);
+
+    for _ in 0..samples {
+        let start = Instant::now();
+        intc.send_sysevt(sysevt);
+        if try!(irq.try_wait_timeout(timeout)).is_none() {
+            return Err(WaitError::Io(io::Error::new(io::ErrorKind::TimedOut,
+                                                    "the event out was not triggered")));
+        }
+        let latency = start.elapsed();
+        intc.ack(sysevt, irq.get_evtout());
+
+        if latency < min {
+            min = latency;
+        }
+        if latency > max {
+            max = latency;
+        }
+        total += latency;
+    }
+
+    Ok(Latency {
+        samples: samples,
+        min: min,
+        max: max,
+        mean: Duration::from_nanos((total.as_nanos() / samples as u128) as u64),
+    })
+}
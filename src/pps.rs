@@ -0,0 +1,278 @@
+//! Pulse-per-second generation and disciplining.
+//!
+//! The companion firmware (`examples/asm/pps_generator.asm` or
+//! `examples/pasm/pps_generator.pasm`) generates a periodic pulse on P9_31 with edges scheduled on
+//! the IEP counter. The host controls the period, the pulse width and the phase of the output
+//! through a control block located at the beginning of the PRU0 data RAM and can discipline the
+//! output, e.g. to the host clock, by periodically submitting corrections.
+
+use {MemSegment, SegmentAddr};
+use clock::PruClock;
+use timesync::Calibration;
+use util::VolatileCell;
+
+use std::time::Duration;
+
+
+
+/// Control block shared with the PPS firmware.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PpsControl {
+    /// Output period (in IEP ticks), applied from the next rising edge.
+    pub period: VolatileCell<u32>,
+    /// Pulse width (in IEP ticks), applied from the next rising edge.
+    pub high_time: VolatileCell<u32>,
+    /// One-shot phase adjustment (in IEP ticks), cleared by the firmware once applied.
+    pub adjust: VolatileCell<i32>,
+    /// Number of rising edges generated so far, written by the firmware.
+    pub edge_count: VolatileCell<u32>,
+    /// IEP count at the last rising edge, written by the firmware.
+    pub last_edge: VolatileCell<u32>,
+}
+
+
+
+/// Rising edge of the output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    /// Index of the edge, starting at 1.
+    pub count: u32,
+    /// IEP count at the edge.
+    pub ticks: u32,
+}
+
+
+
+/// Correction applied to the output after an edge.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Correction {
+    /// New output period (in IEP ticks), if it should be changed.
+    pub period: Option<u32>,
+    /// Phase adjustment (in IEP ticks) applied to the next rising edge.
+    pub phase: i32,
+}
+
+
+
+/// Disciplining strategy.
+///
+/// A discipline is notified of each rising edge and returns the correction to apply.
+pub trait Discipline {
+    /// Computes the correction to apply following the specified edge.
+    fn correct(&mut self, edge: &Edge) -> Correction;
+}
+
+
+
+/// Host interface to the PPS firmware.
+pub struct PpsGenerator<'a> {
+    ctrl: &'a mut PpsControl,
+    clock: PruClock,
+    last_count: u32,
+}
+
+impl<'a> PpsGenerator<'a> {
+    /// Allocates the control block at the beginning of the segment and initializes it.
+    ///
+    /// The segment should start at the beginning of the PRU0 data RAM and the generator should be
+    /// created before the firmware is started. The clock is the IEP clock, which is the PRU core
+    /// clock on the AM335x.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the frequency or the duty cycle are out of range, or for the
+    /// same reasons as `MemSegment::alloc`.
+    pub fn new<A>(segment: &'a mut MemSegment<A>,
+                  clock: PruClock,
+                  frequency: f64,
+                  duty_cycle: f64)
+                  -> PpsGenerator<'a>
+        where A: SegmentAddr
+    {
+        let mut generator = PpsGenerator {
+            ctrl: segment.alloc(PpsControl {
+                period: VolatileCell::new(0),
+                high_time: VolatileCell::new(0),
+                adjust: VolatileCell::new(0),
+                edge_count: VolatileCell::new(0),
+                last_edge: VolatileCell::new(0),
+            }),
+            clock: clock,
+            last_count: 0,
+        };
+        generator.set_frequency(frequency, duty_cycle);
+
+        generator
+    }
+
+    /// Returns the IEP clock.
+    pub fn clock(&self) -> PruClock {
+        self.clock
+    }
+
+    /// Sets the output frequency (in Hz) and duty cycle (between 0 and 1).
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the period is shorter than 2 IEP ticks or does not fit in 32
+    /// bits, or if the duty cycle is not strictly between 0 and 1.
+    pub fn set_frequency(&mut self, frequency: f64, duty_cycle: f64) {
+        assert!(duty_cycle > 0.0 && duty_cycle < 1.0);
+        let period = (self.clock.hz() as f64 / frequency).round();
+        assert!(period >= 2.0 && period <= u32::MAX as f64);
+        let high_time = ((period * duty_cycle).round() as u32).max(1).min(period as u32 - 1);
+        self.ctrl.period.set(period as u32);
+        self.ctrl.high_time.set(high_time);
+    }
+
+    /// Returns the output period (in IEP ticks).
+    pub fn period(&self) -> u32 {
+        self.ctrl.period.get()
+    }
+
+    /// Sets the output period (in IEP ticks), applied from the next rising edge.
+    pub fn set_period(&mut self, ticks: u32) {
+        self.ctrl.period.set(ticks);
+    }
+
+    /// Sets the pulse width (in IEP ticks), applied from the next rising edge.
+    pub fn set_pulse_width(&mut self, ticks: u32) {
+        self.ctrl.high_time.set(ticks);
+    }
+
+    /// Shifts the next rising edges by the specified number of IEP ticks.
+    ///
+    /// The adjustment is added to any adjustment not yet consumed by the firmware.
+    pub fn adjust_phase(&mut self, ticks: i32) {
+        let pending = self.ctrl.adjust.get();
+        self.ctrl.adjust.set(pending.wrapping_add(ticks));
+    }
+
+    /// Shifts the next rising edges by the specified duration, forward or backward.
+    pub fn adjust_phase_by(&mut self, shift: Duration, forward: bool) {
+        let ticks = self.clock.duration_to_cycles(shift) as i32;
+        self.adjust_phase(if forward { ticks } else { -ticks });
+    }
+
+    /// Returns the last rising edge if it was generated since the previous call.
+    ///
+    /// Edges may be missed if this function is not called at least once per period.
+    pub fn poll_edge(&mut self) -> Option<Edge> {
+        // Read the count twice to make sure the edge time is consistent with the edge count.
+        loop {
+            let count = self.ctrl.edge_count.get();
+            let ticks = self.ctrl.last_edge.get();
+            if self.ctrl.edge_count.get() == count {
+                if count == self.last_count {
+                    return None;
+                }
+                self.last_count = count;
+                return Some(Edge {
+                    count: count,
+                    ticks: ticks,
+                });
+            }
+        }
+    }
+
+    /// Applies a correction.
+    pub fn apply(&mut self, correction: &Correction) {
+        if let Some(period) = correction.period {
+            self.set_period(period);
+        }
+        if correction.phase != 0 {
+            self.adjust_phase(correction.phase);
+        }
+    }
+
+    /// Polls for a new edge and applies the correction computed by the discipline, if any.
+    ///
+    /// Returns the new edge, if any.
+    pub fn step<D: Discipline>(&mut self, discipline: &mut D) -> Option<Edge> {
+        let edge = self.poll_edge();
+        if let Some(ref edge) = edge {
+            let correction = discipline.correct(edge);
+            self.apply(&correction);
+        }
+
+        edge
+    }
+}
+
+
+
+/// Discipline locking the output edges to whole multiples of the output period of the host
+/// monotonic clock.
+///
+/// The phase error of each edge is measured with a counter calibration (see
+/// `timesync::calibrate`) and corrected by a proportional-integral controller: the proportional
+/// term is applied as a phase adjustment while the integral term adjusts the period to compensate
+/// for the frequency offset between the IEP clock and the host clock.
+///
+/// The edge times are tracked as a 64-bit count of ticks since the calibration reference so that
+/// the lock survives wrap-arounds of the IEP counter, provided that it is notified of edges at
+/// least once per wrap-around period.
+#[derive(Copy, Clone, Debug)]
+pub struct PhaseLock {
+    calibration: Calibration,
+    nominal_period: u32,
+    // Raw and extended IEP counts at the last edge.
+    last_edge: Option<(u32, i64)>,
+    /// Proportional gain (dimensionless, typically between 0 and 1).
+    pub kp: f64,
+    /// Integral gain (dimensionless, typically much smaller than `kp`).
+    pub ki: f64,
+    integral: f64,
+}
+
+impl PhaseLock {
+    /// Creates a phase lock for the specified calibration and nominal period (in IEP ticks).
+    pub fn new(calibration: Calibration, nominal_period: u32, kp: f64, ki: f64) -> PhaseLock {
+        PhaseLock {
+            calibration: calibration,
+            nominal_period: nominal_period,
+            last_edge: None,
+            kp: kp,
+            ki: ki,
+            integral: 0.0,
+        }
+    }
+
+    /// Returns the phase error (in IEP ticks) of an edge relative to the host clock.
+    ///
+    /// The edge should follow the last edge the lock was notified of by less than a wrap-around
+    /// period of the IEP counter or, for the first edge, lie within half a wrap-around period of
+    /// the calibration reference.
+    pub fn phase_error(&self, edge: &Edge) -> f64 {
+        let period = self.nominal_period as f64 / self.calibration.rate;
+        let t = self.calibration.host_time_extended(self.extended_ticks(edge.ticks)).as_secs_f64();
+        let error = t - (t / period).round() * period;
+
+        error * self.calibration.rate
+    }
+
+    // Converts a raw IEP count to a number of ticks elapsed since the calibration reference.
+    fn extended_ticks(&self, ticks: u32) -> i64 {
+        match self.last_edge {
+            Some((last_ticks, last_extended)) => {
+                last_extended + ticks.wrapping_sub(last_ticks) as i64
+            }
+            None => ticks.wrapping_sub(self.calibration.reference) as i32 as i64,
+        }
+    }
+}
+
+impl Discipline for PhaseLock {
+    fn correct(&mut self, edge: &Edge) -> Correction {
+        let error = self.phase_error(edge);
+        self.last_edge = Some((edge.ticks, self.extended_ticks(edge.ticks)));
+        self.integral += error;
+        let period = self.nominal_period as f64 - self.ki * self.integral;
+
+        Correction {
+            period: Some(period.round().max(2.0).min(u32::MAX as f64) as u32),
+            phase: (-self.kp * error).round() as i32,
+        }
+    }
+}
@@ -0,0 +1,14 @@
+//! The prusst prelude.
+//!
+//! This module re-exports the items that are needed by most PRU applications so that they can be
+//! brought into scope with a single glob import:
+//!
+//! ```
+//! use prusst::prelude::*;
+//! ```
+
+pub use {Pruss, PrussBuilder, Intc, IntcConfig, CompiledIntcConfig};
+pub use {PruLoader, PruCode, MemSegment, EvtoutIrq, Firmware};
+pub use {PruAddr, HostOffset};
+pub use {Sysevt, Channel, Host, Evtout};
+pub use util::VolatileCell;
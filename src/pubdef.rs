@@ -1,9 +1,40 @@
 
+use def::{NUM_CHANNELS, NUM_EVTOUTS, NUM_HOSTS, NUM_SYSEVTS};
+use error::OutOfRangeError;
+
+use std::convert::TryFrom;
 use std::mem;
+use std::result;
+
+
+// Implements the checked conversion from and the conversion into the number of an enum
+// item.
+macro_rules! impl_number_conversions {
+    ($name:ident, $count:expr, $what:expr) => {
+        impl TryFrom<u8> for $name {
+            type Error = OutOfRangeError;
+
+            fn try_from(n: u8) -> result::Result<$name, OutOfRangeError> {
+                if n < $count {
+                    Ok(unsafe { mem::transmute::<u8, $name>(n) })
+                } else {
+                    Err(OutOfRangeError::new($what, n, $count))
+                }
+            }
+        }
+
+        impl From<$name> for u8 {
+            fn from(item: $name) -> u8 {
+                item as u8
+            }
+        }
+    }
+}
+
 
 /// A PRU-generated system event.
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Sysevt {
     S0,
     S1,
@@ -74,7 +105,7 @@ pub enum Sysevt {
 
 /// A channel to which system interrupts can be mapped.
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Channel {
     C0,
     C1,
@@ -92,7 +123,7 @@ pub enum Channel {
 
 /// A host to which channels can be mapped.
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Host {
     Pru0,
     Pru1,
@@ -110,7 +141,7 @@ pub enum Host {
 
 /// An event out.
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Evtout {
     E0,
     E1,
@@ -129,3 +160,38 @@ impl Into<Host> for Evtout
     }
 }
 
+impl_number_conversions!(Sysevt, NUM_SYSEVTS, "system event");
+impl_number_conversions!(Channel, NUM_CHANNELS, "channel");
+impl_number_conversions!(Host, NUM_HOSTS, "host interrupt");
+impl_number_conversions!(Evtout, NUM_EVTOUTS, "event out");
+
+
+
+/// Polarity of a system event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SysevtPolarity {
+    /// The system event is raised on a high level or a rising edge (default).
+    ActiveHigh,
+    /// The system event is raised on a low level or a falling edge.
+    ActiveLow,
+}
+
+
+
+/// Type of a system event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SysevtType {
+    /// The system event is a pulse (default), as generated by the PRU cores.
+    Pulse,
+    /// The system event is a level, as generated by some peripherals.
+    Level,
+}
+
+
+
+/// A PRU core.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PruCore {
+    Pru0,
+    Pru1,
+}
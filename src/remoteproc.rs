@@ -0,0 +1,307 @@
+//! Resource tables of firmwares written for the Linux remoteproc framework.
+//!
+//! Firmwares targeting the remoteproc PRU driver embed a `.resource_table` section which
+//! describes the memory carveouts, device memories, trace buffers and virtio devices they expect
+//! the host to provide. The table is exposed by `ElfFirmware::resource_table` so that such
+//! firmwares can be loaded with prusst, the application being responsible for providing the
+//! described resources.
+//!
+//! The table starts with a header followed by the offsets of its entries:
+//!
+//! ```text
+//!   0x00  version (u32), 1
+//!   0x04  number of entries (u32)
+//!   0x08  reserved (2 x u32)
+//!   0x10  offsets of the entries relative to the beginning of the table (u32 each)
+//! ```
+//!
+//! Each entry starts with a type word (u32) followed by a type-specific body, as defined in the
+//! `linux/remoteproc.h` header and, for the PRU-specific custom resources, in the
+//! `pru_rsc_types.h` header of the TI PRU software support package.
+
+use std::io;
+
+
+/// Version of the resource table format.
+pub const RESOURCE_TABLE_VERSION: u32 = 1;
+
+// Resource types.
+const RSC_CARVEOUT: u32 = 0;
+const RSC_DEVMEM: u32 = 1;
+const RSC_TRACE: u32 = 2;
+const RSC_VDEV: u32 = 3;
+const RSC_CUSTOM: u32 = 5;
+
+// Size of the header of the table.
+const HEADER_SIZE: usize = 16;
+
+// Size of resource names.
+const NAME_SIZE: usize = 32;
+
+// Size of a vring descriptor.
+const VRING_SIZE: usize = 20;
+
+
+
+/// Memory region described by a carveout or a device memory resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryResource {
+    /// Device address, as seen by the PRU.
+    pub da: u32,
+    /// Physical address.
+    pub pa: u32,
+    /// Length in bytes.
+    pub len: u32,
+    /// IOMMU protection flags.
+    pub flags: u32,
+    /// Name of the region.
+    pub name: String,
+}
+
+
+
+/// Trace buffer resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceResource {
+    /// Device address of the buffer.
+    pub da: u32,
+    /// Length of the buffer in bytes.
+    pub len: u32,
+    /// Name of the buffer.
+    pub name: String,
+}
+
+
+
+/// Vring of a virtio device resource.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Vring {
+    /// Device address of the vring, or `0xffffffff` if it is to be allocated by the host.
+    pub da: u32,
+    /// Alignment between the consumer and producer parts of the vring.
+    pub align: u32,
+    /// Number of buffers.
+    pub num: u32,
+    /// Notification identifier.
+    pub notify_id: u32,
+    /// Physical address, filled in by the host.
+    pub pa: u32,
+}
+
+
+
+/// Virtio device resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VdevResource {
+    /// Virtio device identifier, e.g. 7 for rpmsg.
+    pub id: u32,
+    /// Notification identifier.
+    pub notify_id: u32,
+    /// Device features.
+    pub dfeatures: u32,
+    /// Guest features, filled in by the host.
+    pub gfeatures: u32,
+    /// Virtio device status.
+    pub status: u8,
+    /// Vrings of the device.
+    pub vrings: Vec<Vring>,
+    /// Virtio configuration space.
+    pub config: Vec<u8>,
+}
+
+
+
+/// Resource table entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Resource {
+    /// Physically contiguous memory to be allocated by the host.
+    Carveout(MemoryResource),
+    /// Device memory to be mapped for the firmware.
+    Devmem(MemoryResource),
+    /// Trace buffer of the firmware.
+    Trace(TraceResource),
+    /// Virtio device.
+    Vdev(VdevResource),
+    /// PRU-specific custom resource, such as the interrupt mapping of the firmware.
+    Custom {
+        /// Resource sub-type; 0 denotes the interrupt mapping.
+        sub_type: u32,
+        /// Resource data.
+        data: Vec<u8>,
+    },
+    /// Resource of an unknown type, with the bytes following the type word up to the next entry.
+    Unknown {
+        /// Resource type.
+        kind: u32,
+        /// Resource data.
+        data: Vec<u8>,
+    },
+}
+
+
+
+/// Resource table of a remoteproc firmware.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceTable {
+    /// Entries of the table, in table order.
+    pub resources: Vec<Resource>,
+}
+
+impl ResourceTable {
+    /// Parses a resource table.
+    ///
+    /// # Errors
+    ///
+    /// An error of the kind `ErrorKind::InvalidData` is returned if the table is truncated, if an
+    /// entry is malformed or if the version is not supported.
+    pub fn parse(table: &[u8]) -> io::Result<ResourceTable> {
+        let version = try!(read_u32(table, 0));
+        if version != RESOURCE_TABLE_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("unsupported resource table version {}", version)));
+        }
+        let num = try!(read_u32(table, 4)) as usize;
+        let mut offsets = Vec::new();
+        for i in 0..num {
+            offsets.push(try!(read_u32(table, HEADER_SIZE + 4 * i)) as usize);
+        }
+
+        let mut resources = Vec::new();
+        for (i, &offset) in offsets.iter().enumerate() {
+            // An entry extends at most to the next entry or to the end of the table.
+            let end = offsets.iter()
+                .cloned()
+                .filter(|&o| o > offset)
+                .min()
+                .unwrap_or(table.len());
+            let entry = try!(table.get(offset..end).ok_or_else(malformed));
+            resources.push(try!(parse_resource(entry)
+                .map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("malformed resource table entry {}", i))
+                })));
+        }
+
+        Ok(ResourceTable { resources: resources })
+    }
+
+    /// Returns the carveout resources.
+    pub fn carveouts(&self) -> Vec<&MemoryResource> {
+        self.resources
+            .iter()
+            .filter_map(|resource| match *resource {
+                Resource::Carveout(ref carveout) => Some(carveout),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the virtio device resources.
+    pub fn vdevs(&self) -> Vec<&VdevResource> {
+        self.resources
+            .iter()
+            .filter_map(|resource| match *resource {
+                Resource::Vdev(ref vdev) => Some(vdev),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+
+// Parses an entry, including its type word.
+fn parse_resource(entry: &[u8]) -> io::Result<Resource> {
+    let kind = try!(read_u32(entry, 0));
+    let resource = match kind {
+        RSC_CARVEOUT | RSC_DEVMEM => {
+            let memory = MemoryResource {
+                da: try!(read_u32(entry, 4)),
+                pa: try!(read_u32(entry, 8)),
+                len: try!(read_u32(entry, 12)),
+                flags: try!(read_u32(entry, 16)),
+                name: try!(read_name(entry, 24)),
+            };
+            if kind == RSC_CARVEOUT {
+                Resource::Carveout(memory)
+            } else {
+                Resource::Devmem(memory)
+            }
+        }
+        RSC_TRACE => {
+            Resource::Trace(TraceResource {
+                da: try!(read_u32(entry, 4)),
+                len: try!(read_u32(entry, 8)),
+                name: try!(read_name(entry, 16)),
+            })
+        }
+        RSC_VDEV => {
+            let config_len = try!(read_u32(entry, 20)) as usize;
+            let status = *try!(entry.get(24).ok_or_else(malformed));
+            let num_vrings = *try!(entry.get(25).ok_or_else(malformed)) as usize;
+            let mut vrings = Vec::new();
+            for i in 0..num_vrings {
+                let vring = 28 + i * VRING_SIZE;
+                vrings.push(Vring {
+                    da: try!(read_u32(entry, vring)),
+                    align: try!(read_u32(entry, vring + 4)),
+                    num: try!(read_u32(entry, vring + 8)),
+                    notify_id: try!(read_u32(entry, vring + 12)),
+                    pa: try!(read_u32(entry, vring + 16)),
+                });
+            }
+            let config = 28 + num_vrings * VRING_SIZE;
+            Resource::Vdev(VdevResource {
+                id: try!(read_u32(entry, 4)),
+                notify_id: try!(read_u32(entry, 8)),
+                dfeatures: try!(read_u32(entry, 12)),
+                gfeatures: try!(read_u32(entry, 16)),
+                status: status,
+                vrings: vrings,
+                config: try!(config.checked_add(config_len)
+                        .and_then(|end| entry.get(config..end))
+                        .ok_or_else(malformed))
+                    .to_vec(),
+            })
+        }
+        RSC_CUSTOM => {
+            let size = try!(read_u32(entry, 8)) as usize;
+            Resource::Custom {
+                sub_type: try!(read_u32(entry, 4)),
+                data: try!(size.checked_add(12)
+                        .and_then(|end| entry.get(12..end))
+                        .ok_or_else(malformed))
+                    .to_vec(),
+            }
+        }
+        kind => {
+            Resource::Unknown {
+                kind: kind,
+                data: entry[4..].to_vec(),
+            }
+        }
+    };
+
+    Ok(resource)
+}
+
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed resource table")
+}
+
+
+fn read_u32(bytes: &[u8], offset: usize) -> io::Result<u32> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(malformed()),
+    }
+}
+
+
+// Reads a NUL-padded name.
+fn read_name(bytes: &[u8], offset: usize) -> io::Result<String> {
+    let name = try!(bytes.get(offset..offset + NAME_SIZE).ok_or_else(malformed));
+    let len = name.iter().position(|&c| c == 0).unwrap_or(NAME_SIZE);
+
+    Ok(String::from_utf8_lossy(&name[..len]).into_owned())
+}
@@ -0,0 +1,314 @@
+//! Request/reply channel over shared RAM.
+//!
+//! Configuration-style interactions with the firmware, where a lost or corrupted command must be
+//! detected rather than silently acted upon, can be carried by small CRC-protected frames
+//! exchanged through a memory segment shared with the firmware. The segment starts with two flag
+//! words followed by a request slot and a response slot of equal size:
+//!
+//! ```text
+//!   0x00  request flag    set to 1 by the host when a request is posted, cleared by the PRU
+//!                         once the request has been consumed
+//!   0x04  response flag   set to 1 by the PRU when a response is posted, cleared by the host
+//!                         once the response has been consumed
+//!   0x08  request slot
+//!   ....  response slot   located at 0x08 + slot size
+//! ```
+//!
+//! The slot size is half the size of the segment minus the flag words, rounded down to a
+//! multiple of 4 bytes. Each slot holds one frame with the following little-endian layout:
+//!
+//! ```text
+//!   0x00  sequence number (u16)
+//!   0x02  opcode (u8)
+//!   0x03  status (u8), 0 in requests and for successful responses
+//!   0x04  payload length (u16)
+//!   0x06  reserved (u16), must be 0
+//!   0x08  payload
+//!   ....  CRC-32 (u32) of all preceding bytes of the frame, unaligned
+//! ```
+//!
+//! The CRC is the IEEE 802.3 CRC-32 computed by `digest::crc32`. On the PRU side, the firmware
+//! polls the request flag and, when it is set:
+//!
+//! 1. reads the request frame and clears the request flag,
+//! 2. verifies the CRC of the request and discards the request if it does not match,
+//! 3. executes the request,
+//! 4. writes the response frame with the sequence number and opcode of the request, a status
+//!    code and the response payload,
+//! 5. sets the response flag.
+//!
+//! The response flag must be set only after the whole response frame has been written.
+
+use {MemSegment, SegmentAddr};
+use digest;
+use util::VolatileCell;
+use wait::{self, SpinPolicy};
+use {volatile_copy_from, volatile_copy_to};
+
+use std::error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::result;
+use std::time::{Duration, Instant};
+
+
+/// Size of a frame header (in bytes).
+pub const FRAME_HEADER_SIZE: usize = 8;
+
+/// Size of the CRC trailing a frame (in bytes).
+pub const FRAME_CRC_SIZE: usize = 4;
+
+// Size of the flag words.
+const FLAGS_SIZE: usize = 8;
+
+
+
+/// RPC error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RpcError {
+    /// No response was received within the allotted time.
+    Timeout,
+    /// The frame is truncated or its CRC does not match.
+    Corrupted,
+    /// The payload does not fit in a slot.
+    PayloadTooLarge,
+    /// The firmware returned a non-zero status code.
+    Remote(u8),
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RpcError::Timeout => write!(f, "no response from the firmware"),
+            RpcError::Corrupted => write!(f, "corrupted frame"),
+            RpcError::PayloadTooLarge => write!(f, "payload too large"),
+            RpcError::Remote(status) => write!(f, "request failed with status {}", status),
+        }
+    }
+}
+
+impl error::Error for RpcError {
+    fn description(&self) -> &str {
+        match *self {
+            RpcError::Timeout => "timeout",
+            RpcError::Corrupted => "corrupted frame",
+            RpcError::PayloadTooLarge => "payload too large",
+            RpcError::Remote(_) => "remote error",
+        }
+    }
+}
+
+
+
+/// Request or response frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Sequence number, echoed by the firmware in the response.
+    pub sequence: u16,
+    /// Opcode, echoed by the firmware in the response.
+    pub opcode: u8,
+    /// Status code, 0 in requests and for successful responses.
+    pub status: u8,
+    /// Payload.
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Size of the encoded frame (in bytes).
+    pub fn encoded_len(&self) -> usize {
+        FRAME_HEADER_SIZE + self.payload.len() + FRAME_CRC_SIZE
+    }
+
+    /// Encodes the frame.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the payload is longer than 65535 bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        assert!(self.payload.len() <= u16::MAX as usize);
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&self.sequence.to_le_bytes());
+        bytes.push(self.opcode);
+        bytes.push(self.status);
+        bytes.extend_from_slice(&(self.payload.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(&self.payload);
+        let crc = digest::crc32(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+
+        bytes
+    }
+
+    /// Decodes a frame, ignoring any bytes following the CRC.
+    ///
+    /// # Errors
+    ///
+    /// `RpcError::Corrupted` is returned if the frame is truncated or if its CRC does not match.
+    pub fn decode(bytes: &[u8]) -> result::Result<Frame, RpcError> {
+        let len = try!(payload_len(bytes));
+        let end = FRAME_HEADER_SIZE + len;
+        if bytes.len() < end + FRAME_CRC_SIZE {
+            return Err(RpcError::Corrupted);
+        }
+        let crc = u32::from_le_bytes([bytes[end], bytes[end + 1], bytes[end + 2], bytes[end + 3]]);
+        if digest::crc32(&bytes[..end]) != crc {
+            return Err(RpcError::Corrupted);
+        }
+
+        Ok(Frame {
+            sequence: u16::from_le_bytes([bytes[0], bytes[1]]),
+            opcode: bytes[2],
+            status: bytes[3],
+            payload: bytes[FRAME_HEADER_SIZE..end].to_vec(),
+        })
+    }
+}
+
+
+
+// Flag words at the beginning of the segment.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct RpcFlags {
+    request: VolatileCell<u32>,
+    response: VolatileCell<u32>,
+}
+
+
+
+/// Host side of a request/reply channel.
+pub struct RpcChannel<'a> {
+    flags: &'a mut RpcFlags,
+    request: *mut u8,
+    response: *const u8,
+    slot_size: usize,
+    sequence: u16,
+    _memory_marker: PhantomData<&'a [u8]>,
+}
+
+impl<'a> RpcChannel<'a> {
+    /// Initializes the channel in the segment.
+    ///
+    /// The channel should be created before the firmware is started.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the beginning of the segment is not 4-byte aligned or if the
+    /// segment is too small to hold frames with an empty payload.
+    pub fn new<A: SegmentAddr>(segment: &'a mut MemSegment<A>) -> RpcChannel<'a> {
+        let size = segment.to - segment.from;
+        assert!(size >= FLAGS_SIZE);
+        let slot_size = ((size - FLAGS_SIZE) / 2) & !3;
+        assert!(slot_size >= FRAME_HEADER_SIZE + FRAME_CRC_SIZE);
+        let (request, response) = unsafe {
+            let request = segment.base.offset((segment.from + FLAGS_SIZE) as isize);
+            (request, request.offset(slot_size as isize) as *const u8)
+        };
+        let flags = segment.alloc(RpcFlags {
+            request: VolatileCell::new(0),
+            response: VolatileCell::new(0),
+        });
+
+        RpcChannel {
+            flags: flags,
+            request: request,
+            response: response,
+            slot_size: slot_size,
+            sequence: 0,
+            _memory_marker: PhantomData,
+        }
+    }
+
+    /// Maximum payload size (in bytes).
+    pub fn max_payload(&self) -> usize {
+        self.slot_size - FRAME_HEADER_SIZE - FRAME_CRC_SIZE
+    }
+
+    /// Sends a request and waits for the response.
+    ///
+    /// Responses to earlier requests that timed out are discarded.
+    ///
+    /// # Errors
+    ///
+    /// An `RpcError` is returned if the payload is too large, if no response is received before
+    /// the timeout expires, if the response is corrupted or if the firmware returns a non-zero
+    /// status.
+    pub fn call(&mut self,
+                opcode: u8,
+                payload: &[u8],
+                timeout: Duration)
+                -> result::Result<Vec<u8>, RpcError> {
+        if payload.len() > self.max_payload() {
+            return Err(RpcError::PayloadTooLarge);
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        let request = Frame {
+            sequence: self.sequence,
+            opcode: opcode,
+            status: 0,
+            payload: payload.to_vec(),
+        };
+        self.flags.response.set(0);
+        unsafe {
+            volatile_copy_to(self.request, &request.encode());
+        }
+        self.flags.request.set(1);
+
+        let start = Instant::now();
+        loop {
+            let remaining = match timeout.checked_sub(start.elapsed()) {
+                Some(remaining) => remaining,
+                None => return Err(RpcError::Timeout),
+            };
+            let policy = SpinPolicy {
+                budget: Some(remaining),
+                pause: true,
+            };
+            let flags: &RpcFlags = self.flags;
+            if !wait::spin_until(&policy, || flags.response.get() != 0) {
+                return Err(RpcError::Timeout);
+            }
+            let response = self.read_response();
+            self.flags.response.set(0);
+            let response = try!(response);
+            if response.sequence != request.sequence || response.opcode != opcode {
+                continue;
+            }
+            if response.status != 0 {
+                return Err(RpcError::Remote(response.status));
+            }
+
+            return Ok(response.payload);
+        }
+    }
+
+    // Reads and decodes the frame in the response slot.
+    fn read_response(&self) -> result::Result<Frame, RpcError> {
+        let mut header = [0u8; FRAME_HEADER_SIZE];
+        unsafe {
+            volatile_copy_from(&mut header, self.response);
+        }
+        let len = FRAME_HEADER_SIZE + try!(payload_len(&header)) + FRAME_CRC_SIZE;
+        if len > self.slot_size {
+            return Err(RpcError::Corrupted);
+        }
+        let mut bytes = vec![0u8; len];
+        unsafe {
+            volatile_copy_from(&mut bytes, self.response);
+        }
+
+        Frame::decode(&bytes)
+    }
+}
+
+unsafe impl<'a> Send for RpcChannel<'a> {}
+
+
+// Returns the payload length of a frame.
+fn payload_len(bytes: &[u8]) -> result::Result<usize, RpcError> {
+    if bytes.len() < FRAME_HEADER_SIZE {
+        return Err(RpcError::Corrupted);
+    }
+
+    Ok(u16::from_le_bytes([bytes[4], bytes[5]]) as usize)
+}
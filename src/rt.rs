@@ -0,0 +1,120 @@
+//! Real-time thread helpers.
+//!
+//! The jitter of the host-side handler often dominates the end-to-end latency of PRU
+//! applications. This module provides helpers to run PRU monitoring threads with a real-time
+//! `SCHED_FIFO` scheduling policy and a fixed CPU affinity.
+//!
+//! Note that setting a real-time priority usually requires root privileges or the
+//! `CAP_SYS_NICE` capability.
+
+use libc;
+
+use std::io;
+use std::mem;
+use std::sync::mpsc;
+use std::thread;
+
+
+/// Real-time configuration of a thread.
+#[derive(Clone, Debug, Default)]
+pub struct RtConfig {
+    /// `SCHED_FIFO` priority (between 1 and 99), or `None` to keep the current scheduling policy.
+    pub fifo_priority: Option<i32>,
+    /// CPUs on which the thread may run, or an empty vector to keep the current affinity.
+    pub cpus: Vec<usize>,
+}
+
+impl RtConfig {
+    /// Applies the configuration to the calling thread.
+    ///
+    /// This can be used for threads that are not spawned with `spawn`, for instance scoped
+    /// threads.
+    ///
+    /// # Errors
+    ///
+    /// OS errors that may occur while setting the scheduling policy or the affinity are forwarded.
+    pub fn apply(&self) -> io::Result<()> {
+        if let Some(priority) = self.fifo_priority {
+            let param = libc::sched_param { sched_priority: priority };
+            let err = unsafe {
+                libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+            };
+            if err != 0 {
+                return Err(io::Error::from_raw_os_error(err));
+            }
+        }
+
+        if !self.cpus.is_empty() {
+            unsafe {
+                let mut cpuset: libc::cpu_set_t = mem::zeroed();
+                libc::CPU_ZERO(&mut cpuset);
+                for &cpu in &self.cpus {
+                    libc::CPU_SET(cpu, &mut cpuset);
+                }
+                if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &cpuset) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Spawns a thread with the specified real-time configuration.
+///
+/// The configuration is applied from within the new thread before the closure is run.
+///
+/// # Errors
+///
+/// If the configuration cannot be applied, the closure is not run and the OS error is returned.
+/// Errors that may occur while spawning the thread are forwarded.
+pub fn spawn<F, T>(config: &RtConfig, f: F) -> io::Result<JoinHandle<T>>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    let config = config.clone();
+    let (tx, rx) = mpsc::channel();
+    let handle = try!(thread::Builder::new().spawn(move || {
+        let status = config.apply();
+        let is_ok = status.is_ok();
+        let _ = tx.send(status);
+        if is_ok {
+            Some(f())
+        } else {
+            None
+        }
+    }));
+
+    match rx.recv() {
+        Ok(Ok(())) => Ok(JoinHandle(handle)),
+        Ok(Err(e)) => {
+            let _ = handle.join();
+            Err(e)
+        }
+        Err(_) => Err(io::Error::other("thread terminated prematurely")),
+    }
+}
+
+
+
+/// Handle to a thread spawned with `spawn`.
+pub struct JoinHandle<T>(thread::JoinHandle<Option<T>>);
+
+impl<T> JoinHandle<T> {
+    /// Returns the handle of the underlying thread.
+    pub fn thread(&self) -> &thread::Thread {
+        self.0.thread()
+    }
+
+    /// Waits for the thread to finish and returns the result of the closure.
+    ///
+    /// # Errors
+    ///
+    /// If the closure panicked, the panic payload is returned.
+    pub fn join(self) -> thread::Result<T> {
+        // The closure is always run by threads whose handle was returned by `spawn`.
+        self.0.join().map(|result| result.unwrap())
+    }
+}
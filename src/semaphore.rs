@@ -0,0 +1,132 @@
+//! Turn-taking between the host and a PRU.
+//!
+//! A `Handshake` formalizes the common protocol in which the host writes shared data, sets a
+//! flag in the PRU data RAM and notifies the firmware with a system event, after which the
+//! firmware processes the data, resets the flag and notifies the host through an event out. The
+//! flag, which acts as a binary semaphore, tells whose turn it is to access the shared data:
+//!
+//! ```text
+//!   HOST_TURN (0)  the host may access the shared data; set by the firmware when done
+//!   PRU_TURN (1)   the PRU may access the shared data; set by the host in `post`
+//! ```
+//!
+//! Since the turn is decided by the flag rather than by the notifications, a spurious or
+//! coalesced event out cannot make the host believe that the firmware is done, and a
+//! notification missed by the firmware does not prevent it from seeing its turn by polling the
+//! flag.
+//!
+//! ```no_run
+//! # use prusst::{Pruss, IntcConfig, Evtout, Sysevt};
+//! # use prusst::semaphore::Handshake;
+//! # use prusst::util::VolatileCell;
+//! # use std::time::Duration;
+//! # let mut pruss = Pruss::new(&IntcConfig::new_populated()).unwrap();
+//! let irq = pruss.intc.register_irq(Evtout::E0);
+//! let flag = pruss.dram0.alloc(VolatileCell::new(0u32));
+//! let mut handshake = Handshake::new(&pruss.intc, Sysevt::S21, &irq, Sysevt::S19, flag);
+//! // Write the request to the shared data, then hand it over to the firmware.
+//! handshake.post();
+//! if handshake.take(Duration::from_millis(10)) {
+//!     // Read the reply from the shared data.
+//! }
+//! ```
+
+use {Intc, EvtoutIrq, Sysevt};
+use util::VolatileCell;
+
+use std::sync::atomic::{self, Ordering};
+use std::time::{Duration, Instant};
+
+
+/// Flag value giving the turn to the host.
+pub const HOST_TURN: u32 = 0;
+
+/// Flag value giving the turn to the PRU.
+pub const PRU_TURN: u32 = 1;
+
+
+
+/// Turn-taking primitive between the host and a PRU.
+pub struct Handshake<'a> {
+    intc: &'a Intc,
+    notify: Sysevt,
+    irq: &'a EvtoutIrq,
+    reply: Sysevt,
+    flag: &'a mut VolatileCell<u32>,
+}
+
+impl<'a> Handshake<'a> {
+    /// Creates a handshake held by the host.
+    ///
+    /// The `notify` system event is sent to the firmware when the turn is handed over to the
+    /// PRU, and the firmware hands the turn back by setting the flag to `HOST_TURN` and then
+    /// triggering the `reply` system event, which must be mapped to the event out monitored by
+    /// `irq`.
+    ///
+    /// The flag is initialized to `HOST_TURN`, so the handshake should be created before the
+    /// firmware is started.
+    pub fn new<N, R>(intc: &'a Intc,
+                     notify: N,
+                     irq: &'a EvtoutIrq,
+                     reply: R,
+                     flag: &'a mut VolatileCell<u32>)
+                     -> Handshake<'a>
+        where N: Into<Sysevt>,
+              R: Into<Sysevt>
+    {
+        flag.set(HOST_TURN);
+
+        Handshake {
+            intc: intc,
+            notify: notify.into(),
+            irq: irq,
+            reply: reply.into(),
+            flag: flag,
+        }
+    }
+
+    /// Hands the turn over to the PRU and notifies the firmware.
+    ///
+    /// Returns `false` without notifying the firmware if the PRU already has the turn.
+    pub fn post(&mut self) -> bool {
+        if !self.is_host_turn() {
+            return false;
+        }
+        // Make sure the shared data is written to memory before the turn is handed over.
+        atomic::fence(Ordering::SeqCst);
+        self.flag.set(PRU_TURN);
+        self.intc.send_sysevt(self.notify);
+
+        true
+    }
+
+    /// Waits until the firmware hands the turn back to the host.
+    ///
+    /// Event outs received in the meantime are acknowledged. Returns `true` immediately if the
+    /// host already has the turn, or `false` if the turn was not handed back before the timeout
+    /// expired or if waiting on the event out device failed.
+    pub fn take(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.is_host_turn() {
+                // Make sure the shared data is not read before the turn is handed back.
+                atomic::fence(Ordering::SeqCst);
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            match self.irq.try_wait_timeout(deadline - now) {
+                Ok(Some(_)) => self.intc.ack(self.reply, self.irq.get_evtout()),
+                Ok(None) => {}
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// Checks whether the host has the turn.
+    pub fn is_host_turn(&self) -> bool {
+        self.flag.get() == HOST_TURN
+    }
+}
@@ -0,0 +1,163 @@
+//! Snapshot of the PRU subsystem state.
+//!
+//! The PRU subsystem loses its state across system suspend. Applications that must survive a
+//! suspend/resume cycle can capture the state of the PRU subsystem with `Pruss::snapshot` before
+//! suspending and restore it with `Pruss::restore` once resumed.
+
+use {Pruss, PruLoader, PruCtrl, CompiledIntcConfig};
+use def::*;
+use {volatile_copy_from, volatile_copy_to};
+
+use std::cmp;
+use std::ptr;
+
+
+
+/// State of a PRU core.
+#[derive(Clone, Debug)]
+pub struct PruState {
+    /// Content of the instruction RAM.
+    pub iram: Vec<u8>,
+    /// Content of the control register.
+    pub ctrl: u32,
+    /// Program counter (as a word address).
+    pub pc: u32,
+    /// General purpose registers R0 to R31.
+    pub regs: [u32; 32],
+}
+
+
+
+/// Snapshot of the PRU subsystem state.
+///
+/// The snapshot contains the instruction RAMs, the data RAMs, the control, program counter and
+/// general purpose registers of both PRUs and the interrupt controller mapping. The host memory,
+/// which resides in the system DRAM and is not lost during suspend, is not part of the snapshot;
+/// neither are the cycle and stall counters, the constant table configuration and the pending
+/// system events.
+#[derive(Clone)]
+pub struct Snapshot {
+    /// State of PRU0.
+    pub pru0: PruState,
+    /// State of PRU1.
+    pub pru1: PruState,
+    /// Content of the data RAM of PRU0.
+    pub dram0: Vec<u8>,
+    /// Content of the data RAM of PRU1.
+    pub dram1: Vec<u8>,
+    /// Content of the shared data RAM.
+    pub dram2: Vec<u8>,
+    /// Interrupt controller mapping.
+    pub intc: CompiledIntcConfig,
+}
+
+
+
+impl<'a> Pruss<'a> {
+    /// Captures the state of the PRU subsystem.
+    ///
+    /// Both PRUs are halted so that their registers can be read; use `Pruss::restore` to resume
+    /// their execution.
+    ///
+    /// Since this function borrows the `Pruss` instance mutably, memory segments cannot be
+    /// concurrently allocated; data RAMs are nevertheless captured entirely, including regions
+    /// that were previously split.
+    pub fn snapshot(&mut self) -> Snapshot {
+        let pru0 = capture_pru(&mut self.pru0);
+        let pru1 = capture_pru(&mut self.pru1);
+        let base = self._prumap.base;
+
+        Snapshot {
+            pru0: pru0,
+            pru1: pru1,
+            dram0: unsafe { capture_mem(base, DRAM0_OFFSET, DRAM0_SIZE) },
+            dram1: unsafe { capture_mem(base, DRAM1_OFFSET, DRAM1_SIZE) },
+            dram2: unsafe { capture_mem(base, DRAM2_OFFSET, DRAM2_SIZE) },
+            intc: self.intc.read_image(),
+        }
+    }
+
+    /// Restores the state of the PRU subsystem.
+    ///
+    /// Memories and registers are restored first, then the interrupt controller mapping is
+    /// re-established and finally the PRUs that were running when the snapshot was taken are
+    /// restarted from their saved program counter.
+    ///
+    /// # Safety
+    ///
+    /// This resumes the execution of binary codes that have unrestricted access to pretty much
+    /// all the processor memory and peripherals.
+    pub unsafe fn restore(&mut self, snapshot: &Snapshot) {
+        let base = self._prumap.base;
+        restore_pru(&mut self.pru0, &snapshot.pru0);
+        restore_pru(&mut self.pru1, &snapshot.pru1);
+        restore_mem(base, DRAM0_OFFSET, DRAM0_SIZE, &snapshot.dram0);
+        restore_mem(base, DRAM1_OFFSET, DRAM1_SIZE, &snapshot.dram1);
+        restore_mem(base, DRAM2_OFFSET, DRAM2_SIZE, &snapshot.dram2);
+        self.intc.map_compiled_interrupts(&snapshot.intc);
+        resume_pru(&mut self.pru0, &snapshot.pru0);
+        resume_pru(&mut self.pru1, &snapshot.pru1);
+    }
+}
+
+
+// Halts a PRU and captures its state.
+fn capture_pru(loader: &mut PruLoader) -> PruState {
+    unsafe {
+        let ctrl = PruCtrl::read(loader.pructrl_reg);
+        ctrl.with_enable(false).write(loader.pructrl_reg);
+        let pc = ptr::read_volatile(loader.pructrl_reg.offset(PRUSTATUS_REG)) & 0xffff;
+        let mut regs = [0u32; 32];
+        for (i, reg) in regs.iter_mut().enumerate() {
+            *reg = ptr::read_volatile(loader.pructrl_reg.offset(PRUDBG_GPREG + i as isize));
+        }
+
+        PruState {
+            iram: capture_mem(loader.iram_base, 0, loader.iram_size),
+            ctrl: ctrl.bits(),
+            pc: pc,
+            regs: regs,
+        }
+    }
+}
+
+
+// Restores the state of a PRU, leaving it halted.
+unsafe fn restore_pru(loader: &mut PruLoader, state: &PruState) {
+    restore_mem(loader.iram_base, 0, loader.iram_size, &state.iram);
+    // Soft-reset the PRU so that its program counter is loaded from the reset value field, then
+    // release the reset while keeping the PRU halted. The counter enable and single step bits
+    // are preserved.
+    let saved = PruCtrl::from_bits(state.ctrl);
+    let ctrl = PruCtrl::default()
+        .with_pctr_rst_val(state.pc as u16)
+        .with_counter_enable(saved.counter_enable())
+        .with_single_step(saved.single_step());
+    ctrl.write(loader.pructrl_reg);
+    ctrl.with_soft_rst_n(true).write(loader.pructrl_reg);
+    for (i, &reg) in state.regs.iter().enumerate() {
+        ptr::write_volatile(loader.pructrl_reg.offset(PRUDBG_GPREG + i as isize), reg);
+    }
+}
+
+
+// Restarts a PRU if it was running when its state was captured.
+unsafe fn resume_pru(loader: &mut PruLoader, state: &PruState) {
+    if PruCtrl::from_bits(state.ctrl).enable() {
+        PruCtrl::read(loader.pructrl_reg).with_enable(true).write(loader.pructrl_reg);
+    }
+}
+
+
+unsafe fn capture_mem(base: *mut u8, offset: usize, size: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    volatile_copy_from(&mut data, base.offset(offset as isize));
+
+    data
+}
+
+
+unsafe fn restore_mem(base: *mut u8, offset: usize, size: usize, data: &[u8]) {
+    let len = cmp::min(size, data.len());
+    volatile_copy_to(base.offset(offset as isize), &data[..len]);
+}
@@ -0,0 +1,167 @@
+//! Aggregated status of the PRU subsystem.
+
+use {Intc, Pruss, PruLoader, PruCtrl};
+use def::*;
+
+use std::fmt;
+use std::ptr;
+
+
+
+/// Status of a PRU core.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CoreStatus {
+    /// Content of the control register.
+    pub ctrl: PruCtrl,
+    /// Whether the core is executing instructions.
+    pub running: bool,
+    /// Whether the core is sleeping.
+    pub sleeping: bool,
+    /// Whether the cycle and stall counters are enabled.
+    pub counters_enabled: bool,
+    /// Program counter (as a word address).
+    pub pc: u32,
+    /// Cycle counter.
+    pub cycles: u32,
+    /// Stall counter.
+    pub stalls: u32,
+}
+
+
+
+/// Status of the interrupt controller.
+///
+/// System events and host interrupts are listed by number in increasing order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntcStatus {
+    /// Pending system events, whether enabled or not (SRSR registers).
+    pub raw_pending: Vec<u8>,
+    /// Pending system events which are enabled (SECR registers).
+    pub enabled_pending: Vec<u8>,
+    /// Enabled system events (ESR registers).
+    pub enabled_sysevts: Vec<u8>,
+    /// Enabled host interrupts (HIER register).
+    pub enabled_hosts: Vec<u8>,
+}
+
+/// Formats the status on a single line.
+impl fmt::Display for IntcStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "raw pending: {:?}, enabled pending: {:?}, enabled sysevts: {:?}, enabled \
+                hosts: {:?}",
+               self.raw_pending,
+               self.enabled_pending,
+               self.enabled_sysevts,
+               self.enabled_hosts)
+    }
+}
+
+
+
+/// Status of the PRU subsystem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrussStatus {
+    /// Status of PRU0.
+    pub pru0: CoreStatus,
+    /// Status of PRU1.
+    pub pru1: CoreStatus,
+    /// Pending system events, whether enabled or not.
+    pub pending_sysevts: Vec<u8>,
+    /// Enabled host interrupts.
+    pub enabled_hosts: Vec<u8>,
+    /// Size of the mapping of the PRU memory and registers (in bytes).
+    pub prumem_size: usize,
+    /// Size of the mapping of the host memory (in bytes).
+    pub hostmem_size: usize,
+}
+
+/// Formats the status on a single line, e.g. for periodic health logging.
+impl fmt::Display for PrussStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "pru0: {}, pru1: {}, pending sysevts: {:?}, enabled hosts: {:?}",
+                    self.pru0,
+                    self.pru1,
+                    self.pending_sysevts,
+                    self.enabled_hosts));
+        write!(f, ", prumem: {:#x}, hostmem: {:#x}", self.prumem_size, self.hostmem_size)
+    }
+}
+
+impl fmt::Display for CoreStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match (self.running, self.sleeping) {
+            (_, true) => "sleeping",
+            (true, false) => "running",
+            (false, false) => "halted",
+        };
+        try!(write!(f, "{} at {:#06x}", state, self.pc));
+        if self.counters_enabled {
+            try!(write!(f, " ({} cycles, {} stalls)", self.cycles, self.stalls));
+        }
+
+        Ok(())
+    }
+}
+
+
+
+impl<'a> Pruss<'a> {
+    /// Returns the status of the PRU cores and of the interrupt controller.
+    ///
+    /// The registers are read one after the other while the PRUs keep running, so the status is
+    /// not an atomic snapshot.
+    pub fn status(&self) -> PrussStatus {
+        let intc = self.intc.status();
+
+        PrussStatus {
+            pru0: core_status(&self.pru0),
+            pru1: core_status(&self.pru1),
+            pending_sysevts: intc.raw_pending,
+            enabled_hosts: intc.enabled_hosts,
+            prumem_size: self.prumem_size(),
+            hostmem_size: self.hostmem_size(),
+        }
+    }
+}
+
+
+
+impl Intc {
+    /// Returns the status of the system events and host interrupts.
+    ///
+    /// The registers are read one after the other, so the status is not an atomic snapshot.
+    pub fn status(&self) -> IntcStatus {
+        let reg = |offset: isize| unsafe { ptr::read_volatile(self.intc_reg.offset(offset)) };
+        let sysevts = |low: u32, high: u32| {
+            let bits = (high as u64) << 32 | low as u64;
+            (0..NUM_SYSEVTS).filter(|&se| bits & (1 << se) != 0).collect()
+        };
+        let hier = reg(HIER_REG);
+
+        IntcStatus {
+            raw_pending: sysevts(reg(SRSR1_REG), reg(SRSR2_REG)),
+            enabled_pending: sysevts(reg(SECR1_REG), reg(SECR2_REG)),
+            enabled_sysevts: sysevts(reg(ESR1_REG), reg(ESR2_REG)),
+            enabled_hosts: (0..NUM_HOSTS).filter(|&h| hier & (1 << h) != 0).collect(),
+        }
+    }
+}
+
+
+fn core_status(loader: &PruLoader) -> CoreStatus {
+    unsafe {
+        let reg = |offset: isize| ptr::read_volatile(loader.pructrl_reg.offset(offset));
+        let ctrl = PruCtrl::read(loader.pructrl_reg);
+
+        CoreStatus {
+            ctrl: ctrl,
+            running: ctrl.runstate(),
+            sleeping: ctrl.sleeping(),
+            counters_enabled: ctrl.counter_enable(),
+            pc: reg(PRUSTATUS_REG) & 0xffff,
+            cycles: reg(PRUCYCLE_REG),
+            stalls: reg(PRUSTALL_REG),
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Streaming of samples from the PRU to the host through a shared ring buffer.
+//!
+//! The ring buffer occupies a whole memory segment: a header is located at the beginning of the
+//! segment and is followed by the data area. The PRU firmware writes samples at the write index
+//! and advances it, while the host reads samples at the read index and advances it. Both indices
+//! are byte offsets within the data area; the ring is empty when they are equal and full when
+//! the write index is one byte behind the read index, so a ring can hold at most `capacity - 1`
+//! bytes.
+//!
+//! The firmware never overwrites unread data: samples that do not fit in the ring are dropped
+//! and accounted for in the overrun counter. The firmware also maintains a high watermark, namely
+//! the largest number of unread bytes it has observed, which helps sizing the ring and the rate
+//! at which the host drains it.
+//!
+//! The header is laid out as follows:
+//!
+//! ```text
+//!   0x00  write index   written by the PRU
+//!   0x04  read index    written by the host
+//!   0x08  capacity      size of the data area in bytes, written by the host
+//!   0x0c  overruns      number of bytes dropped because the ring was full, written by the PRU
+//!   0x10  watermark     largest number of unread bytes observed, written by the PRU
+//!   0x14  reserved
+//!   0x18  reserved
+//!   0x1c  reserved
+//! ```
+
+use {MemSegment, SegmentAddr};
+use util::VolatileCell;
+use volatile_copy_from;
+
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+
+
+/// Size of the ring buffer header (in bytes).
+pub const RING_HEADER_SIZE: usize = 32;
+
+
+
+/// Header of a ring buffer shared with the PRU firmware.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct RingHeader {
+    /// Write index, written by the PRU.
+    pub write: VolatileCell<u32>,
+    /// Read index, written by the host.
+    pub read: VolatileCell<u32>,
+    /// Size of the data area in bytes.
+    pub capacity: VolatileCell<u32>,
+    /// Number of bytes dropped because the ring was full, written by the PRU.
+    pub overruns: VolatileCell<u32>,
+    /// Largest number of unread bytes observed, written by the PRU.
+    pub high_watermark: VolatileCell<u32>,
+    _reserved: [VolatileCell<u32>; 3],
+}
+
+
+
+/// Reader of a ring buffer filled by the PRU firmware.
+pub struct StreamReader<'a> {
+    header: &'a mut RingHeader,
+    data: *const u8,
+    capacity: usize,
+    host_watermark: usize,
+    _memory_marker: PhantomData<&'a [u8]>,
+}
+
+impl<'a> StreamReader<'a> {
+    /// Initializes an empty ring buffer spanning the whole segment.
+    ///
+    /// The reader should be created before the firmware is started.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the beginning of the segment is not 4-byte aligned or if the
+    /// segment is not large enough to hold the header and at least 2 bytes of data.
+    pub fn new<A: SegmentAddr>(segment: &'a mut MemSegment<A>) -> StreamReader<'a> {
+        assert!(mem::size_of::<RingHeader>() == RING_HEADER_SIZE);
+        assert!(segment.to - segment.from >= RING_HEADER_SIZE + 2);
+        let capacity = segment.to - segment.from - RING_HEADER_SIZE;
+        let data = unsafe { segment.base.offset((segment.from + RING_HEADER_SIZE) as isize) };
+        let header = segment.alloc(RingHeader {
+            write: VolatileCell::new(0),
+            read: VolatileCell::new(0),
+            capacity: VolatileCell::new(capacity as u32),
+            overruns: VolatileCell::new(0),
+            high_watermark: VolatileCell::new(0),
+            _reserved: [VolatileCell::new(0); 3],
+        });
+
+        StreamReader {
+            header: header,
+            data: data,
+            capacity: capacity,
+            host_watermark: 0,
+            _memory_marker: PhantomData,
+        }
+    }
+
+    /// Size of the data area in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of bytes that can be read without blocking.
+    pub fn available(&self) -> usize {
+        let (write, read) = self.indices();
+
+        (write + self.capacity - read) % self.capacity
+    }
+
+    /// Number of bytes dropped by the firmware because the ring was full.
+    ///
+    /// The counter is maintained by the firmware and wraps around on overflow.
+    pub fn overruns(&self) -> u32 {
+        self.header.overruns.get()
+    }
+
+    /// Largest number of unread bytes observed so far.
+    ///
+    /// This is the largest of the watermark maintained by the firmware and of the fill levels
+    /// observed by the host when reading. A watermark close to `capacity - 1` announces imminent
+    /// overruns.
+    pub fn high_watermark(&self) -> usize {
+        cmp::max(self.header.high_watermark.get() as usize, self.host_watermark)
+    }
+
+    /// Resets the overrun counter and the high watermark.
+    ///
+    /// An update of the counters made by the firmware concurrently with the reset may be lost.
+    pub fn reset_stats(&mut self) {
+        self.header.overruns.set(0);
+        self.header.high_watermark.set(0);
+        self.host_watermark = 0;
+    }
+
+    /// Reads as many bytes as available into the buffer and returns the number of bytes read.
+    ///
+    /// This function never blocks and returns 0 if the ring is empty.
+    pub fn read_available(&mut self, buf: &mut [u8]) -> usize {
+        let (write, mut read) = self.indices();
+        let available = (write + self.capacity - read) % self.capacity;
+        self.host_watermark = cmp::max(self.host_watermark, available);
+        let n = cmp::min(buf.len(), available);
+        let mut done = 0;
+        while done < n {
+            let chunk = cmp::min(n - done, self.capacity - read);
+            unsafe {
+                volatile_copy_from(&mut buf[done..done + chunk], self.data.offset(read as isize));
+            }
+            done += chunk;
+            read = (read + chunk) % self.capacity;
+        }
+        self.header.read.set(read as u32);
+
+        n
+    }
+
+    /// Discards all bytes available in the ring.
+    pub fn skip_available(&mut self) {
+        let (write, _) = self.indices();
+        self.header.read.set(write as u32);
+    }
+
+    // Returns the write and read indices, with a corrupted write index treated as an empty ring.
+    fn indices(&self) -> (usize, usize) {
+        let read = self.header.read.get() as usize;
+        let write = self.header.write.get() as usize;
+        if write < self.capacity {
+            (write, read)
+        } else {
+            (read, read)
+        }
+    }
+}
+
+unsafe impl<'a> Send for StreamReader<'a> {}
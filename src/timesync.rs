@@ -0,0 +1,187 @@
+//! Correlation of PRU counters with the host monotonic clock.
+//!
+//! Timestamps captured by the PRU firmware with the IEP timer or with a cycle counter can be
+//! merged with host-side logs once an affine mapping between the counter and `CLOCK_MONOTONIC`
+//! has been established with `calibrate`.
+
+use {Pruss, PruCore};
+use def::*;
+
+use libc;
+
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+
+
+/// A free-running 32-bit PRU counter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Counter {
+    /// Counter of the Industrial Ethernet Peripheral timer.
+    Iep,
+    /// Cycle counter of a PRU core, which only counts while the core is running and the counter
+    /// is enabled in its control register.
+    Cycle(PruCore),
+}
+
+
+
+/// Affine mapping from a PRU counter to the host monotonic clock.
+///
+/// The mapping is expressed relative to a reference counter value, namely the raw counter
+/// value read at the first calibration sample.
+#[derive(Copy, Clone, Debug)]
+pub struct Calibration {
+    /// Counter value taken as reference.
+    pub reference: u32,
+    /// Host monotonic time at the reference counter value.
+    pub reference_time: Duration,
+    /// Measured counter rate (in ticks per second of host time).
+    pub rate: f64,
+    /// Relative deviation of the measured rate from the nominal rate (in parts per million).
+    pub drift_ppm: f64,
+    /// Root mean square residual of the fit (in seconds).
+    pub residual: f64,
+}
+
+impl Calibration {
+    /// Converts a raw counter value to host monotonic time.
+    ///
+    /// The counter value is assumed to lie within half a wrap-around period of the reference
+    /// value; counter values that are not close to the calibration time should be converted with
+    /// `host_time_extended`.
+    pub fn host_time(&self, ticks: u32) -> Duration {
+        self.host_time_extended(ticks.wrapping_sub(self.reference) as i32 as i64)
+    }
+
+    /// Converts a counter value expressed as a signed number of ticks elapsed since the
+    /// reference value to host monotonic time.
+    ///
+    /// Times preceding the origin of the monotonic clock saturate to zero.
+    pub fn host_time_extended(&self, ticks_since_reference: i64) -> Duration {
+        let t = self.reference_time.as_secs_f64() + ticks_since_reference as f64 / self.rate;
+        if t > 0.0 {
+            Duration::from_secs_f64(t)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+}
+
+
+
+/// Calibrates a PRU counter against the host monotonic clock.
+///
+/// The counter is sampled `samples` times at `interval` intervals, bracketing each read with
+/// host clock reads so that the host time assigned to each sample is the midpoint of the
+/// bracket. The mapping is then obtained by a least-squares fit, with counter wrap-arounds
+/// accounted for provided that the interval is shorter than the wrap-around period. The drift is
+/// estimated against `nominal_hz`, the nominal counter rate.
+///
+/// For the IEP counter, the nominal rate is the IEP clock frequency multiplied by the default
+/// increment configured in the IEP global configuration register.
+///
+/// Returns `None` if the counter did not advance during the calibration, e.g. because it is not
+/// enabled, or if the fitted rate is not positive.
+///
+/// # Panics
+///
+/// This function will panic if fewer than 2 samples are requested.
+pub fn calibrate(pruss: &Pruss,
+                 counter: Counter,
+                 nominal_hz: f64,
+                 samples: usize,
+                 interval: Duration)
+                 -> Option<Calibration> {
+    assert!(samples >= 2);
+
+    let reg = counter_reg(pruss, counter);
+    let mut points: Vec<(f64, f64)> = Vec::with_capacity(samples);
+    let mut reference = 0;
+    let mut reference_time = Duration::from_secs(0);
+    let mut last = 0u32;
+    let mut ticks = 0i64;
+    for i in 0..samples {
+        if i != 0 {
+            thread::sleep(interval);
+        }
+        let before = monotonic();
+        let raw = unsafe { ptr::read_volatile(reg) };
+        let after = monotonic();
+        let time = before + (after - before) / 2;
+        if i == 0 {
+            reference = raw;
+            reference_time = time;
+        } else {
+            ticks += raw.wrapping_sub(last) as i64;
+        }
+        last = raw;
+        points.push((ticks as f64, (time - reference_time).as_secs_f64()));
+    }
+
+    // Least-squares fit of host time against counter ticks.
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.0 - mean_x)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    let rate = 1.0 / slope;
+    if !rate.is_finite() || rate <= 0.0 {
+        return None;
+    }
+    let intercept = mean_y - slope * mean_x;
+    let residual = (points.iter()
+        .map(|p| {
+            let e = p.1 - (intercept + slope * p.0);
+            e * e
+        })
+        .sum::<f64>() / n)
+        .sqrt();
+    let reference_secs = reference_time.as_secs_f64() + intercept;
+
+    Some(Calibration {
+        reference: reference,
+        reference_time: Duration::from_secs_f64(reference_secs.max(0.0)),
+        rate: rate,
+        drift_ppm: (rate / nominal_hz - 1.0) * 1e6,
+        residual: residual,
+    })
+}
+
+
+/// Reads the current value of a PRU counter.
+pub fn read_counter(pruss: &Pruss, counter: Counter) -> u32 {
+    unsafe { ptr::read_volatile(counter_reg(pruss, counter)) }
+}
+
+
+fn counter_reg(pruss: &Pruss, counter: Counter) -> *const u32 {
+    unsafe {
+        match counter {
+            Counter::Iep => {
+                (pruss._prumap.base.offset(IEP_OFFSET as isize) as *const u32).offset(IEP_COUNT_REG)
+            }
+            Counter::Cycle(PruCore::Pru0) => pruss.pru0.pructrl_reg.offset(PRUCYCLE_REG),
+            Counter::Cycle(PruCore::Pru1) => pruss.pru1.pructrl_reg.offset(PRUCYCLE_REG),
+        }
+    }
+}
+
+
+// Reads CLOCK_MONOTONIC.
+fn monotonic() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
@@ -1,6 +1,10 @@
 //! Useful objects and functions.
 
+pub use digest::crc32;
+
+use std::hint;
 use std::ptr::{write_volatile, read_volatile};
+use std::sync::atomic::{fence, Ordering};
 
 
 
@@ -45,3 +49,91 @@ impl<T> VolatileCell<T> {
     }
 }
 
+
+
+/// A value updated by the PRU and protected by a sequence counter.
+///
+/// This allows the host to take tear-free snapshots of structures larger than a bus word while
+/// the PRU keeps updating them. The layout is that of the following C structure:
+///
+/// ```c
+/// struct seqlocked {
+///     volatile uint32_t seq;
+///     T value;
+/// };
+/// ```
+///
+/// The firmware must increment the sequence counter before and after each update, so that the
+/// counter is odd while an update is in progress:
+///
+/// ```c
+/// s->seq++;
+/// /* update s->value */
+/// s->seq++;
+/// ```
+///
+/// The host retries reads that overlap an update.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct SeqLocked<T: Copy> {
+    seq: VolatileCell<u32>,
+    value: T,
+}
+
+impl<T: Copy> SeqLocked<T> {
+    /// Creates a new `SeqLocked` containing the given value, with a zero sequence counter.
+    pub fn new(value: T) -> SeqLocked<T> {
+        SeqLocked {
+            seq: VolatileCell::new(0),
+            value: value,
+        }
+    }
+
+    /// Returns a consistent copy of the contained value, spinning while an update is in
+    /// progress.
+    pub fn read(&self) -> T {
+        loop {
+            if let Some(value) = self.try_read() {
+                return value;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    /// Returns a copy of the contained value, or `None` if the read overlapped an update.
+    pub fn try_read(&self) -> Option<T> {
+        let before = self.seq.get();
+        if before & 1 != 0 {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        let value = unsafe { read_volatile(&self.value as *const T) };
+        fence(Ordering::Acquire);
+        match self.seq.get() == before {
+            true => Some(value),
+            false => None,
+        }
+    }
+
+    /// Returns the current value of the sequence counter.
+    ///
+    /// The counter advances by 2 with each completed update.
+    pub fn sequence(&self) -> u32 {
+        self.seq.get()
+    }
+
+    /// Updates the contained value following the same protocol as the firmware.
+    ///
+    /// This is only meant for values updated by the host and read by the firmware; the firmware
+    /// and the host must never update the same value.
+    pub fn write(&mut self, value: T) {
+        let seq = self.seq.get();
+        self.seq.set(seq.wrapping_add(1));
+        fence(Ordering::Release);
+        unsafe {
+            write_volatile(&mut self.value as *mut T, value);
+        }
+        fence(Ordering::Release);
+        self.seq.set(seq.wrapping_add(2));
+    }
+}
@@ -0,0 +1,83 @@
+//! Waiting strategies.
+
+use libc;
+
+use std::cmp;
+use std::hint;
+use std::time::{Duration, Instant};
+
+
+/// Busy-wait polling strategy.
+///
+/// Busy-waiting on the status of a system event bypasses the kernel entirely and therefore avoids
+/// the scheduler latency associated with a blocking wait on an event out, at the cost of keeping
+/// a CPU busy.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SpinPolicy {
+    /// Maximum spinning duration, or `None` to spin until the system event is pending.
+    pub budget: Option<Duration>,
+    /// Whether a spin-loop hint should be issued to the processor at each polling iteration.
+    pub pause: bool,
+}
+
+
+
+/// Strategy used to wait for an event out.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum WaitPolicy {
+    /// Blocks on the event out device until the host interrupt is signaled by the kernel.
+    #[default]
+    Block,
+    /// Polls the host interrupt status of the interrupt controller according to the spin policy
+    /// and then blocks on the event out device.
+    ///
+    /// When the host interrupt is detected while spinning, the subsequent blocking read returns
+    /// almost immediately since the calling thread has not been descheduled in the meantime. The
+    /// spin budget hence sets the trade-off between latency and CPU usage.
+    SpinThenBlock(SpinPolicy),
+}
+
+/// Spins until the condition is met or the spin budget is exhausted.
+///
+/// Returns `true` if the condition was met.
+pub fn spin_until<F: Fn() -> bool>(policy: &SpinPolicy, condition: F) -> bool {
+    let start = Instant::now();
+    loop {
+        if condition() {
+            return true;
+        }
+        if let Some(budget) = policy.budget {
+            if start.elapsed() >= budget {
+                return false;
+            }
+        }
+        if policy.pause {
+            hint::spin_loop();
+        }
+    }
+}
+
+
+/// Converts a timeout to a `poll` timeout, rounding up to the next millisecond.
+pub fn poll_timeout_ms(timeout: Duration) -> libc::c_int {
+    let mut ms = timeout.as_millis();
+    if !timeout.subsec_nanos().is_multiple_of(1000000) {
+        ms += 1;
+    }
+
+    cmp::min(ms, libc::c_int::MAX as u128) as libc::c_int
+}
+
+
+/// Interrupt re-arming semantics of the UIO driver backing event outs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum IrqControl {
+    /// The interrupt is only re-enabled through the PRU interrupt controller, as is appropriate
+    /// for the `uio_pruss` driver.
+    #[default]
+    Off,
+    /// In addition, the interrupt is re-armed by writing 1 to the device file at the beginning of
+    /// each wait, i.e. after the previous interrupt was read, as required by `uio_pdrv_genirq` and
+    /// similar drivers.
+    Rearm,
+}
@@ -0,0 +1,143 @@
+//! Fake UIO device tree for tests running without PRU hardware.
+//!
+//! The PRU memory and host memory are backed by a regular file standing in for `/dev/uio0`, and
+//! the event out devices `/dev/uio1` to `/dev/uio7` are FIFOs into which interrupt counts can be
+//! written with `FakeUio::trigger`. Since the fake `/dev/uio0` is a regular file, event out 0
+//! cannot be used in tests.
+
+#![allow(dead_code)]
+
+use libc;
+
+use prusst::DevicePaths;
+
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+/// Size of the fake PRU memory map, which covers all PRU memories and registers.
+pub const PRUMEM_SIZE: usize = 0x40000;
+
+/// Size of the fake host memory map.
+pub const HOSTMEM_SIZE: usize = 0x2000;
+
+/// Physical address reported for the fake host memory.
+pub const HOSTMEM_ADDR: usize = 0x9c940000;
+
+// Offset of the host memory map within the device file.
+const HOSTMEM_OFFSET: usize = 0x1000;
+
+static SERIAL: Mutex<()> = Mutex::new(());
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+
+
+/// Serializes the tests of a test binary, since only one `Pruss` may exist at a time.
+pub fn serialize() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+
+
+/// Temporary fake UIO device tree, removed when dropped.
+pub struct FakeUio {
+    root: PathBuf,
+    evtouts: Vec<File>,
+}
+
+impl FakeUio {
+    /// Creates a fake device tree in a new temporary directory.
+    pub fn new() -> FakeUio {
+        let root = env::temp_dir().join(format!("prusst-test-{}-{}",
+                                                process::id(),
+                                                COUNTER.fetch_add(1, Ordering::SeqCst)));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dev")).unwrap();
+
+        let device = File::create(root.join("dev/uio0")).unwrap();
+        device.set_len(PRUMEM_SIZE.max(HOSTMEM_OFFSET + HOSTMEM_SIZE) as u64).unwrap();
+
+        let mut evtouts = Vec::new();
+        for e in 1..8 {
+            let path = root.join(format!("dev/uio{}", e));
+            let cpath = CString::new(path.as_os_str().as_bytes()).unwrap();
+            assert_eq!(unsafe { libc::mkfifo(cpath.as_ptr(), 0o600) }, 0);
+            // Keep both ends open so that opening the FIFO for reading does not block.
+            evtouts.push(OpenOptions::new().read(true).write(true).open(&path).unwrap());
+        }
+
+        let fake = FakeUio {
+            root: root,
+            evtouts: evtouts,
+        };
+        fake.set_map_attr(0, "size", &format!("{:#x}", PRUMEM_SIZE));
+        fake.set_map_attr(1, "size", &format!("{:#x}", HOSTMEM_SIZE));
+        fake.set_map_attr(1, "addr", &format!("{:#x}", HOSTMEM_ADDR));
+
+        fake
+    }
+
+    /// Root directory of the fake tree.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Device paths pointing at the fake tree.
+    pub fn paths(&self) -> DevicePaths {
+        DevicePaths::with_root(&self.root)
+    }
+
+    /// Overwrites an attribute of a memory map.
+    pub fn set_map_attr(&self, map: usize, attr: &str, value: &str) {
+        let dir = self.root.join(format!("sys/class/uio/uio0/maps/map{}", map));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(attr), format!("{}\n", value)).unwrap();
+    }
+
+    /// Removes a file of the fake tree.
+    pub fn remove(&self, path: &str) {
+        fs::remove_file(self.root.join(path)).unwrap();
+    }
+
+    /// Signals an event out interrupt with the specified total interrupt count.
+    pub fn trigger(&mut self, evtout: usize, total: u32) {
+        assert!((1..8).contains(&evtout));
+        self.evtouts[evtout - 1].write_all(&total.to_ne_bytes()).unwrap();
+    }
+
+    /// Reads the fake PRU memory.
+    pub fn read_prumem(&self, offset: usize, len: usize) -> Vec<u8> {
+        let mut device = File::open(self.root.join("dev/uio0")).unwrap();
+        device.seek(SeekFrom::Start(offset as u64)).unwrap();
+        let mut data = vec![0u8; len];
+        device.read_exact(&mut data).unwrap();
+
+        data
+    }
+
+    /// Writes to the fake PRU memory.
+    pub fn write_prumem(&self, offset: usize, data: &[u8]) {
+        let mut device = OpenOptions::new().write(true).open(self.root.join("dev/uio0")).unwrap();
+        device.seek(SeekFrom::Start(offset as u64)).unwrap();
+        device.write_all(data).unwrap();
+    }
+
+    /// Reads a 32-bit word of the fake PRU memory.
+    pub fn read_prumem_u32(&self, offset: usize) -> u32 {
+        let data = self.read_prumem(offset, 4);
+        u32::from_ne_bytes([data[0], data[1], data[2], data[3]])
+    }
+}
+
+impl Drop for FakeUio {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
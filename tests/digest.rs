@@ -0,0 +1,40 @@
+//! Firmware image digests.
+
+extern crate prusst;
+
+use prusst::digest::{self, Digest};
+
+use std::io;
+
+
+#[test]
+fn digests_match_known_answers() {
+    assert_eq!(digest::crc32(b"123456789"), 0xcbf43926);
+    assert_eq!(Digest::Sha256(digest::sha256(b"abc")).to_string(),
+               "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    // The padding of a message longer than a block spills over into an additional block.
+    assert_eq!(Digest::Sha256(digest::sha256(&[b'a'; 1000])).to_string(),
+               "sha256:41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3");
+}
+
+
+#[test]
+fn digests_are_parsed() {
+    let crc = Digest::parse("crc32:CBF43926").unwrap();
+    assert_eq!(crc, Digest::Crc32(0xcbf43926));
+    assert!(crc.matches(b"123456789"));
+    let sha = "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    assert_eq!(Digest::parse(sha).unwrap().to_string(), sha);
+    assert!(Digest::parse(sha).unwrap().matches(b"abc"));
+
+    for malformed in &["crc32:+bf43926",
+                       "crc32:cbf4392",
+                       "crc32:cbf43926a",
+                       "md5:cbf43926",
+                       "sha256:+a7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                       "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015",
+                       "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ag"] {
+        let err = Digest::parse(malformed).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", malformed);
+    }
+}
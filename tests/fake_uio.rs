@@ -0,0 +1,2051 @@
+//! End-to-end tests against a fake UIO device tree.
+
+extern crate libc;
+extern crate prusst;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(feature = "async")]
+extern crate tokio;
+
+mod common;
+
+use common::FakeUio;
+use prusst::{Channel, ConfigError, DispatchError, ElfFirmware, Error, Evtout, Host, IntcConfig,
+             IramAddr, LoadError, PruAddr, PruCore, Pruss, PrussBuilder, Sysevt, SysevtPolarity,
+             SysevtType, WaitError};
+use prusst::dispatch::IrqDispatcher;
+use prusst::doorbell::Doorbell;
+use prusst::event_loop::EventLoop;
+use prusst::handshake::{self, HandshakeConfig, HandshakeError};
+use prusst::linker::LinkerMap;
+use prusst::lock::SpinLock;
+use prusst::logger::DataLogger;
+#[cfg(feature = "manifest")]
+use prusst::Firmware;
+#[cfg(feature = "manifest")]
+use prusst::manifest::{FirmwareManifest, Manifest, Ram};
+use prusst::perf;
+use prusst::pps::{Discipline, Edge, PhaseLock};
+use prusst::rpc::{Frame, RpcChannel, RpcError};
+use prusst::semaphore;
+use prusst::stream::{StreamReader, RING_HEADER_SIZE};
+use prusst::timesync::{self, Calibration, Counter};
+use prusst::util::VolatileCell;
+
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsFd, AsRawFd};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+
+// Reader returning at most 3 bytes at a time.
+struct ShortReader<'a>(&'a [u8]);
+
+impl<'a> Read for ShortReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = cmp::min(cmp::min(buf.len(), 3), self.0.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+
+        Ok(n)
+    }
+}
+
+
+fn build(fake: &FakeUio) -> prusst::Result<Pruss<'static>> {
+    PrussBuilder::new().paths(fake.paths()).build(&IntcConfig::new_populated())
+}
+
+
+// Builds a 32-bit little-endian ELF executable with one loadable segment per
+// `(address, content, memory size, executable)` tuple, followed by a symbol table with one
+// global symbol per `(name, address, executable)` tuple.
+fn elf_image(entry: u32,
+             segments: &[(u32, &[u8], u32, bool)],
+             symbols: &[(&str, u32, bool)])
+             -> Vec<u8> {
+    fn push_u16(image: &mut Vec<u8>, values: &[u16]) {
+        for value in values {
+            image.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    fn push_u32(image: &mut Vec<u8>, values: &[u32]) {
+        for value in values {
+            image.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    // Contents following the program headers: segments, symbol table and string table.
+    let phoff = 52u32;
+    let mut contents = Vec::new();
+    let mut offsets = Vec::new();
+    for &(_, content, _, _) in segments {
+        offsets.push(phoff + 32 * segments.len() as u32 + contents.len() as u32);
+        contents.extend_from_slice(content);
+    }
+    let mut strtab = vec![0u8];
+    let mut symtab = vec![0u8; 16];
+    for &(name, address, executable) in symbols {
+        push_u32(&mut symtab, &[strtab.len() as u32, address, 4]);
+        symtab.extend_from_slice(&[0x11, 0]);
+        push_u16(&mut symtab, &[if executable { 1 } else { 2 }]);
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+    }
+    let symtab_offset = phoff + 32 * segments.len() as u32 + contents.len() as u32;
+    let strtab_offset = symtab_offset + symtab.len() as u32;
+    let shoff = strtab_offset + strtab.len() as u32;
+
+    let mut image = vec![0x7f, b'E', b'L', b'F', 1, 1, 1];
+    image.resize(16, 0);
+    push_u16(&mut image, &[2, 144]);
+    push_u32(&mut image, &[1, entry, phoff, shoff, 0]);
+    push_u16(&mut image, &[52, 32, segments.len() as u16, 40, 5, 0]);
+    for (&(address, content, mem_size, executable), &offset) in segments.iter().zip(&offsets) {
+        let flags = if executable { 5 } else { 6 };
+        push_u32(&mut image,
+                 &[1, offset, address, address, content.len() as u32, mem_size, flags, 4]);
+    }
+    image.extend_from_slice(&contents);
+    image.extend_from_slice(&symtab);
+    image.extend_from_slice(&strtab);
+    // Section headers: null, code, data, symbol table and string table.
+    push_u32(&mut image, &[0; 10]);
+    push_u32(&mut image, &[0, 1, 6, 0, 0, 0, 0, 0, 4, 0]);
+    push_u32(&mut image, &[0, 1, 3, 0, 0, 0, 0, 0, 4, 0]);
+    push_u32(&mut image,
+             &[0, 2, 0, 0, symtab_offset, symtab.len() as u32, 4, 1, 4, 16]);
+    push_u32(&mut image, &[0, 3, 0, 0, strtab_offset, strtab.len() as u32, 0, 0, 1, 0]);
+
+    image
+}
+
+
+// Device-tree node which can be written as a directory or as a flattened device tree blob.
+struct DtNode {
+    name: &'static str,
+    props: Vec<(&'static str, Vec<u8>)>,
+    children: Vec<DtNode>,
+}
+
+impl DtNode {
+    fn write_dir(&self, parent: &Path) {
+        let path = parent.join(self.name);
+        fs::create_dir_all(&path).unwrap();
+        for &(name, ref value) in &self.props {
+            fs::write(path.join(name), value).unwrap();
+        }
+        for child in &self.children {
+            child.write_dir(&path);
+        }
+    }
+
+    fn dtb(&self) -> Vec<u8> {
+        fn push_be_u32(image: &mut Vec<u8>, value: u32) {
+            image.extend_from_slice(&value.to_be_bytes());
+        }
+        fn pad(image: &mut Vec<u8>) {
+            while !image.len().is_multiple_of(4) {
+                image.push(0);
+            }
+        }
+        fn push_node(node: &DtNode, structs: &mut Vec<u8>, strings: &mut Vec<u8>) {
+            push_be_u32(structs, 1);
+            structs.extend_from_slice(node.name.as_bytes());
+            structs.push(0);
+            pad(structs);
+            for &(name, ref value) in &node.props {
+                push_be_u32(structs, 3);
+                push_be_u32(structs, value.len() as u32);
+                push_be_u32(structs, strings.len() as u32);
+                strings.extend_from_slice(name.as_bytes());
+                strings.push(0);
+                structs.extend_from_slice(value);
+                pad(structs);
+            }
+            for child in &node.children {
+                push_node(child, structs, strings);
+            }
+            push_be_u32(structs, 2);
+        }
+
+        let mut structs = Vec::new();
+        let mut strings = Vec::new();
+        push_node(self, &mut structs, &mut strings);
+        push_be_u32(&mut structs, 9);
+
+        let mut image = Vec::new();
+        for &value in &[0xd00dfeed, (40 + structs.len() + strings.len()) as u32, 40,
+                        40 + structs.len() as u32, 40, 17, 16, 0, strings.len() as u32,
+                        structs.len() as u32] {
+            push_be_u32(&mut image, value);
+        }
+        image.extend_from_slice(&structs);
+        image.extend_from_slice(&strings);
+
+        image
+    }
+}
+
+
+fn be_cells(cells: &[u32]) -> Vec<u8> {
+    cells.iter().flat_map(|cell| cell.to_be_bytes().to_vec()).collect()
+}
+
+
+#[test]
+fn maps_are_discovered() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+
+    assert_eq!(pruss.prumem_size(), common::PRUMEM_SIZE);
+    assert_eq!(pruss.hostmem_size(), common::HOSTMEM_SIZE);
+    assert_eq!(pruss.hostmem_phys_addr(), common::HOSTMEM_ADDR);
+}
+
+
+#[test]
+fn only_one_instance_at_a_time() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+
+    assert_eq!(build(&fake).err(), Some(Error::AlreadyInstantiated));
+    drop(pruss);
+    assert!(build(&fake).is_ok());
+}
+
+
+#[test]
+fn missing_device_is_reported() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    fake.remove("dev/uio0");
+
+    assert_eq!(build(&fake).err(), Some(Error::DeviceNotFound));
+}
+
+
+#[test]
+fn malformed_map_attribute_is_reported() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    fake.set_map_attr(0, "size", "garbage");
+
+    assert_eq!(build(&fake).err(), Some(Error::OtherDeviceError));
+}
+
+
+#[test]
+fn failed_instantiation_can_be_retried() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    fake.remove("sys/class/uio/uio0/maps/map1/addr");
+    assert_eq!(build(&fake).err(), Some(Error::DeviceNotFound));
+
+    fake.set_map_attr(1, "addr", "0x0");
+    assert!(build(&fake).is_ok());
+}
+
+
+#[test]
+fn code_is_loaded_in_iram() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code = [0x01u8, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+
+    pruss.pru1.load_code(&mut &code[..]).unwrap();
+
+    assert_eq!(fake.read_prumem(0x38000, code.len()), code);
+}
+
+
+#[test]
+fn iram_can_be_patched_after_loading() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let mut code = pruss.pru1.load_code(&mut &[0u8; 16][..]).unwrap();
+    unsafe { code.run() };
+
+    {
+        let mut iram = code.iram();
+        assert_eq!((iram.begin(), iram.end()), (IramAddr(0), IramAddr(0x2000)));
+        let (_, mut patch) = iram.split_at(IramAddr(4));
+        *patch.alloc(0u32) = 0x12345678;
+    }
+
+    assert!(!code.ctrl().enable());
+    assert_eq!(fake.read_prumem_u32(0x38004), 0x12345678);
+}
+
+
+#[test]
+fn code_is_started_at_the_requested_word() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let mut code = pruss.pru0.load_code(&mut &[0u8; 16][..]).unwrap();
+
+    unsafe { code.run_at(3) };
+
+    assert_eq!(code.ctrl().pctr_rst_val(), 3);
+    assert!(code.ctrl().enable());
+    assert_eq!(fake.read_prumem_u32(0x22000), 0x0003_0002);
+}
+
+
+#[test]
+fn code_is_swapped_without_touching_data_ram() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    unsafe { pruss.pru0.load_code(&mut &[1u8; 16][..]).unwrap().run() };
+    *pruss.dram0.alloc(0u32) = 0xcafe;
+
+    let code = unsafe { pruss.pru0.swap_code(&mut &[2u8; 8][..]).unwrap() };
+
+    assert!(code.ctrl().enable());
+    assert_eq!(fake.read_prumem(0x34000, 8), [2; 8]);
+    assert_eq!(fake.read_prumem_u32(0x0), 0xcafe);
+    let oversized = vec![0u8; 0x2001];
+    assert!(unsafe { pruss.pru0.swap_code(&mut &oversized[..]) }.is_err());
+    assert_eq!(fake.read_prumem(0x34000, 8), [2; 8]);
+}
+
+
+#[test]
+fn both_codes_are_validated_before_loading() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let oversized = vec![0u8; 0x2001];
+
+    let result = Pruss::load_both(&mut pruss.pru0, &mut pruss.pru1, &mut &[1u8; 8][..],
+                                  &mut &oversized[..]);
+    assert!(result.is_err());
+    assert_eq!(fake.read_prumem(0x34000, 8), [0; 8]);
+
+    let (code0, code1) = Pruss::load_both(&mut pruss.pru0, &mut pruss.pru1, &mut &[1u8; 8][..],
+                                          &mut &[2u8; 4][..])
+        .unwrap();
+    assert_eq!((code0.word_count(), code1.word_count()), (2, 1));
+    assert_eq!(fake.read_prumem(0x34000, 8), [1; 8]);
+    assert_eq!(fake.read_prumem(0x38000, 4), [2; 4]);
+}
+
+
+#[test]
+fn halted_code_is_detected() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code = pruss.pru0.load_code(&mut &[0u8; 4][..]).unwrap();
+
+    // Emulate a running core by setting the enable and RUNSTATE bits.
+    fake.write_prumem(0x22000, &0x8002u32.to_ne_bytes());
+    assert!(!code.wait_halted(Duration::from_millis(5)));
+
+    fake.write_prumem(0x22000, &0x0000u32.to_ne_bytes());
+    assert!(code.wait_halted(Duration::from_millis(5)));
+}
+
+
+#[test]
+fn program_counter_is_read_from_the_status_register() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code = pruss.pru1.load_code(&mut &[0u8; 4][..]).unwrap();
+
+    fake.write_prumem(0x24004, &0x0001_0123u32.to_ne_bytes());
+
+    assert_eq!(code.pc(), 0x123);
+}
+
+
+#[test]
+fn cycle_and_stall_counters_are_reset() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let mut code = pruss.pru0.load_code(&mut &[0u8; 4][..]).unwrap();
+    code.enable_cycle_counter();
+    fake.write_prumem(0x2200c, &1000u32.to_ne_bytes());
+    fake.write_prumem(0x22010, &10u32.to_ne_bytes());
+    assert_eq!((code.cycle_count(), code.stall_count()), (1000, 10));
+
+    code.reset_counters();
+
+    assert_eq!((code.cycle_count(), code.stall_count()), (0, 0));
+    assert!(code.ctrl().counter_enable());
+}
+
+
+#[test]
+fn resume_preserves_the_program_counter_reset_value() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let mut code = pruss.pru0.load_code(&mut &[0u8; 16][..]).unwrap();
+    unsafe { code.run_at(2) };
+    code.halt();
+
+    unsafe { code.resume() };
+    assert_eq!(fake.read_prumem_u32(0x22000), 0x0002_0003);
+
+    code.set_counter_enable(true);
+    unsafe { code.restart() };
+    assert_eq!(fake.read_prumem_u32(0x22000), 0x0000_0002);
+}
+
+
+#[test]
+fn code_is_read_until_eof() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code: Vec<u8> = (1..11).collect();
+
+    let loaded = pruss.pru0.load_code(&mut ShortReader(&code)).unwrap();
+
+    assert_eq!(loaded.word_count(), 3);
+    assert_eq!(fake.read_prumem(0x34000, code.len()), code);
+}
+
+
+#[test]
+fn code_is_loaded_from_a_slice() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code: Vec<u8> = (1..8).collect();
+
+    let loaded = pruss.pru1.load_code_from_slice(&code).unwrap();
+    assert_eq!(loaded.word_count(), 2);
+    assert_eq!(fake.read_prumem(0x38000, code.len()), code);
+
+    let oversized = vec![0xffu8; 0x2001];
+    match pruss.pru1.load_code_from_slice(&oversized) {
+        Err(LoadError::CodeTooLarge { size: 0x2001, capacity: 0x2000 }) => {}
+        _ => panic!("oversized code was accepted"),
+    }
+    assert_eq!(fake.read_prumem(0x38000, code.len()), code);
+}
+
+
+#[test]
+fn data_image_is_loaded_to_the_core_data_ram() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code = [1u8, 2, 3, 4];
+    let data = [5u8, 6, 7];
+
+    pruss.pru1.load_code_with_data(&mut &code[..], &mut &data[..], &mut pruss.dram1).unwrap();
+    assert_eq!(fake.read_prumem(0x38000, 4), code);
+    assert_eq!(fake.read_prumem(0x2000, 3), data);
+    assert_eq!(fake.read_prumem(0x0, 3), [0; 3]);
+
+    let oversized = vec![0xffu8; 0x2001];
+    match pruss.pru0.load_code_with_data(&mut &code[..], &mut &oversized[..], &mut pruss.dram0) {
+        Err(LoadError::DataTooLarge { size: 0x2001, capacity: 0x2000 }) => {}
+        _ => panic!("oversized data image was accepted"),
+    }
+    assert_eq!(fake.read_prumem(0x34000, 4), [0; 4]);
+}
+
+
+#[test]
+fn code_checksum_is_verified() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code = b"123456789";
+
+    match pruss.pru0.load_code_checked(&mut &code[..], 0xdeadbeef) {
+        Err(LoadError::ChecksumMismatch { expected: 0xdeadbeef, actual: 0xcbf43926 }) => {}
+        _ => panic!("checksum mismatch was not detected"),
+    }
+    assert_eq!(fake.read_prumem(0x34000, 4), [0; 4]);
+
+    pruss.pru0.load_code_checked(&mut &code[..], 0xcbf43926).unwrap();
+    assert_eq!(fake.read_prumem(0x34000, code.len()), code);
+}
+
+
+#[test]
+fn loaded_code_is_verified() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code: Vec<u8> = (1..9).collect();
+
+    let loaded = pruss.pru1.load_code_verified(&mut ShortReader(&code)).unwrap();
+
+    assert_eq!(loaded.word_count(), 2);
+    assert_eq!(fake.read_prumem(0x38000, code.len()), code);
+}
+
+
+#[test]
+fn oversized_code_is_rejected() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let code = vec![0u8; 0x2000 + 6];
+
+    match pruss.pru0.load_code(&mut ShortReader(&code)) {
+        Err(LoadError::CodeTooLarge { size, capacity }) => {
+            assert_eq!(size, 0x2006);
+            assert_eq!(capacity, 0x2000);
+        }
+        _ => panic!("oversized code was accepted"),
+    }
+    assert!(pruss.pru0.load_code(&mut &code[..0x2000]).is_ok());
+}
+
+
+#[test]
+fn elf_segments_are_loaded_in_local_memory() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let text = [0x11u8, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88];
+    let data = [0xaau8, 0xbb, 0xcc, 0xdd];
+    let image = elf_image(0x104, &[(0x100, &text, 8, true), (0x20, &data, 12, false)], &[]);
+    fake.write_prumem(0x2020, &[0xff; 12]);
+    let firmware = ElfFirmware::parse(&image).unwrap();
+
+    let code = pruss.pru1.load_elf(&firmware, &mut [&mut pruss.dram1]).unwrap();
+
+    assert_eq!(code.entry_point(), 0x104);
+    assert_eq!(fake.read_prumem(0x38100, text.len()), text);
+    assert_eq!(fake.read_prumem(0x2020, 12), [0xaa, 0xbb, 0xcc, 0xdd, 0, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+
+#[test]
+fn elf_code_runs_from_entry_point() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let text = [0u8; 16];
+    let image = elf_image(0x108, &[(0x100, &text, 16, true)], &[]);
+    let firmware = ElfFirmware::parse(&image).unwrap();
+
+    let mut code = pruss.pru1.load_elf(&firmware, &mut []).unwrap();
+    unsafe { code.run() };
+
+    // The program counter reset value is the instruction word of the entry point.
+    assert_eq!(fake.read_prumem_u32(0x24000), 0x0042_0002);
+}
+
+
+#[test]
+fn elf_segments_exceeding_data_ram_are_rejected() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let image = elf_image(0, &[(0x0, &[0; 4], 4, true), (0x1ffc, &[1; 4], 8, false)], &[]);
+    let firmware = ElfFirmware::parse(&image).unwrap();
+
+    let err = pruss.pru0.load_elf(&firmware, &mut [&mut pruss.dram0]).err().unwrap();
+
+    match err {
+        LoadError::BadFormat(_) => {}
+        err => panic!("unexpected error: {:?}", err),
+    }
+    assert_eq!(fake.read_prumem(0x1ffc, 4), [0; 4]);
+
+    // Data segments must lie within the provided segments.
+    let image = elf_image(0, &[(0x0, &[0; 4], 4, true), (0x100, &[1; 4], 4, false)], &[]);
+    let firmware = ElfFirmware::parse(&image).unwrap();
+    let (_, mut tail) = pruss.dram0.split_at(PruAddr(0x1000));
+    match pruss.pru0.load_elf(&firmware, &mut [&mut tail]).err().unwrap() {
+        LoadError::BadFormat(_) => {}
+        err => panic!("unexpected error: {:?}", err),
+    }
+    assert_eq!(fake.read_prumem(0x100, 4), [0; 4]);
+
+    let image = elf_image(0, &[(0x1ffc, &[1; 4], 8, true)], &[]);
+    let firmware = ElfFirmware::parse(&image).unwrap();
+    match pruss.pru0.load_elf(&firmware, &mut []).err().unwrap() {
+        LoadError::CodeTooLarge { size, capacity } => {
+            assert_eq!((size, capacity), (0x2004, 0x2000))
+        }
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+
+#[test]
+fn elf_segments_outside_of_the_image_are_rejected() {
+    let image = elf_image(0, &[(0x0, &[0; 8], 8, true)], &[]);
+    let mut truncated = image.clone();
+    truncated[56..60].copy_from_slice(&0xffff_fffcu32.to_le_bytes());
+
+    let err = ElfFirmware::parse(&truncated).err().unwrap();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(ElfFirmware::parse(&image).is_ok());
+}
+
+
+#[test]
+fn elf_data_symbols_are_resolved() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let image = elf_image(0,
+                          &[(0x0, &[0; 4], 4, true), (0x40, &[0x5a; 8], 8, false)],
+                          &[("main", 0x0, true), ("ctrl_block", 0x44, false)]);
+    let firmware = ElfFirmware::parse(&image).unwrap();
+
+    assert_eq!(firmware.symbol("main").map(|symbol| symbol.executable), Some(true));
+    assert_eq!(firmware.symbol_addr(PruCore::Pru1, "main"), None);
+    assert_eq!(firmware.symbol_addr(PruCore::Pru1, "missing"), None);
+    let ctrl_block = firmware.symbol_addr(PruCore::Pru1, "ctrl_block").unwrap();
+    assert_eq!(ctrl_block, PruAddr(0x2044));
+
+    pruss.pru1.load_elf(&firmware, &mut [&mut pruss.dram1]).unwrap();
+    let (_, mut segment) = pruss.dram1.split_at(ctrl_block);
+    *segment.alloc(0u32) = 0x12345678;
+    assert_eq!(fake.read_prumem_u32(0x2044), 0x12345678);
+}
+
+
+#[cfg(feature = "manifest")]
+#[test]
+fn firmware_manifest_is_checked_before_loading() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let path = fake.root().join("app-pru0.bin");
+    fs::write(&path, [1u8, 2, 3, 4]).unwrap();
+    let firmware = Firmware::open(&path).unwrap();
+    let compatible = FirmwareManifest::parse("[intc]\nchannels = [[19, 2]]\nhosts = [[2, 2]]\n")
+        .unwrap();
+    let incompatible = FirmwareManifest::parse("[intc]\nchannels = [[19, 3]]\n").unwrap();
+    // The fake memory does not emulate the indexed enable registers, so set the enable bits of
+    // system event 19 and host 2 directly.
+    fake.write_prumem(0x20000 + 0x300, &(1u32 << 19).to_ne_bytes());
+    fake.write_prumem(0x20000 + 0x1500, &(1u32 << 2).to_ne_bytes());
+
+    let err = pruss.pru0.load_with_manifest(&pruss.intc, &firmware, &incompatible).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(fake.read_prumem(0x34000, 4), [0; 4]);
+
+    pruss.pru0.load_with_manifest(&pruss.intc, &firmware, &compatible).unwrap();
+    assert_eq!(fake.read_prumem(0x34000, 4), [1, 2, 3, 4]);
+}
+
+
+#[cfg(feature = "manifest")]
+#[test]
+fn application_manifest_is_parsed() {
+    let manifest = Manifest::parse(r#"
+        [pru0]
+        firmware = "myapp-pru0"
+        digest = "crc32:1c291ca3"
+
+        [pru1]
+        firmware = "myapp-pru1"
+        run = false
+
+        [intc]
+        channels = [[16, 2], [17, 0]]
+        hosts = [[2, 2], [0, 0]]
+
+        [memory.samples]
+        ram = "dram2"
+        offset = 0x100
+        size = 4096
+
+        [pins]
+        P9_31 = "pruout"
+    "#)
+        .unwrap();
+    let pru0 = manifest.pru0.as_ref().unwrap();
+    assert_eq!(pru0.firmware, "myapp-pru0");
+    assert!(pru0.run && pru0.digest.is_some());
+    assert!(!manifest.pru1.as_ref().unwrap().run);
+    let area = manifest.memory_area("samples").unwrap();
+    assert_eq!((area.ram, area.offset, area.size), (Ram::Dram2, 0x100, 4096));
+    assert_eq!(manifest.pins, [("P9_31".to_string(), "pruout".to_string())]);
+
+    for invalid in &["[pru0]\nrun = true\n",
+                     "[pru0]\nfirmware = \"a\"\nspeed = 1\n",
+                     "[intc]\nchannels = [[64, 2]]\n",
+                     "[intc]\nchannels = [[16, 2], [16, 3]]\n",
+                     "[memory.samples]\nram = \"iram0\"\nsize = 4\n",
+                     "[gpio]\n"] {
+        let err = Manifest::parse(invalid).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", invalid);
+    }
+}
+
+
+#[cfg(feature = "manifest")]
+#[test]
+fn manifest_memory_areas_are_checked_before_loading() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let path = fake.root().join("app-pru0.bin");
+    fs::write(&path, [1u8, 2, 3, 4]).unwrap();
+    let manifest = |offset: usize| {
+        Manifest::parse(&format!("[pru0]\nfirmware = {:?}\nrun = false\n\n\
+                                  [memory.samples]\nram = \"dram2\"\noffset = {}\nsize = 4096\n",
+                                 path,
+                                 offset))
+            .unwrap()
+    };
+
+    let err = unsafe { PrussBuilder::new().paths(fake.paths()).launch(&manifest(0x2001)) }
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(fake.read_prumem(0x34000, 4), [0; 4]);
+
+    let pruss = unsafe { PrussBuilder::new().paths(fake.paths()).launch(&manifest(0x2000)) };
+    pruss.unwrap();
+    assert_eq!(fake.read_prumem(0x34000, 4), [1, 2, 3, 4]);
+}
+
+
+#[test]
+fn elf_symbols_are_patched() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let image = elf_image(0,
+                          &[(0x0, &[0; 8], 8, true), (0x40, &[0; 4], 4, false)],
+                          &[("CALIB", 0x4, true), ("board_id", 0x40, false)]);
+    let firmware = ElfFirmware::parse(&image).unwrap();
+    let mut code = pruss.pru1.load_elf(&firmware, &mut [&mut pruss.dram1]).unwrap();
+
+    code.patch_symbol(&firmware, "CALIB", &[1, 2, 3, 4], &mut []).unwrap();
+    code.patch_symbol(&firmware, "board_id", &[5, 6], &mut [&mut pruss.dram1]).unwrap();
+
+    assert_eq!(fake.read_prumem(0x38004, 4), [1, 2, 3, 4]);
+    assert_eq!(fake.read_prumem(0x2040, 4), [5, 6, 0, 0]);
+    let err = code.patch_symbol(&firmware, "board_id", &[0; 8], &mut [&mut pruss.dram1])
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = code.patch_symbol(&firmware, "board_id", &[0; 4], &mut []).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = code.patch_symbol(&firmware, "missing", &[0; 4], &mut []).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+
+#[test]
+fn sysevts_are_sent_through_the_intc() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+
+    pruss.intc.send_sysevt(Sysevt::S17);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x200), 1 << 17);
+
+    pruss.intc.send_sysevt(Sysevt::S33);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x204), 1 << 1);
+}
+
+
+#[test]
+#[allow(deprecated)]
+fn evtout_interrupts_are_counted() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E1);
+
+    fake.trigger(1, 1);
+    assert_eq!(irq.wait(), 1);
+    assert_eq!(irq.wait_timeout(Duration::from_millis(10)), None);
+
+    fake.trigger(1, 4);
+    assert_eq!(irq.drain(&pruss.intc, Sysevt::S20), 3);
+
+    fake.trigger(1, 5);
+    assert_eq!(irq.wait_counted(), (1, 5));
+    fake.trigger(1, 9);
+    assert_eq!(irq.wait_counted(), (4, 9));
+}
+
+
+#[test]
+fn evtout_waits_are_fallible() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E1);
+
+    fake.trigger(1, 1);
+    assert_eq!(irq.try_wait().unwrap(), 1);
+    assert_eq!(irq.try_wait_timeout(Duration::from_millis(10)).unwrap(), None);
+    fake.trigger(1, 4);
+    assert_eq!(irq.try_drain(&pruss.intc, Sysevt::S20).unwrap(), 3);
+    fake.trigger(1, 6);
+    assert_eq!(irq.try_wait_counted().unwrap(), (2, 6));
+
+    // Reading from a directory fails, as would a device whose driver went away.
+    fake.remove("dev/uio2");
+    fs::create_dir(fake.root().join("dev/uio2")).unwrap();
+    let broken = pruss.intc.register_irq(Evtout::E2);
+    match broken.try_wait() {
+        Err(WaitError::Io(err)) => assert_eq!(err.raw_os_error(), Some(libc::EISDIR)),
+        other => panic!("unexpected wait result: {:?}", other),
+    }
+    assert!(broken.try_wait_timeout(Duration::from_millis(10)).is_err());
+    assert!(broken.try_drain(&pruss.intc, Sysevt::S21).is_err());
+}
+
+
+#[test]
+fn evtout_interrupt_can_be_rearmed_and_masked() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+
+    // The control words written to the fake device are read back as interrupt counts.
+    let irq = pruss.intc.register_irq(Evtout::E1);
+    assert!(irq.rearm().unwrap());
+    assert_eq!(irq.try_wait().unwrap(), 1);
+    assert!(irq.mask().unwrap());
+    assert_eq!(irq.try_wait().unwrap(), 0);
+    fake.trigger(1, 2);
+    assert_eq!(irq.try_wait_counted().unwrap(), (2, 2));
+
+    // A directory can only be opened read-only, as would a device without write permission.
+    fake.remove("dev/uio2");
+    fs::create_dir(fake.root().join("dev/uio2")).unwrap();
+    let read_only = pruss.intc.register_irq(Evtout::E2);
+    assert!(!read_only.rearm().unwrap());
+    assert!(!read_only.mask().unwrap());
+}
+
+
+#[test]
+fn evtout_device_can_be_polled() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E3);
+    let mut pollfd = libc::pollfd {
+        fd: irq.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    assert_eq!(irq.as_fd().as_raw_fd(), pollfd.fd);
+
+    assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 0) }, 0);
+    fake.trigger(3, 1);
+    assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 1000) }, 1);
+    assert_eq!(irq.try_wait().unwrap(), 1);
+}
+
+
+#[cfg(feature = "async")]
+#[test]
+fn evtout_can_be_awaited() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E4);
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+    fake.trigger(4, 1);
+    assert_eq!(runtime.block_on(irq.wait_async()).unwrap(), 1);
+    fake.trigger(4, 3);
+    assert_eq!(runtime.block_on(irq.wait_async()).unwrap(), 3);
+    let timeout = {
+        let _context = runtime.enter();
+        tokio::time::timeout(Duration::from_millis(10), irq.wait_async())
+    };
+    assert!(runtime.block_on(timeout).is_err());
+
+    // Trigger the event out while the future is pending.
+    let device = fake.root().join("dev/uio4");
+    let trigger = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        fs::OpenOptions::new().write(true).open(device).unwrap().write_all(&6u32.to_ne_bytes())
+    });
+    assert_eq!(runtime.block_on(irq.wait_async()).unwrap(), 6);
+    trigger.join().unwrap().unwrap();
+}
+
+
+#[cfg(feature = "async")]
+#[test]
+fn evtout_notifications_are_streamed() {
+    use futures_core::Stream;
+    use std::future;
+    use std::pin::Pin;
+
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E5);
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let mut rearmed = 0;
+
+    {
+        let mut events = irq.stream_with(|| rearmed += 1);
+        let mut next = || {
+            runtime.block_on(future::poll_fn(|cx| Pin::new(&mut events).poll_next(cx)))
+        };
+        fake.trigger(5, 1);
+        assert_eq!(next(), Some(1));
+        fake.trigger(5, 2);
+        assert_eq!(next(), Some(2));
+    }
+    assert_eq!(rearmed, 2);
+}
+
+
+#[test]
+fn evtouts_are_dispatched_to_callbacks() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let (tx, rx) = mpsc::channel();
+    let mut dispatcher = IrqDispatcher::new();
+    for &(e, sysevt) in &[(Evtout::E6, Sysevt::S24), (Evtout::E7, Sysevt::S25)] {
+        let tx = tx.clone();
+        dispatcher.on_evtout(&pruss.intc, e, sysevt, move |_, n| {
+                tx.send((e as usize, n)).unwrap()
+            })
+            .unwrap();
+    }
+    let err = dispatcher.on_evtout(&pruss.intc, Evtout::E7, Sysevt::S25, |_, _| {}).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    let handle = dispatcher.spawn(&pruss.intc).unwrap();
+
+    fake.trigger(7, 1);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap(), (7, 1));
+    // The system event is cleared and the host interrupt re-enabled before the callback runs.
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x24), 25);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x34), 9);
+    fake.trigger(6, 2);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap(), (6, 1));
+    fake.trigger(6, 5);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)).unwrap(), (6, 3));
+
+    handle.stop().unwrap();
+    fake.trigger(7, 2);
+    assert!(rx.recv_timeout(Duration::from_millis(10)).is_err());
+}
+
+
+#[test]
+fn dispatcher_reports_device_errors() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    fake.remove("dev/uio2");
+    fs::create_dir(fake.root().join("dev/uio2")).unwrap();
+
+    let mut dispatcher = IrqDispatcher::new();
+    dispatcher.on_evtout(&pruss.intc, Evtout::E2, Sysevt::S21, |_, _| {
+            panic!("unexpected event out")
+        })
+        .unwrap();
+    let handle = dispatcher.spawn(&pruss.intc).unwrap();
+    // Let the service thread poll the failing device rather than the stop pipe.
+    thread::sleep(Duration::from_millis(50));
+    match handle.stop() {
+        Err(DispatchError::Wait(WaitError::Io(err))) => {
+            assert_eq!(err.raw_os_error(), Some(libc::EISDIR))
+        }
+        other => panic!("unexpected dispatcher result: {:?}", other),
+    }
+}
+
+
+#[test]
+fn evtout_latency_stats_are_collected() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let mut irq = pruss.intc.register_irq(Evtout::E3);
+    assert!(irq.latency_stats().is_none());
+    irq.enable_latency_stats(Duration::from_millis(1), 4);
+
+    for total in 1..4 {
+        fake.trigger(3, total);
+        irq.try_wait().unwrap();
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    let stats = irq.latency_stats().unwrap();
+    assert_eq!(stats.inter_event.samples, 2);
+    assert!(stats.inter_event.min >= Duration::from_millis(2));
+    assert_eq!(stats.inter_event.histogram.iter().sum::<u64>(), 2);
+    assert_eq!(stats.inter_event.histogram[..2], [0, 0]);
+    assert_eq!(stats.service.samples, 2);
+    assert!(stats.service.mean >= Duration::from_millis(2));
+    assert!(stats.service.max >= stats.service.min);
+    irq.disable_latency_stats();
+    assert!(irq.latency_stats().is_none());
+}
+
+
+#[test]
+fn event_latency_times_out_without_event_out() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E3);
+
+    match perf::event_latency(&pruss.intc, &irq, Sysevt::S22, 4, Duration::from_millis(10)) {
+        Err(WaitError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        other => panic!("unexpected latency result: {:?}", other),
+    }
+}
+
+
+#[test]
+fn pending_sysevts_are_polled() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    assert!(!pruss.intc.is_pending(Sysevt::S19));
+
+    pruss.intc.send_sysevt(Sysevt::S19);
+    pruss.intc.send_sysevt(Sysevt::S40);
+    assert!(pruss.intc.is_pending(Sysevt::S19));
+    assert!(!pruss.intc.is_pending(Sysevt::S20));
+    assert!(pruss.intc.is_pending(Sysevt::S40));
+    assert!(!pruss.intc.is_pending(Sysevt::S41));
+}
+
+
+#[test]
+fn intc_status_is_decoded() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    pruss.intc.send_sysevt(Sysevt::S19);
+    pruss.intc.send_sysevt(Sysevt::S40);
+    // The fake memory does not emulate the status and enable registers, so set them directly.
+    fake.write_prumem(0x20000 + 0x280, &(1u32 << 19).to_ne_bytes());
+    fake.write_prumem(0x20000 + 0x300, &(1u32 << 19 | 1 << 20).to_ne_bytes());
+    fake.write_prumem(0x20000 + 0x304, &(1u32 << 8).to_ne_bytes());
+    fake.write_prumem(0x20000 + 0x1500, &(1u32 << 2 | 1 << 9).to_ne_bytes());
+
+    let status = pruss.intc.status();
+    assert_eq!(status.raw_pending, [19, 40]);
+    assert_eq!(status.enabled_pending, [19]);
+    assert_eq!(status.enabled_sysevts, [19, 20, 40]);
+    assert_eq!(status.enabled_hosts, [2, 9]);
+    assert_eq!(pruss.status().pending_sysevts, status.raw_pending);
+}
+
+
+#[test]
+fn sysevts_are_cleared_in_bulk() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    fake.write_prumem(0x20000 + 0x280, &[0; 8]);
+
+    pruss.intc.clear_sysevts(&[Sysevt::S3, Sysevt::S19, Sysevt::S63]);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x280), 1 << 3 | 1 << 19);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x284), 1 << 31);
+}
+
+
+#[test]
+fn sysevts_are_enabled_and_disabled_in_bulk() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    fake.write_prumem(0x20000 + 0x300, &[0; 8]);
+    fake.write_prumem(0x20000 + 0x380, &[0; 8]);
+
+    pruss.intc.enable_sysevts(&[Sysevt::S16, Sysevt::S17, Sysevt::S32]);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x300), 1 << 16 | 1 << 17);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x304), 1);
+    pruss.intc.disable_sysevts(&[Sysevt::S17]);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x380), 1 << 17);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x384), 0);
+}
+
+
+#[test]
+fn pending_sysevt_of_host_is_read() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    fake.write_prumem(0x20000 + 0x900 + 4 * 3, &21u32.to_ne_bytes());
+    fake.write_prumem(0x20000 + 0x900 + 4 * 4, &0x80000000u32.to_ne_bytes());
+    fake.write_prumem(0x20000 + 0x900 + 4 * 5, &0x105u32.to_ne_bytes());
+
+    assert_eq!(pruss.intc.pending_sysevt(Evtout::E1).map(|se| se as u8), Some(21));
+    assert!(pruss.intc.pending_sysevt(Evtout::E2).is_none());
+    assert!(pruss.intc.pending_sysevt(Evtout::E3).is_none());
+}
+
+
+#[test]
+fn sysevt_is_acknowledged() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+
+    pruss.intc.ack(Sysevt::S22, Evtout::E3);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x24), 22);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x34), 5);
+
+    assert!(pruss.intc.ack_checked(Sysevt::S23, Evtout::E3));
+    // The fake memory does not emulate the clearing of the raw status, so the event appears to
+    // be raised again.
+    pruss.intc.send_sysevt(Sysevt::S23);
+    assert!(!pruss.intc.ack_checked(Sysevt::S23, Evtout::E3));
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x24), 23);
+}
+
+
+#[test]
+fn sysevt_polarity_and_type_are_configured() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut config = IntcConfig::new_populated();
+    config.set_sysevt_polarities(&[(Sysevt::S3, SysevtPolarity::ActiveLow),
+                                   (Sysevt::S40, SysevtPolarity::ActiveLow),
+                                   (Sysevt::S41, SysevtPolarity::ActiveHigh)]);
+    config.set_sysevt_types(&[(Sysevt::S5, SysevtType::Level), (Sysevt::S6, SysevtType::Pulse)]);
+    let _pruss = PrussBuilder::new().paths(fake.paths()).build(&config).unwrap();
+
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0xd00), !(1 << 3));
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0xd04), !(1 << 8));
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0xd80), 1 << 5);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0xd84), 0);
+}
+
+
+#[test]
+fn host_nesting_level_is_set() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+
+    pruss.intc.set_nesting_level(Evtout::E1, 4);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x1100 + 4 * 3), 4);
+    assert_eq!(pruss.intc.nesting_level(Evtout::E1), 4);
+
+    pruss.intc.override_nesting_level(Evtout::E1);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x1100 + 4 * 3), 0x80000000);
+    assert_eq!(pruss.intc.nesting_level(Evtout::E1), 0);
+}
+
+
+#[test]
+fn intc_is_remapped() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+
+    let mut config = IntcConfig::new_empty();
+    config.map_sysevts_to_channels(&[(Sysevt::S3, Channel::C5)]);
+    config.map_channels_to_hosts(&[(Channel::C5, Host::Evtout3)]);
+    config.auto_enable_sysevts();
+    config.auto_enable_hosts();
+    pruss.intc.remap(&config);
+
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x380), 0xffffffff);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x384), 0xffffffff);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x280), 1 << 3);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x300), 1 << 3);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x400), 5 << 24);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x804), 5 << 8);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x34), 5);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x10), 1);
+}
+
+
+#[test]
+fn current_intc_config_is_read_back() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut config = IntcConfig::new_populated();
+    config.set_sysevt_types(&[(Sysevt::S20, SysevtType::Level)]);
+    let pruss = PrussBuilder::new().paths(fake.paths()).build(&config).unwrap();
+    // The fake memory does not emulate the host interrupt enable registers.
+    fake.write_prumem(0x20000 + 0x1500, &0b1111u32.to_ne_bytes());
+
+    assert_eq!(pruss.intc.current_config().compile(), config.compile());
+}
+
+
+#[test]
+fn conflicting_intc_config_is_rejected() {
+    let mut config = IntcConfig::new_populated();
+    let image = config.compile();
+
+    assert_eq!(config.try_map_sysevts_to_channels(&[(Sysevt::S3, Channel::C1),
+                                                    (Sysevt::S3, Channel::C2)]),
+               Err(ConfigError::DuplicateSysevt(3)));
+    assert_eq!(config.try_map_channels_to_hosts(&[(Channel::C4, Host::Pru0),
+                                                  (Channel::C4, Host::Pru1)]),
+               Err(ConfigError::DuplicateChannel(4)));
+    assert_eq!(config.try_enable_hosts(&[Host::Evtout2, Host::Evtout2]),
+               Err(ConfigError::DuplicateHost(4)));
+    assert_eq!(config.try_set_sysevt_types(&[(Sysevt::S9, SysevtType::Level),
+                                             (Sysevt::S9, SysevtType::Pulse)]),
+               Err(ConfigError::DuplicateSysevt(9)));
+    assert_eq!(config.compile(), image);
+
+    assert_eq!(config.try_enable_sysevts(&[Sysevt::S17, Sysevt::S18]), Ok(()));
+}
+
+
+#[test]
+fn intc_config_is_built_from_routes() {
+    let config = IntcConfig::builder()
+        .route(Sysevt::S19, Channel::C2, Host::Evtout0)
+        .route(Sysevt::S20, Channel::C3, Host::Evtout1)
+        .route(Sysevt::S21, Channel::C2, Host::Evtout0)
+        .sysevt_type(Sysevt::S21, SysevtType::Level)
+        .build()
+        .unwrap();
+
+    let mut expected = IntcConfig::new_empty();
+    expected.map_sysevts_to_channels(&[(Sysevt::S19, Channel::C2),
+                                       (Sysevt::S20, Channel::C3),
+                                       (Sysevt::S21, Channel::C2)]);
+    expected.map_channels_to_hosts(&[(Channel::C2, Host::Evtout0), (Channel::C3, Host::Evtout1)]);
+    expected.set_sysevt_types(&[(Sysevt::S21, SysevtType::Level)]);
+    expected.auto_enable_sysevts();
+    expected.auto_enable_hosts();
+    assert_eq!(config.compile(), expected.compile());
+
+    let err = IntcConfig::builder()
+        .route(Sysevt::S19, Channel::C2, Host::Evtout0)
+        .route(Sysevt::S20, Channel::C2, Host::Evtout1)
+        .build()
+        .err();
+    assert_eq!(err, Some(ConfigError::DuplicateChannel(2)));
+}
+
+
+#[test]
+fn numbers_are_converted_to_enums() {
+    assert_eq!(u8::from(Sysevt::try_from(63).unwrap()), 63);
+    assert_eq!(u8::from(Channel::try_from(9).unwrap()), 9);
+    assert_eq!(u8::from(Host::try_from(4).unwrap()), 4);
+    assert_eq!(u8::from(Evtout::try_from(7).unwrap()), 7);
+
+    let err = Sysevt::try_from(64).err().unwrap();
+    assert_eq!(err.value(), 64);
+    assert_eq!(err.to_string(), "invalid system event number 64, expected 0 to 63");
+    assert!(Channel::try_from(10).is_err());
+    assert!(Host::try_from(10).is_err());
+    assert_eq!(Evtout::try_from(8).err().unwrap().to_string(),
+               "invalid event out number 8, expected 0 to 7");
+}
+
+
+#[test]
+fn enums_can_be_compared_and_hashed() {
+    let mut routes = HashMap::new();
+    routes.insert(Sysevt::S19, (Channel::C2, Host::Evtout0));
+    routes.insert(Sysevt::S20, (Channel::C3, Host::Evtout1));
+    assert_eq!(routes[&Sysevt::S20], (Channel::C3, Host::Evtout1));
+
+    assert!(Sysevt::S3 < Sysevt::S40);
+    assert!(Evtout::E7 > Evtout::E0);
+    assert_eq!(format!("{:?}", Host::Evtout2), "Evtout2");
+}
+
+
+#[test]
+fn sysevts_of_evtout_are_resolved() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    assert_eq!(pruss.intc.sysevts_for(Evtout::E0), vec![Sysevt::S19]);
+    assert_eq!(pruss.intc.sysevts_for(Evtout::E1), vec![Sysevt::S20]);
+    assert_eq!(pruss.intc.sysevts_for(Evtout::E2), vec![]);
+
+    let config = IntcConfig::builder()
+        .route(Sysevt::S24, Channel::C2, Host::Evtout0)
+        .route(Sysevt::S19, Channel::C2, Host::Evtout0)
+        .route(Sysevt::S20, Channel::C4, Host::Evtout2)
+        .build()
+        .unwrap();
+    pruss.intc.remap(&config);
+    assert_eq!(pruss.intc.sysevts_for(Evtout::E0), vec![Sysevt::S19, Sysevt::S24]);
+    assert_eq!(pruss.intc.sysevts_for(Evtout::E2), vec![Sysevt::S20]);
+}
+
+
+#[test]
+fn prus_are_kicked() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    assert_eq!(pruss.intc.kick(PruCore::Pru0), Some(Sysevt::S21));
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x200), 1 << 21);
+    assert_eq!(pruss.intc.kick(PruCore::Pru1), Some(Sysevt::S22));
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x200), 1 << 22);
+
+    let config = IntcConfig::builder()
+        .route(Sysevt::S40, Channel::C1, Host::Pru1)
+        .route(Sysevt::S33, Channel::C1, Host::Pru1)
+        .build()
+        .unwrap();
+    pruss.intc.remap(&config);
+    assert_eq!(pruss.intc.kick(PruCore::Pru1), Some(Sysevt::S33));
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x204), 1 << 1);
+    assert_eq!(pruss.intc.kick(PruCore::Pru0), None);
+}
+
+
+#[test]
+fn intc_config_presets_are_routed() {
+    assert_eq!(IntcConfig::default().compile(), IntcConfig::new_populated().compile());
+
+    let expected = IntcConfig::builder()
+        .route(Sysevt::S19, Channel::C2, Host::Evtout0)
+        .route(Sysevt::S20, Channel::C3, Host::Evtout1)
+        .build()
+        .unwrap();
+    assert_eq!(IntcConfig::for_evtout_per_pru().compile(), expected.compile());
+
+    let mut expected = IntcConfig::new_empty();
+    expected.map_sysevts_to_channels(&[(Sysevt::S17, Channel::C1), (Sysevt::S18, Channel::C0)]);
+    expected.map_channels_to_hosts(&[(Channel::C1, Host::Pru1), (Channel::C0, Host::Pru0)]);
+    expected.auto_enable_sysevts();
+    expected.auto_enable_hosts();
+    assert_eq!(IntcConfig::pru_crosstalk_only().compile(), expected.compile());
+
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let config = IntcConfig::for_all_evtouts();
+    let _pruss = PrussBuilder::new().paths(fake.paths()).build(&config).unwrap();
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x410), 0x02000000);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x414), 0x06050403);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x418), 0x00090807);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x800), 0x03020000);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x804), 0x07060504);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x808), 0x00000908);
+}
+
+
+#[test]
+fn intc_config_is_read_from_device_tree() {
+    let fake = FakeUio::new();
+    let pru = |name, interrupts: &[u32]| {
+        DtNode {
+            name: name,
+            props: vec![("interrupt-parent", be_cells(&[0x10])),
+                        ("interrupts", be_cells(interrupts))],
+            children: Vec::new(),
+        }
+    };
+    let intc = DtNode {
+        name: "interrupt-controller@20000",
+        props: vec![("compatible", b"ti,pruss-intc\0".to_vec()),
+                    ("phandle", be_cells(&[0x10]))],
+        children: Vec::new(),
+    };
+    // The interrupt of the PRUSS node itself is routed to the host processor.
+    let pruss = DtNode {
+        name: "pruss@0",
+        props: vec![("interrupt-parent", be_cells(&[0x1])),
+                    ("interrupts", be_cells(&[0, 20, 4]))],
+        children: vec![intc,
+                       pru("pru@34000", &[16, 2, 2, 17, 3, 3]),
+                       pru("pru@38000", &[18, 2, 2])],
+    };
+    let root = DtNode {
+        name: "device-tree",
+        props: Vec::new(),
+        children: vec![pruss],
+    };
+    root.write_dir(fake.root());
+
+    let expected = IntcConfig::builder()
+        .route(Sysevt::S16, Channel::C2, Host::Evtout0)
+        .route(Sysevt::S17, Channel::C3, Host::Evtout1)
+        .route(Sysevt::S18, Channel::C2, Host::Evtout0)
+        .build()
+        .unwrap()
+        .compile();
+    let config = IntcConfig::from_device_tree(fake.root().join("device-tree")).unwrap();
+    assert_eq!(config.compile(), expected);
+    assert_eq!(IntcConfig::from_dtb(&root.dtb()).unwrap().compile(), expected);
+
+    let err = IntcConfig::from_dtb(&[0; 40]).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    // Property of the PRUSS node extending beyond the blob.
+    let mut dtb = root.dtb();
+    assert_eq!(dtb[68..72], [0, 0, 0, 3]);
+    dtb[72..76].copy_from_slice(&[0xff; 4]);
+    let err = IntcConfig::from_dtb(&dtb).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+
+#[test]
+fn doorbell_is_rung_and_acknowledged() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let doorbell = Doorbell::from_evtout(&pruss.intc, Sysevt::S21, Evtout::E2, Sysevt::S19)
+        .unwrap();
+
+    doorbell.ring();
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x200), 1 << 21);
+    assert!(!doorbell.wait_ack(Duration::from_millis(10)).unwrap());
+    assert_eq!(doorbell.status(), None);
+
+    fake.trigger(2, 1);
+    assert!(doorbell.wait_ack(Duration::from_secs(1)).unwrap());
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x24), 19);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x34), 4);
+
+    // Device errors are reported rather than mistaken for a missing reply.
+    fake.remove("dev/uio3");
+    fs::create_dir(fake.root().join("dev/uio3")).unwrap();
+    let doorbell = Doorbell::from_evtout(&pruss.intc, Sysevt::S21, Evtout::E3, Sysevt::S19)
+        .unwrap();
+    assert!(doorbell.wait_ack(Duration::from_millis(10)).is_err());
+}
+
+
+#[test]
+fn handshake_turn_is_taken_in_turns() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E2);
+    let flag = pruss.dram0.alloc(VolatileCell::new(1u32));
+    let mut handshake =
+        semaphore::Handshake::new(&pruss.intc, Sysevt::S21, &irq, Sysevt::S19, flag);
+    assert!(handshake.is_host_turn());
+    assert!(handshake.take(Duration::from_millis(0)));
+
+    assert!(handshake.post());
+    assert_eq!(fake.read_prumem_u32(0), semaphore::PRU_TURN);
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x200), 1 << 21);
+    assert!(!handshake.post());
+
+    // A notification does not hand the turn back unless the flag is reset.
+    fake.trigger(2, 1);
+    assert!(!handshake.take(Duration::from_millis(10)));
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x24), 19);
+
+    // Emulate the firmware handing the turn back.
+    fake.write_prumem(0, &semaphore::HOST_TURN.to_ne_bytes());
+    fake.trigger(2, 2);
+    assert!(handshake.take(Duration::from_secs(1)));
+}
+
+
+#[test]
+fn host_interrupts_are_globally_gated() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    assert!(pruss.intc.is_globally_enabled());
+
+    pruss.intc.global_disable();
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x10), 0);
+    assert!(!pruss.intc.is_globally_enabled());
+    pruss.intc.global_enable();
+    assert_eq!(fake.read_prumem_u32(0x20000 + 0x10), 1);
+}
+
+
+#[test]
+fn cleared_sysevts_are_counted() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    pruss.intc.clear_sysevt(Sysevt::S19);
+    assert_eq!(pruss.intc.event_counts(), None);
+
+    pruss.intc.enable_event_counts();
+    pruss.intc.clear_sysevt(Sysevt::S19);
+    pruss.intc.ack(Sysevt::S19, Evtout::E0);
+    pruss.intc.clear_sysevts(&[Sysevt::S20, Sysevt::S40]);
+    let counts = pruss.intc.event_counts().unwrap();
+    assert_eq!(counts.get(Sysevt::S19), 2);
+    assert_eq!(counts.total(), 4);
+    assert_eq!(counts.nonzero(), vec![(Sysevt::S19, 2), (Sysevt::S20, 1), (Sysevt::S40, 1)]);
+
+    pruss.intc.disable_event_counts();
+    assert_eq!(pruss.intc.event_counts(), None);
+}
+
+
+#[test]
+fn wait_is_resumed_after_signal() {
+    extern "C" fn ignore(_: libc::c_int) {}
+
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E2);
+    // Install a handler without SA_RESTART so that the wait is interrupted by the signal.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as usize;
+        assert_eq!(libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()), 0);
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let waiter = thread::spawn(move || {
+        tx.send(unsafe { libc::pthread_self() }).unwrap();
+        irq.try_wait_timeout(Duration::from_secs(5)).unwrap()
+    });
+    let thread = rx.recv().unwrap();
+    thread::sleep(Duration::from_millis(50));
+    unsafe {
+        libc::pthread_kill(thread, libc::SIGUSR1);
+    }
+    thread::sleep(Duration::from_millis(50));
+    fake.trigger(2, 1);
+    assert_eq!(waiter.join().unwrap(), Some(1));
+}
+
+
+#[test]
+fn event_loop_acks_before_dispatching() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    fake.trigger(1, 2);
+    fake.trigger(2, 1);
+
+    let events = RefCell::new(Vec::new());
+    let timeouts = Cell::new(0);
+    let mut event_loop = EventLoop::new(&pruss.intc);
+    event_loop.on_evtout(Evtout::E1, Sysevt::S20, |_, n| {
+            // The system event is cleared and the host interrupt re-enabled before dispatching.
+            events.borrow_mut().push((1, n, fake.read_prumem_u32(0x20000 + 0x24)));
+            assert_eq!(fake.read_prumem_u32(0x20000 + 0x34), 3);
+        })
+        .unwrap();
+    event_loop.on_evtout(Evtout::E2, Sysevt::S21, |_, n| {
+            events.borrow_mut().push((2, n, fake.read_prumem_u32(0x20000 + 0x24)));
+        })
+        .unwrap();
+    let err = event_loop.on_evtout(Evtout::E2, Sysevt::S21, |_, _| {}).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    event_loop.set_timeout(Duration::from_millis(10), |_| timeouts.set(timeouts.get() + 1));
+    event_loop.shutdown_when(|| timeouts.get() == 2);
+    event_loop.run().unwrap();
+
+    assert_eq!(*events.borrow(), [(1, 1, 20), (2, 1, 21)]);
+    assert_eq!(timeouts.get(), 2);
+}
+
+
+#[test]
+fn event_loop_reports_device_errors() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    fake.remove("dev/uio2");
+    fs::create_dir(fake.root().join("dev/uio2")).unwrap();
+
+    let mut event_loop = EventLoop::new(&pruss.intc);
+    event_loop.on_evtout(Evtout::E2, Sysevt::S21, |_, _| panic!("unexpected event out")).unwrap();
+    match event_loop.run() {
+        Err(WaitError::Io(err)) => assert_eq!(err.raw_os_error(), Some(libc::EISDIR)),
+        other => panic!("unexpected event loop result: {:?}", other),
+    }
+}
+
+
+#[test]
+fn large_objects_are_initialized_in_place() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+
+    let buffer = unsafe {
+        pruss.dram0.alloc_with(|buffer: &mut MaybeUninit<[u32; 1024]>| {
+            let words = buffer.as_mut_ptr() as *mut u32;
+            for i in 0..1024 {
+                *words.add(i) = i as u32;
+            }
+        })
+    };
+    assert_eq!(buffer[1023], 1023);
+    assert_eq!(fake.read_prumem_u32(0), 0);
+    assert_eq!(fake.read_prumem_u32(4 * 1000), 1000);
+}
+
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn pod_objects_can_be_allocated() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    fake.write_prumem(0, &[0xff; 64]);
+    let mut pruss = build(&fake).unwrap();
+    assert_eq!(fake.read_prumem_u32(16), 0xffffffff);
+
+    {
+        let (mut head, mut tail) = pruss.dram0.split_at(PruAddr(16));
+        *head.alloc_pod([1u32, 2, 3, 4]) = [5, 6, 7, 8];
+        assert_eq!(*tail.alloc_zeroed::<[u64; 6]>(), [0; 6]);
+    }
+    assert_eq!(fake.read_prumem_u32(12), 8);
+    assert_eq!(fake.read_prumem(16, 48), vec![0; 48]);
+}
+
+
+#[cfg(feature = "zerocopy")]
+#[test]
+fn firmware_objects_can_be_viewed() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    fake.write_prumem(0x40, &0xdeadbeefu32.to_ne_bytes());
+    let mut pruss = build(&fake).unwrap();
+
+    assert_eq!(*pruss.dram0.view_at::<u32>(PruAddr(0x40)), 0xdeadbeef);
+    *pruss.dram0.view_at::<[u16; 2]>(PruAddr(0x44)) = [1, 2];
+    pruss.dram0.view_slice_at::<u8>(PruAddr(0x49), 3).copy_from_slice(&[3, 4, 5]);
+    assert_eq!(fake.read_prumem(0x44, 8), vec![1, 0, 2, 0, 0, 3, 4, 5]);
+}
+
+
+#[test]
+fn bytes_are_copied_at_arbitrary_positions() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    fake.write_prumem(0x2000, &[9, 8, 7, 6, 5, 4, 3, 2, 1]);
+    let mut pruss = build(&fake).unwrap();
+
+    let table: Vec<u8> = (0..11).collect();
+    pruss.dram0.write_at(PruAddr(0x103), &table);
+    assert_eq!(fake.read_prumem(0x103, 11), table);
+
+    let mut snapshot = [0u8; 7];
+    pruss.dram1.read_at(PruAddr(0x2001), &mut snapshot);
+    assert_eq!(snapshot, [8, 7, 6, 5, 4, 3, 2]);
+
+    // The end of the segment can be reached but not crossed.
+    let end = pruss.dram0.end();
+    pruss.dram0.write_at(PruAddr(end.0 - 2), &[0xaa, 0xbb]);
+    pruss.dram0.read_at(end, &mut []);
+    assert_eq!(fake.read_prumem(end.0 - 2, 2), vec![0xaa, 0xbb]);
+}
+
+
+#[test]
+fn segments_can_be_filled() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    fake.write_prumem(0, &[0x55; 0x20]);
+    let mut pruss = build(&fake).unwrap();
+
+    {
+        let (_, mut tail) = pruss.dram0.split_at(PruAddr(0x3));
+        let (mut middle, _) = tail.split_at(PruAddr(0x1d));
+        middle.fill(0xa5);
+        assert_eq!(fake.read_prumem(0, 4), vec![0x55, 0x55, 0x55, 0xa5]);
+        assert_eq!(fake.read_prumem(0x1a, 6), vec![0xa5, 0xa5, 0xa5, 0x55, 0x55, 0x55]);
+        middle.zero();
+        assert_eq!(fake.read_prumem(0x3, 0x1a), vec![0; 0x1a]);
+    }
+    pruss.dram0.zero();
+    assert_eq!(fake.read_prumem(0, 0x2000), vec![0; 0x2000]);
+}
+
+
+#[test]
+fn transaction_times_out_without_reply() {
+    let _serial = common::serialize();
+    let mut fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+    let irq = pruss.intc.register_irq(Evtout::E2);
+
+    let err = pruss.intc.transaction(Sysevt::S21, &irq, Duration::from_millis(10)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+    fake.trigger(2, 1);
+    assert_eq!(pruss.intc.transaction(Sysevt::S21, &irq, Duration::from_secs(1)).unwrap(), 1);
+}
+
+
+#[test]
+fn host_version_is_announced_to_the_firmware() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let device = fake.root().join("dev/uio0");
+    // Emulate a firmware which waits for the host header and publishes its own header.
+    let firmware = thread::spawn(move || {
+        let mut device = fs::OpenOptions::new().read(true).write(true).open(device).unwrap();
+        let mut host = [0u8; 8];
+        for _ in 0..1000 {
+            device.seek(SeekFrom::Start(12)).unwrap();
+            device.read_exact(&mut host).unwrap();
+            if host[..4] == handshake::HOST_MAGIC.to_ne_bytes() {
+                device.seek(SeekFrom::Start(4)).unwrap();
+                device.write_all(&host[4..]).unwrap();
+                device.write_all(&1u32.to_ne_bytes()).unwrap();
+                device.seek(SeekFrom::Start(0)).unwrap();
+                device.write_all(&handshake::FIRMWARE_MAGIC.to_ne_bytes()).unwrap();
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+    let mut code = pruss.pru0.load_code_from_slice(&[0; 4]).unwrap();
+    let expected = HandshakeConfig {
+        version: 3,
+        capabilities: 1,
+        timeout: Duration::from_secs(2),
+    };
+
+    let header = unsafe { code.handshake(&mut pruss.dram0, &expected) }.unwrap();
+    firmware.join().unwrap();
+    assert_eq!((header.version, header.capabilities), (3, 1));
+    assert_eq!(fake.read_prumem_u32(12), handshake::HOST_MAGIC);
+    assert_eq!(fake.read_prumem_u32(16), 3);
+
+    let timeout = HandshakeConfig { timeout: Duration::from_millis(10), ..expected };
+    let err = unsafe { code.handshake(&mut pruss.dram0, &timeout) }.unwrap_err();
+    assert_eq!(err, HandshakeError::Timeout);
+}
+
+
+#[test]
+fn rpc_frames_are_encoded_and_decoded() {
+    let frame = Frame {
+        sequence: 0x1234,
+        opcode: 7,
+        status: 0,
+        payload: vec![1, 2, 3, 4, 5],
+    };
+    let mut bytes = frame.encode();
+    assert_eq!(bytes.len(), frame.encoded_len());
+    assert_eq!(bytes[..8], [0x34, 0x12, 7, 0, 5, 0, 0, 0]);
+    assert_eq!(Frame::decode(&bytes).unwrap(), frame);
+    // Bytes following the CRC are ignored.
+    bytes.extend_from_slice(&[0xff; 3]);
+    assert_eq!(Frame::decode(&bytes).unwrap(), frame);
+    bytes.truncate(frame.encoded_len());
+
+    for bit in 0..8 * bytes.len() {
+        let mut corrupted = bytes.clone();
+        corrupted[bit / 8] ^= 1 << (bit % 8);
+        assert_eq!(Frame::decode(&corrupted), Err(RpcError::Corrupted), "bit {}", bit);
+    }
+    for len in 0..bytes.len() {
+        assert_eq!(Frame::decode(&bytes[..len]), Err(RpcError::Corrupted), "length {}", len);
+    }
+}
+
+
+#[test]
+fn rpc_calls_discard_stale_responses() {
+    // Offsets of the flags and slots of a channel spanning the whole 8kB DRAM0.
+    const REQUEST_FLAG: u64 = 0;
+    const RESPONSE_FLAG: u64 = 4;
+    const REQUEST_SLOT: u64 = 8;
+    const RESPONSE_SLOT: u64 = 8 + 4092;
+
+    fn read_at(device: &mut fs::File, offset: u64, len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        device.seek(SeekFrom::Start(offset)).unwrap();
+        device.read_exact(&mut data).unwrap();
+        data
+    }
+    fn write_at(device: &mut fs::File, offset: u64, data: &[u8]) {
+        device.seek(SeekFrom::Start(offset)).unwrap();
+        device.write_all(data).unwrap();
+    }
+    fn flag(device: &mut fs::File, offset: u64) -> u32 {
+        let data = read_at(device, offset, 4);
+        u32::from_ne_bytes([data[0], data[1], data[2], data[3]])
+    }
+    // Waits for a request with the specified sequence number and consumes it.
+    fn receive(device: &mut fs::File, sequence: u16) -> Frame {
+        for _ in 0..1000 {
+            if flag(device, REQUEST_FLAG) == 1 {
+                let bytes = read_at(device, REQUEST_SLOT, 64);
+                if let Ok(request) = Frame::decode(&bytes) {
+                    if request.sequence == sequence {
+                        write_at(device, REQUEST_FLAG, &0u32.to_ne_bytes());
+                        return request;
+                    }
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("no request received");
+    }
+    // Posts a response once the previous one has been consumed.
+    fn respond(device: &mut fs::File, bytes: &[u8]) {
+        for _ in 0..1000 {
+            if flag(device, RESPONSE_FLAG) == 0 {
+                write_at(device, RESPONSE_SLOT, bytes);
+                write_at(device, RESPONSE_FLAG, &1u32.to_ne_bytes());
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("response not consumed");
+    }
+
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let mut channel = RpcChannel::new(&mut pruss.dram0);
+    assert_eq!(channel.max_payload(), 4092 - 12);
+    // The first request is never answered.
+    assert_eq!(channel.call(1, b"ping", Duration::from_millis(10)), Err(RpcError::Timeout));
+    assert_eq!(channel.call(1, &[0; 4081], Duration::from_millis(10)),
+               Err(RpcError::PayloadTooLarge));
+
+    let device = fake.root().join("dev/uio0");
+    // Emulate a firmware which posts a late response to the first request before answering the
+    // second request, and corrupts the CRC of its response to the third request.
+    let firmware = thread::spawn(move || {
+        let mut device = fs::OpenOptions::new().read(true).write(true).open(device).unwrap();
+        let mut request = receive(&mut device, 2);
+        request.sequence = 1;
+        request.payload = b"stale".to_vec();
+        respond(&mut device, &request.encode());
+        request.sequence = 2;
+        request.payload.reverse();
+        respond(&mut device, &request.encode());
+
+        let mut response = receive(&mut device, 3).encode();
+        let last = response.len() - 1;
+        response[last] ^= 0x80;
+        respond(&mut device, &response);
+    });
+
+    assert_eq!(channel.call(1, b"ping", Duration::from_secs(1)), Ok(b"elats".to_vec()));
+    assert_eq!(channel.call(2, b"pong", Duration::from_secs(1)), Err(RpcError::Corrupted));
+    firmware.join().unwrap();
+}
+
+
+#[test]
+fn stream_reader_wraps_around() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    // A ring with a 16-byte data area, emulating the firmware by writing the fake memory.
+    let (mut segment, _) = pruss.dram0.split_at(PruAddr(RING_HEADER_SIZE + 16));
+    let mut reader = StreamReader::new(&mut segment);
+    assert_eq!(reader.capacity(), 16);
+    let set_write = |write: u32| fake.write_prumem(0, &write.to_ne_bytes());
+    let data: Vec<u8> = (1..23).collect();
+
+    fake.write_prumem(RING_HEADER_SIZE, &data[..10]);
+    set_write(10);
+    let mut buf = [0u8; 16];
+    assert_eq!(reader.read_available(&mut buf), 10);
+    assert_eq!(buf[..10], data[..10]);
+    assert_eq!(fake.read_prumem_u32(4), 10);
+
+    // 12 more bytes wrap around the end of the data area.
+    fake.write_prumem(RING_HEADER_SIZE + 10, &data[10..16]);
+    fake.write_prumem(RING_HEADER_SIZE, &data[16..]);
+    set_write(6);
+    assert_eq!(reader.available(), 12);
+    assert_eq!(reader.read_available(&mut buf[..5]), 5);
+    assert_eq!(buf[..5], data[10..15]);
+    assert_eq!(reader.read_available(&mut buf), 7);
+    assert_eq!(buf[..7], data[15..]);
+    assert_eq!(fake.read_prumem_u32(4), 6);
+    assert_eq!(reader.high_watermark(), 12);
+
+    // A corrupted write index is treated as an empty ring and leaves the read index untouched.
+    set_write(16);
+    assert_eq!(reader.available(), 0);
+    assert_eq!(reader.read_available(&mut buf), 0);
+    assert_eq!(fake.read_prumem_u32(4), 6);
+    reader.skip_available();
+    assert_eq!(fake.read_prumem_u32(4), 6);
+}
+
+
+#[test]
+fn data_logger_stalls_when_both_buffers_are_full() {
+    // Writer blocking on each write until it is released.
+    struct GatedWriter {
+        gate: mpsc::Receiver<()>,
+        data: Vec<u8>,
+    }
+
+    impl Write for GatedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _ = self.gate.recv();
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let (mut segment, _) = pruss.dram0.split_at(PruAddr(RING_HEADER_SIZE + 16));
+    let mut reader = StreamReader::new(&mut segment);
+    let (gate, gate_rx) = mpsc::channel();
+    let writer = GatedWriter {
+        gate: gate_rx,
+        data: Vec::new(),
+    };
+    let mut logger = DataLogger::new(writer, 4);
+    let data: Vec<u8> = (1..16).collect();
+    fake.write_prumem(RING_HEADER_SIZE, &data);
+    fake.write_prumem(0, &15u32.to_ne_bytes());
+
+    // The first buffer is held by the blocked writer and the second one is filled, so draining
+    // stalls with 7 bytes left in the ring.
+    assert_eq!(logger.poll(&mut reader).unwrap(), 8);
+    let stats = logger.stats();
+    assert_eq!((stats.bytes_logged, stats.buffers_submitted, stats.stalls), (8, 1, 1));
+    assert_eq!(stats.high_watermark, 15);
+    assert_eq!(reader.available(), 7);
+    assert_eq!(logger.poll(&mut reader).unwrap(), 0);
+    assert_eq!(logger.stats().stalls, 2);
+
+    drop(gate);
+    while reader.available() != 0 {
+        logger.poll(&mut reader).unwrap();
+        thread::sleep(Duration::from_millis(1));
+    }
+    let (writer, stats) = logger.finish().unwrap();
+    assert_eq!(writer.data, data);
+    assert_eq!(stats.bytes_logged, 15);
+    assert!(stats.stalls >= 2);
+}
+
+
+
+#[test]
+fn phase_lock_tracks_edges_across_counter_wrap_arounds() {
+    let calibration = Calibration {
+        reference: 0,
+        reference_time: Duration::from_secs(1000),
+        rate: 200e6,
+        drift_ppm: 0.0,
+        residual: 0.0,
+    };
+    let period = 200_000_000u32;
+    let mut lock = PhaseLock::new(calibration, period, 1.0, 0.0);
+
+    // Edges locked to the host clock, spanning more than one wrap-around of the counter.
+    for count in 1..50u32 {
+        let edge = Edge {
+            count: count,
+            ticks: count.wrapping_mul(period),
+        };
+        assert!(lock.phase_error(&edge).abs() < 0.5);
+        let correction = lock.correct(&edge);
+        assert_eq!(correction.phase, 0);
+        assert_eq!(correction.period, Some(period));
+    }
+}
+
+
+#[test]
+fn stalled_counters_are_not_calibrated() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let pruss = build(&fake).unwrap();
+
+    let calibration =
+        timesync::calibrate(&pruss, Counter::Iep, 200e6, 3, Duration::from_millis(1));
+
+    assert!(calibration.is_none());
+}
+
+
+#[test]
+fn spin_lock_is_contended_with_the_pru() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let lock = pruss.dram0.alloc(SpinLock::new());
+
+    // The PRU holds the lock, having given the priority to the host.
+    fake.write_prumem(4, &1u32.to_ne_bytes());
+    assert!(lock.try_lock_for(Duration::from_millis(10)).is_none());
+    assert_eq!(fake.read_prumem_u32(0), 0);
+    assert_eq!(fake.read_prumem_u32(8), 1);
+
+    let device = fake.root().join("dev/uio0");
+    // Emulate a firmware which releases the lock once the host is waiting for it.
+    let firmware = thread::spawn(move || {
+        let mut device = fs::OpenOptions::new().read(true).write(true).open(device).unwrap();
+        let mut host = [0u8; 4];
+        for _ in 0..1000 {
+            device.seek(SeekFrom::Start(0)).unwrap();
+            device.read_exact(&mut host).unwrap();
+            if host == 1u32.to_ne_bytes() {
+                device.seek(SeekFrom::Start(4)).unwrap();
+                device.write_all(&0u32.to_ne_bytes()).unwrap();
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("lock not requested");
+    });
+
+    {
+        let guard = lock.try_lock_for(Duration::from_secs(1));
+        assert!(guard.is_some());
+        assert_eq!(fake.read_prumem_u32(0), 1);
+    }
+    assert_eq!(fake.read_prumem_u32(0), 0);
+    firmware.join().unwrap();
+}
+
+
+#[test]
+fn data_ram_is_split_after_the_firmware_sections() {
+    let _serial = common::serialize();
+    let fake = FakeUio::new();
+    let mut pruss = build(&fake).unwrap();
+    let map = LinkerMap::parse("\
+MEMORY CONFIGURATION
+PAGE 1:
+  PRU_DMEM_0_1          00000000   00002000  00000118  00001ee8  RWIX
+
+SECTION ALLOCATION MAP
+.stack     1    00000000    00000100     UNINITIALIZED
+.bss       1    00000100    00000018     UNINITIALIZED
+.cio       1    00000118    00000000     UNINITIALIZED
+")
+        .unwrap();
+
+    {
+        // The firmware was linked for PRU1, so its sections lie within the PRU1 data RAM.
+        let (reserved, free) = map.split_reserved(PruCore::Pru1, &mut pruss.dram1);
+        assert_eq!((reserved.begin(), reserved.end()), (PruAddr(0x2000), PruAddr(0x2118)));
+        assert_eq!((free.begin(), free.end()), (PruAddr(0x2118), PruAddr(0x4000)));
+    }
+    let (reserved, free) = map.split_reserved(PruCore::Pru1, &mut pruss.dram0);
+    assert_eq!((reserved.begin(), reserved.end()), (PruAddr(0), PruAddr(0)));
+    assert_eq!((free.begin(), free.end()), (PruAddr(0), PruAddr(0x2000)));
+}
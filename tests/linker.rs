@@ -0,0 +1,218 @@
+//! Parsing of clpru linker command and map files.
+
+extern crate prusst;
+
+use prusst::{PruAddr, PruCore};
+use prusst::linker::{LinkerCommand, LinkerMap, MemoryRange, Placement, Section, Symbol};
+
+use std::io;
+
+
+static COMMAND_FILE: &'static str = "\
+/****************************************************************************/
+/*  AM335x_PRU.cmd                                                          */
+/****************************************************************************/
+
+-cr                             /* Link using C conventions */
+
+/* Specify the System Memory Map */
+MEMORY
+{
+      PAGE 0:
+        PRU_IMEM        : org = 0x00000000 len = 0x00002000  /* 8kB PRU0 Instruction RAM */
+
+      PAGE 1:
+
+        /* RAM */
+
+        PRU_DMEM_0_1    : org = 0x00000000 len = 0x00002000 CREGISTER=24 /* 8kB PRU Data RAM 0_1 */
+        PRU_DMEM_1_0    : org = 0x00002000 len = 0x00002000 CREGISTER=25 /* 8kB PRU Data RAM 1_0 */
+        /* PRU_UNUSED   : org = 0x00004000 len = 0x00001000
+           (disabled) */
+
+      PAGE 2: PRU_SHAREDMEM   : org = 0x00010000 len = 0x00003000 CREGISTER=28 // 12kB Shared RAM
+}
+
+/* Specify the sections allocation into memory */
+SECTIONS {
+    /* Forces _c_int00 to the start of PRU IRAM. Not necessary when loading
+       an ELF file, but useful when loading a binary */
+    .text:_c_int00*     >  0x0, PAGE 0
+
+    .text               >  PRU_IMEM, PAGE 0
+    .stack              >  PRU_DMEM_0_1, PAGE 1
+    .bss                >  PRU_DMEM_0_1, PAGE 1
+    // .fardata         >  PRU_DMEM_1_0, PAGE 1
+    .data               >  PRU_DMEM_0_1, PAGE 1
+    .resource_table     >  PRU_DMEM_0_1, PAGE 1
+}
+";
+
+
+static MAP_FILE: &'static str = "\
+******************************************************************************
+                     PRU Linker Unix v2.3.3
+******************************************************************************
+>> Linked Mon Jan  8 10:12:44 2024
+
+OUTPUT FILE NAME:   <app.out>
+ENTRY POINT SYMBOL: \"_c_int00_noinit_noargs\"  address: 00000000
+
+
+MEMORY CONFIGURATION
+
+         name            origin    length      used     unused   attr    fill
+----------------------  --------  ---------  --------  --------  ----  --------
+PAGE 0:
+  PRU_IMEM              00000000   00002000  00000134  00001ecc  RWIX
+
+PAGE 1:
+  PRU_DMEM_0_1          00000000   00002000  00000118  00001ee8  RWIX
+  PRU_DMEM_1_0          00002000   00002000  00000000  00002000  RWIX
+
+PAGE 2:
+  PRU_SHAREDMEM         00010000   00003000  00000000  00003000  RWIX
+
+
+SECTION ALLOCATION MAP
+
+ output                                  attributes/
+section   page    origin      length       input sections
+--------  ----  ----------  ----------   ----------------
+.text:_c_int00*
+*          0    00000000    00000014
+                  00000000    00000014     boot_special.obj (.text:_c_int00_noinit_noargs)
+
+.text      0    00000014    00000120
+                  00000014    000000e0     main.obj (.text:main)
+                  000000f4    00000040     rtspruv3_le.lib : exit.obj (.text:abort)
+
+.stack     1    00000000    00000100     UNINITIALIZED
+                  00000000    00000004     rtspruv3_le.lib : boot.obj (.stack)
+
+.bss       1    00000100    00000008     UNINITIALIZED
+                  00000100    00000008     main.obj (.bss)
+
+.resource_table
+*          1    00000108    00000010
+                  00000108    00000010     main.obj (.resource_table:retain)
+
+.cio       1    00000118    00000000     UNINITIALIZED
+
+
+GLOBAL SYMBOLS: SORTED ALPHABETICALLY BY Name
+
+page  address   name
+----  -------   ----
+0     00000000  _c_int00_noinit_noargs
+abs   00000100  __TI_STACK_SIZE
+1     00000100  counter
+1     00000108  pru_remoteproc_ResourceTable
+
+[4 symbols]
+
+GLOBAL SYMBOLS: SORTED BY Symbol Address
+
+page  address   name
+----  -------   ----
+0     00000000  _c_int00_noinit_noargs
+abs   00000100  __TI_STACK_SIZE
+1     00000100  counter
+1     00000108  pru_remoteproc_ResourceTable
+
+[4 symbols]
+";
+
+
+fn range(name: &str, page: u32, origin: u32, length: u32) -> MemoryRange {
+    MemoryRange {
+        name: name.to_string(),
+        page: page,
+        origin: origin,
+        length: length,
+    }
+}
+
+
+#[test]
+fn linker_command_file_is_parsed() {
+    let command = LinkerCommand::parse(COMMAND_FILE).unwrap();
+
+    assert_eq!(command.memory,
+               [range("PRU_IMEM", 0, 0, 0x2000),
+                range("PRU_DMEM_0_1", 1, 0, 0x2000),
+                range("PRU_DMEM_1_0", 1, 0x2000, 0x2000),
+                range("PRU_SHAREDMEM", 2, 0x10000, 0x3000)]);
+    let placements: Vec<(&str, &str, Option<u32>)> = command.sections
+        .iter()
+        .map(|p| (p.section.as_str(), p.target.as_str(), p.page))
+        .collect();
+    assert_eq!(placements,
+               [(".text:_c_int00*", "0x0", Some(0)),
+                (".text", "PRU_IMEM", Some(0)),
+                (".stack", "PRU_DMEM_0_1", Some(1)),
+                (".bss", "PRU_DMEM_0_1", Some(1)),
+                (".data", "PRU_DMEM_0_1", Some(1)),
+                (".resource_table", "PRU_DMEM_0_1", Some(1))]);
+    assert_eq!(command.sections[2],
+               Placement {
+                   section: ".stack".to_string(),
+                   target: "PRU_DMEM_0_1".to_string(),
+                   page: Some(1),
+               });
+    assert_eq!(command.memory_range("PRU_DMEM_1_0").unwrap().origin, 0x2000);
+    assert!(command.memory_range("PRU_UNUSED").is_none());
+
+    let err = LinkerCommand::parse("SECTIONS { .text > PRU_IMEM }").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+
+#[test]
+fn linker_map_file_is_parsed() {
+    let map = LinkerMap::parse(MAP_FILE).unwrap();
+
+    assert_eq!(map.memory,
+               [range("PRU_IMEM", 0, 0, 0x2000),
+                range("PRU_DMEM_0_1", 1, 0, 0x2000),
+                range("PRU_DMEM_1_0", 1, 0x2000, 0x2000),
+                range("PRU_SHAREDMEM", 2, 0x10000, 0x3000)]);
+    let sections: Vec<(&str, u32, u32, u32)> = map.sections
+        .iter()
+        .map(|s| (s.name.as_str(), s.page, s.origin, s.length))
+        .collect();
+    // Long section names are wrapped onto a `*` line.
+    assert_eq!(sections,
+               [(".text:_c_int00*", 0, 0, 0x14),
+                (".text", 0, 0x14, 0x120),
+                (".stack", 1, 0, 0x100),
+                (".bss", 1, 0x100, 8),
+                (".resource_table", 1, 0x108, 0x10),
+                (".cio", 1, 0x118, 0)]);
+    assert_eq!(map.section(".bss"),
+               Some(&Section {
+                   name: ".bss".to_string(),
+                   page: 1,
+                   origin: 0x100,
+                   length: 8,
+               }));
+    // Symbols listed in both orders are reported once; absolute symbols are skipped.
+    assert_eq!(map.symbols.len(), 3);
+    assert_eq!(map.symbol("counter"),
+               Some(&Symbol {
+                   name: "counter".to_string(),
+                   page: Some(1),
+                   address: 0x100,
+               }));
+    assert!(map.symbol("__TI_STACK_SIZE").is_none());
+    assert_eq!(map.symbol_addr(PruCore::Pru1, "counter"), Some(PruAddr(0x2100)));
+
+    // Empty sections and program memory are not reserved.
+    assert_eq!(map.reserved_ranges(PruCore::Pru0),
+               [(PruAddr(0), PruAddr(0x100)),
+                (PruAddr(0x100), PruAddr(0x108)),
+                (PruAddr(0x108), PruAddr(0x118))]);
+
+    let err = LinkerMap::parse("SECTION ALLOCATION MAP\n").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
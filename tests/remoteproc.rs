@@ -0,0 +1,80 @@
+//! Parsing of remoteproc resource tables.
+
+extern crate prusst;
+
+use prusst::remoteproc::{Resource, ResourceTable, Vring};
+
+use std::io;
+
+
+fn push_u32(table: &mut Vec<u8>, values: &[u32]) {
+    for value in values {
+        table.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+
+fn push_name(table: &mut Vec<u8>, name: &str) {
+    let mut bytes = [0u8; 32];
+    bytes[..name.len()].copy_from_slice(name.as_bytes());
+    table.extend_from_slice(&bytes);
+}
+
+
+#[test]
+fn resource_table_is_parsed() {
+    let mut table = Vec::new();
+    push_u32(&mut table, &[1, 3, 0, 0, 28, 84, 156]);
+    // Carveout.
+    push_u32(&mut table, &[0, 0x2000, 0, 0x1000, 0, 0]);
+    push_name(&mut table, "buffer");
+    // Virtio device with two vrings and a 4-byte configuration space.
+    push_u32(&mut table, &[3, 7, 0, 1, 0, 4]);
+    table.extend_from_slice(&[0, 2, 0, 0]);
+    push_u32(&mut table, &[0xffffffff, 16, 16, 1, 0, 0xffffffff, 16, 16, 2, 0]);
+    table.extend_from_slice(&[9, 8, 7, 6]);
+    // Custom interrupt mapping.
+    push_u32(&mut table, &[5, 0, 4]);
+    table.extend_from_slice(&[1, 2, 3, 4]);
+
+    let table = ResourceTable::parse(&table).unwrap();
+
+    assert_eq!(table.resources.len(), 3);
+    let carveouts = table.carveouts();
+    assert_eq!(carveouts.len(), 1);
+    assert_eq!((carveouts[0].da, carveouts[0].len), (0x2000, 0x1000));
+    assert_eq!(carveouts[0].name, "buffer");
+    let vdevs = table.vdevs();
+    assert_eq!(vdevs[0].id, 7);
+    assert_eq!(vdevs[0].vrings[1],
+               Vring {
+                   da: 0xffffffff,
+                   align: 16,
+                   num: 16,
+                   notify_id: 2,
+                   pa: 0,
+               });
+    assert_eq!(vdevs[0].config, [9, 8, 7, 6]);
+    assert_eq!(table.resources[2],
+               Resource::Custom {
+                   sub_type: 0,
+                   data: vec![1, 2, 3, 4],
+               });
+}
+
+
+#[test]
+fn truncated_resource_table_is_rejected() {
+    let mut table = Vec::new();
+    push_u32(&mut table, &[1, 1, 0, 0, 20]);
+    push_u32(&mut table, &[0, 0x2000, 0, 0x1000]);
+
+    assert_eq!(ResourceTable::parse(&table).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // Custom entry whose size exceeds the table.
+    let mut table = Vec::new();
+    push_u32(&mut table, &[1, 1, 0, 0, 20]);
+    push_u32(&mut table, &[5, 0, 0xffffffff, 0]);
+
+    assert_eq!(ResourceTable::parse(&table).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}